@@ -0,0 +1,494 @@
+use crate::configuration::*;
+use crate::evaluation::*;
+use crate::recorder::*;
+use crate::selfplay::*;
+use crate::BOARDSIZE;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+// One set of games in which one side's army was changed, and how that side did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trial {
+    pub label: String,
+    // One per opening, the change made to White. Black gets the mirror image of it every other game.
+    pub fens: Vec<String>,
+    // What the change is worth to the changed side by the values we already trust, None for the piece being measured.
+    pub difference: Option<i32>,
+    pub games: usize,
+    pub points: f64,
+}
+
+impl Trial {
+    // Kept off 0 and 1, where the Elo curve goes to infinity.
+    pub fn score(&self) -> f64 {
+        let games: f64 = self.games.max(1) as f64;
+        (self.points / games).clamp(0.5 / games, 1.0 - 0.5 / games)
+    }
+}
+
+// What the games say a piece is worth.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub piece: String,
+    pub replaced: String,
+    pub replaced_value: i32,
+    pub trials: Vec<Trial>,
+    // Centipawns per unit of log10(score / (1 - score)), from the handicap games.
+    pub scale: Option<f64>,
+    pub value: Option<i32>,
+    // Half the width of a 95% confidence interval.
+    pub error: Option<f64>,
+}
+
+impl Estimate {
+    pub fn report(&self) -> String {
+        let mut output: String =
+            format!("{} (playing instead of a {})\n", self.piece, self.replaced);
+        output.push_str("  Trial                        Material  Games   Score\n");
+        for trial in &self.trials {
+            output.push_str(&format!(
+                "  {:<28} {:>8} {:>6} {:>6.1}%\n",
+                trial.label,
+                trial
+                    .difference
+                    .map_or(String::from("?"), |d| d.to_string()),
+                trial.games,
+                trial.points * 100.0 / trial.games.max(1) as f64
+            ));
+        }
+        match (self.value, self.error) {
+            (Some(value), Some(error)) => output.push_str(&format!(
+                "  Estimated value {} +/- {:.0} (a {} is {})\n",
+                value, error, self.replaced, self.replaced_value
+            )),
+            _ => output.push_str(
+                "  No estimate, the handicap games didn't go worse for the side that was down material\n",
+            ),
+        }
+        output
+    }
+}
+
+// Self-play experiments that put a number on a piece. One side has a standard piece swapped for the
+// new one, and to tell what that score means, the same engines also play handicap games where one
+// side is a known amount of material down. A logistic curve through those gives how the score falls
+// with material, and reading the swapped games' score off it gives the new piece's worth.
+pub struct Balance {
+    pub engine: String,
+    pub evaluator: String,
+    // Names from startingPositions.json, taken in turn.
+    pub openings: Vec<String>,
+    // For each trial, in pairs so the change is on each side equally often.
+    pub games: u64,
+    pub threads: usize,
+    // Games that go on longer are called drawn.
+    pub max_plies: usize,
+    // Random moves at the start of every game, so a deterministic engine doesn't play the same game every time.
+    pub random_plies: usize,
+    // See catch_interrupts.
+    pub stop: Arc<AtomicBool>,
+    pub abandon: Arc<AtomicBool>,
+}
+
+impl Balance {
+    pub fn new(openings: Vec<String>) -> Balance {
+        Balance {
+            engine: String::from("engine:depth=2"),
+            evaluator: String::from("material"),
+            openings,
+            games: 20,
+            threads: 1,
+            max_plies: 300,
+            random_plies: RANDOM_PLIES,
+            stop: Arc::new(AtomicBool::new(false)),
+            abandon: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // The piece swapped out by default: the one in the openings whose value is closest to the new piece's
+    // current guess. Pawns and royal pieces are left alone.
+    pub fn replaceable<'a>(
+        &self,
+        piece: &PieceType,
+        piece_list: &'a PieceList,
+        position_name_map: &HashMap<String, String>,
+    ) -> Option<&'a PieceType> {
+        let guess: i32 = Material.piece_value(piece);
+        let pawn: Option<&PieceType> = self.smallest(piece_list, position_name_map);
+        piece_list
+            .pieces
+            .iter()
+            .filter(|p| {
+                !p.royal
+                    && p.name != piece.name
+                    && Some(p.name.as_str()) != pawn.map(|p| p.name.as_str())
+            })
+            .filter(|p| {
+                self.openings
+                    .iter()
+                    .all(|o| on_board(position_name_map.get(o), p.white_id))
+            })
+            .min_by_key(|p| (Material.piece_value(p) - guess).abs())
+    }
+
+    // The handicap unit: the non-royal piece White has most of, pawns in chess.
+    fn smallest<'a>(
+        &self,
+        piece_list: &'a PieceList,
+        position_name_map: &HashMap<String, String>,
+    ) -> Option<&'a PieceType> {
+        let fen: &str = self
+            .openings
+            .first()
+            .and_then(|o| position_name_map.get(o))?;
+        piece_list
+            .pieces
+            .iter()
+            .filter(|p| !p.royal)
+            .max_by_key(|p| {
+                fen.split(' ')
+                    .next()
+                    .unwrap_or("")
+                    .matches(p.white_id)
+                    .count()
+            })
+            .filter(|p| squares(fen).contains(&p.white_id))
+    }
+
+    // The swap, then handicaps of one small piece, the replaced piece for a small one and the replaced piece.
+    pub fn trials(
+        &self,
+        piece: &PieceType,
+        replaced: &PieceType,
+        piece_list: &PieceList,
+        position_name_map: &HashMap<String, String>,
+    ) -> Result<Vec<Trial>, String> {
+        let small: &PieceType = self
+            .smallest(piece_list, position_name_map)
+            .ok_or("the openings have nothing to take away")?;
+        let (replaced_value, small_value): (i32, i32) =
+            (Material.piece_value(replaced), Material.piece_value(small));
+        let changes: [(String, char, Option<char>, Option<i32>); 4] = [
+            (
+                format!("{} -> {}", replaced.name, piece.name),
+                replaced.white_id,
+                Some(piece.white_id),
+                None,
+            ),
+            (
+                format!("without a {}", small.name),
+                small.white_id,
+                None,
+                Some(-small_value),
+            ),
+            (
+                format!("{} -> {}", replaced.name, small.name),
+                replaced.white_id,
+                Some(small.white_id),
+                Some(small_value - replaced_value),
+            ),
+            (
+                format!("without a {}", replaced.name),
+                replaced.white_id,
+                None,
+                Some(-replaced_value),
+            ),
+        ];
+        let mut trials: Vec<Trial> = Vec::new();
+        for (label, from, to, difference) in changes {
+            let mut fens: Vec<String> = Vec::new();
+            for opening in &self.openings {
+                let fen: &String = position_name_map
+                    .get(opening)
+                    .ok_or_else(|| format!("there's no position called {}", opening))?;
+                fens.push(
+                    change_fen(fen, from, to)
+                        .ok_or_else(|| format!("{} has no {} to change", opening, from))?,
+                );
+            }
+            trials.push(Trial {
+                label,
+                fens,
+                difference,
+                games: 0,
+                points: 0.0,
+            });
+        }
+        Ok(trials)
+    }
+
+    // Plays every trial, with on_trial hearing about each as it's finished, and fits the curve.
+    pub fn estimate<'a>(
+        &self,
+        piece: &'a PieceType,
+        replaced: &'a PieceType,
+        piece_list: &'a PieceList,
+        position_name_map: &HashMap<String, String>,
+    ) -> Result<Estimate, String> {
+        let mut trials: Vec<Trial> = self.trials(piece, replaced, piece_list, position_name_map)?;
+        let piece_symbol_map: HashMap<char, &PieceType> = piece_list.map_piecetypes_to_symbols();
+        let games: u64 = self.games + self.games % 2;
+        let setups: Vec<(usize, GameSetup, bool)> = (0..trials.len() as u64 * games)
+            .map(|id| {
+                let trial: &Trial = &trials[(id / games) as usize];
+                let fen: &String = &trial.fens[((id % games) / 2) as usize % trial.fens.len()];
+                // Every other game the change is Black's, with the board turned round.
+                let changed_white: bool = id % 2 == 0;
+                let position: String = if changed_white {
+                    fen.clone()
+                } else {
+                    mirror_fen(fen)
+                };
+                let setup: GameSetup = GameSetup {
+                    white: self.engine.clone(),
+                    black: self.engine.clone(),
+                    position,
+                    evaluator: self.evaluator.clone(),
+                    control: None,
+                    max_plies: Some(self.max_plies),
+                    random_plies: self.random_plies,
+                    opening_seed: None,
+                };
+                ((id / games) as usize, setup, changed_white)
+            })
+            .collect();
+        in_parallel(
+            0..setups.len() as u64,
+            self.threads,
+            &self.stop,
+            &|id| {
+                let (_, setup, _) = &setups[id as usize];
+                play_game(
+                    id,
+                    setup,
+                    &self.abandon,
+                    &piece_symbol_map,
+                    position_name_map,
+                )
+            },
+            &mut |id, log: GameLog| {
+                let (trial, _, changed_white) = &setups[id as usize];
+                let white_points: f64 = match log.result.as_deref() {
+                    Some("1-0") => 1.0,
+                    Some("0-1") => 0.0,
+                    _ => 0.5,
+                };
+                trials[*trial].games += 1;
+                trials[*trial].points += if *changed_white {
+                    white_points
+                } else {
+                    1.0 - white_points
+                };
+                Ok(())
+            },
+        )?;
+        Ok(fit(piece, replaced, trials))
+    }
+}
+
+// The score curve is s = 1 / (1 + 10^(-difference / scale)), so log10(s / (1 - s)) is a straight line
+// through the origin against the material difference. Least squares on the handicap trials gives its
+// slope, and the swap trial's score read off it gives the new piece's difference from the replaced one.
+pub fn fit(piece: &PieceType, replaced: &PieceType, trials: Vec<Trial>) -> Estimate {
+    let logit = |s: f64| (s / (1.0 - s)).log10();
+    let (mut products, mut squares): (f64, f64) = (0.0, 0.0);
+    for trial in trials.iter().filter(|t| t.games > 0) {
+        if let Some(difference) = trial.difference {
+            products += difference as f64 * logit(trial.score());
+            squares += (difference as f64) * (difference as f64);
+        }
+    }
+    let replaced_value: i32 = Material.piece_value(replaced);
+    let scale: Option<f64> = Some(squares / products).filter(|s| products > 0.0 && s.is_finite());
+    let swap: Option<&Trial> = trials
+        .iter()
+        .find(|t| t.difference.is_none() && t.games > 0);
+    let (value, error) = match (scale, swap) {
+        (Some(scale), Some(swap)) => {
+            let score: f64 = swap.score();
+            // How far the score could be off, carried through the curve.
+            let spread: f64 = 1.96 * (score * (1.0 - score) / swap.games as f64).sqrt();
+            let slope: f64 = scale / (std::f64::consts::LN_10 * score * (1.0 - score));
+            (
+                Some(replaced_value + (scale * logit(score)).round() as i32),
+                Some(slope * spread),
+            )
+        }
+        _ => (None, None),
+    };
+    Estimate {
+        piece: piece.name.clone(),
+        replaced: replaced.name.clone(),
+        replaced_value,
+        trials,
+        scale,
+        value,
+        error,
+    }
+}
+
+// The board of a FEN as one symbol per square, a8 first, '.' for empty.
+fn squares(fen: &str) -> Vec<char> {
+    let mut output: Vec<char> = Vec::new();
+    for c in fen.split(' ').next().unwrap_or("").chars() {
+        match c.to_digit(10) {
+            Some(empty) => output.extend(std::iter::repeat_n('.', empty as usize)),
+            None if c != '/' => output.push(c),
+            None => {}
+        }
+    }
+    output
+}
+
+// The other way round: just the piece placement, the rest of the FEN is left to default.
+fn placement(squares: &[char]) -> String {
+    let size: usize = BOARDSIZE as usize;
+    let mut ranks: Vec<String> = Vec::new();
+    for rank in squares.chunks(size) {
+        let mut text: String = String::new();
+        let mut empty: u32 = 0;
+        for &c in rank {
+            if c == '.' {
+                empty += 1;
+                continue;
+            }
+            if empty > 0 {
+                text.push_str(&empty.to_string());
+                empty = 0;
+            }
+            text.push(c);
+        }
+        if empty > 0 {
+            text.push_str(&empty.to_string());
+        }
+        ranks.push(text);
+    }
+    ranks.join("/")
+}
+
+fn on_board(fen: Option<&String>, symbol: char) -> bool {
+    fen.is_some_and(|fen| squares(fen).contains(&symbol))
+}
+
+// Swap White's first piece with symbol from, counting from a1 along the ranks, for to, or take it off if to is None.
+pub fn change_fen(fen: &str, from: char, to: Option<char>) -> Option<String> {
+    let mut board: Vec<char> = squares(fen);
+    let size: usize = BOARDSIZE as usize;
+    let square: usize = (0..board.len())
+        .map(|i| (size - 1 - i / size) * size + i % size)
+        .find(|&i| board.get(i) == Some(&from))?;
+    board[square] = to.unwrap_or('.');
+    Some(placement(&board))
+}
+
+// The same position with the colours swapped and the board turned upside down, so the changed army is Black's.
+pub fn mirror_fen(fen: &str) -> String {
+    let board: Vec<char> = squares(fen);
+    let size: usize = BOARDSIZE as usize;
+    let mirrored: Vec<char> = board
+        .chunks(size)
+        .rev()
+        .flatten()
+        .map(|&c| {
+            if c.is_uppercase() {
+                c.to_ascii_lowercase()
+            } else {
+                c.to_ascii_uppercase()
+            }
+        })
+        .collect();
+    placement(&mirrored)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::balance::*;
+    use std::path::Path;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+
+    #[test]
+    fn changes_positions() {
+        assert_eq!(
+            change_fen(START, 'N', Some('B')).unwrap(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RBBQKBNR"
+        );
+        assert_eq!(
+            change_fen(START, 'P', None).unwrap(),
+            "rnbqkbnr/pppppppp/8/8/8/8/1PPPPPPP/RNBQKBNR"
+        );
+        assert_eq!(change_fen(START, 'X', None), None);
+        assert_eq!(
+            mirror_fen("rnbqkbnr/pppppppp/8/8/8/8/1PPPPPPP/RBBQKBNR"),
+            "rbbqkbnr/1ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR"
+        );
+    }
+
+    #[test]
+    fn fits_the_score_curve() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let piece = |name: &str| piece_list.pieces.iter().find(|p| p.name == name).unwrap();
+        // Made up so that the curve has a scale of exactly 400 and the new piece scores like half a knight up.
+        let trial = |difference: Option<i32>, score: f64| Trial {
+            label: String::new(),
+            fens: Vec::new(),
+            difference,
+            games: 1000,
+            points: score * 1000.0,
+        };
+        let curve = |difference: f64| 1.0 / (1.0 + 10f64.powf(-difference / 400.0));
+        let estimate: Estimate = fit(
+            piece("bishop"),
+            piece("knight"),
+            vec![
+                trial(None, curve(150.0)),
+                trial(Some(-100), curve(-100.0)),
+                trial(Some(-200), curve(-200.0)),
+                trial(Some(-300), curve(-300.0)),
+            ],
+        );
+        assert!((estimate.scale.unwrap() - 400.0).abs() < 1.0);
+        assert_eq!(estimate.value, Some(estimate.replaced_value + 150));
+        assert!(estimate.error.unwrap() > 0.0 && estimate.error.unwrap() < 100.0);
+        // Handicaps that made no difference can't be read.
+        let flat: Estimate = fit(
+            piece("bishop"),
+            piece("knight"),
+            vec![trial(None, 0.6), trial(Some(-100), 0.5)],
+        );
+        assert_eq!(flat.value, None);
+        assert!(flat.report().contains("No estimate"));
+    }
+
+    #[test]
+    fn plans_trials() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut position_name_map: HashMap<String, String> = HashMap::new();
+        position_name_map.insert(String::from("standard"), String::from(START));
+        let balance: Balance = Balance::new(vec![String::from("standard")]);
+        let bishop: &PieceType = piece_list
+            .pieces
+            .iter()
+            .find(|p| p.name == "bishop")
+            .unwrap();
+        let replaced: &PieceType = balance
+            .replaceable(bishop, &piece_list, &position_name_map)
+            .unwrap();
+        assert_ne!(replaced.name, "bishop");
+        assert_ne!(replaced.name, "pawn");
+        let trials: Vec<Trial> = balance
+            .trials(bishop, replaced, &piece_list, &position_name_map)
+            .unwrap();
+        assert_eq!(trials.len(), 4);
+        assert_eq!(trials[0].difference, None);
+        assert_eq!(trials[1].label, "without a pawn");
+        assert_eq!(
+            trials[1].fens[0],
+            "rnbqkbnr/pppppppp/8/8/8/8/1PPPPPPP/RNBQKBNR"
+        );
+    }
+}
@@ -0,0 +1,673 @@
+use crate::configuration::*;
+use crate::game::*;
+use crate::random::*;
+use crate::recorder::*;
+use crate::zobrist::splitmix64;
+use crate::BOARDSIZE;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+// Polyglot books are keyed with the 781 Random64 numbers from the Polyglot book format description,
+// read from this file in the working directory like pieces.json.
+pub const POLYGLOT_KEYS_FILE: &str = "polyglot-random64.txt";
+const POLYGLOT_KEY_COUNT: usize = 781;
+// The positions and keys given as test values in the Polyglot format description: the start, 1.e4, 1.e4 d5,
+// 1.e4 d5 2.e5, 1.e4 d5 2.e5 f5, then 3.Ke2 and 3...Kf7, and 1.a4 b5 2.h4 b4 3.c4 and 3...bxc3 4.Ra3.
+// Between them they cover every kind of key, so a table that gets any of them wrong has been copied wrong.
+const POLYGLOT_TEST_KEYS: [(&str, u64); 9] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        0x463B_9618_1691_FC9C,
+    ),
+    (
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        0x823C_9B50_FD11_4196,
+    ),
+    (
+        "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+        0x0756_B944_61C5_0FB0,
+    ),
+    (
+        "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2",
+        0x662F_AFB9_65DB_29D4,
+    ),
+    (
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        0x22A4_8B5A_8E47_FF78,
+    ),
+    (
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3",
+        0x652A_607C_A3F2_42C1,
+    ),
+    (
+        "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+        0x00FD_D303_C946_BDD9,
+    ),
+    (
+        "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+        0x3C81_23EA_7B06_7637,
+    ),
+    (
+        "rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4",
+        0x5C3F_9B82_9B27_9560,
+    ),
+];
+// Polyglot numbers the pieces in this order, black before white.
+const POLYGLOT_PIECES: &str = "pPnNbBrRqQkK";
+
+// One 16 byte entry of a Polyglot .bin file, stored big-endian and sorted by key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PolyglotEntry {
+    pub key: u64,
+    pub raw_move: u16,
+    pub weight: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct PolyglotBook {
+    keys: Vec<u64>,
+    entries: Vec<PolyglotEntry>,
+}
+
+impl PolyglotBook {
+    pub fn from_bytes(bytes: &[u8], keys: Vec<u64>) -> Result<PolyglotBook, String> {
+        if !bytes.len().is_multiple_of(16) {
+            return Err(String::from(
+                "a Polyglot book is a whole number of 16 byte entries",
+            ));
+        }
+        let mut entries: Vec<PolyglotEntry> = bytes
+            .chunks(16)
+            .map(|entry| PolyglotEntry {
+                key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+                raw_move: u16::from_be_bytes(entry[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(entry[10..12].try_into().unwrap()),
+            })
+            .collect();
+        // They should be sorted already, but lookups rely on it.
+        entries.sort_by_key(|entry| entry.key);
+        Ok(PolyglotBook { keys, entries })
+    }
+
+    // The moves stored for the position, as coordinates, with their weights. Moves that aren't legal here are left out.
+    pub fn moves<'a>(&self, game: &mut Game<'a>) -> Vec<(AvailableMove<'a>, u32)> {
+        let fen: String = game.to_fen();
+        let Ok(key) = polyglot_key(&fen, &self.keys) else {
+            return Vec::new();
+        };
+        let start: usize = self.entries.partition_point(|entry| entry.key < key);
+        let mut output: Vec<(AvailableMove<'a>, u32)> = Vec::new();
+        for entry in self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+        {
+            let coordinate: String = polyglot_move(entry.raw_move, &fen);
+            if let Some(available_move) = game.find_move(&coordinate) {
+                output.push((available_move, entry.weight as u32));
+            }
+        }
+        output
+    }
+}
+
+// The Random64 numbers, as hex written in any layout, including C and Python source copied from elsewhere,
+// checked against the test keys from the format description.
+pub fn polyglot_keys(text: &str) -> Result<Vec<u64>, String> {
+    let keys: Vec<u64> = text
+        .split(|c: char| c.is_whitespace() || ",;{}[]()".contains(c))
+        // Numbers are marked 0x, or written out in full, so the 781 in "Random64[781]" isn't one of them.
+        .filter_map(|word| {
+            let word: &str = word.trim_end_matches(['U', 'L', 'u', 'l']);
+            match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+                Some(number) => Some(number),
+                None if word.len() == 16 => Some(word),
+                None => None,
+            }
+        })
+        .filter(|word| !word.is_empty() && word.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|word| u64::from_str_radix(word, 16).map_err(|e| format!("{}: {}", word, e)))
+        .collect::<Result<Vec<u64>, String>>()?;
+    if keys.len() != POLYGLOT_KEY_COUNT {
+        return Err(format!(
+            "expected {} Random64 numbers, found {}",
+            POLYGLOT_KEY_COUNT,
+            keys.len()
+        ));
+    }
+    for (fen, expected) in POLYGLOT_TEST_KEYS {
+        if polyglot_key(fen, &keys)? != expected {
+            return Err(format!(
+                "these aren't Polyglot's Random64 numbers, {} gets the wrong key",
+                fen
+            ));
+        }
+    }
+    Ok(keys)
+}
+
+// Polyglot's hash of a standard chess position, worked out from its FEN.
+pub fn polyglot_key(fen: &str, keys: &[u64]) -> Result<u64, String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let board: &str = fields.first().ok_or("empty FEN")?;
+    let white_to_move: bool = fields.get(1) != Some(&"b");
+    let mut squares: [Option<char>; 64] = [None; 64];
+    let mut output: u64 = 0;
+    for (rank_from_top, rank) in board.split('/').enumerate() {
+        let mut file: usize = 0;
+        for symbol in rank.chars() {
+            if let Some(empty) = symbol.to_digit(10) {
+                file += empty as usize;
+                continue;
+            }
+            let Some(kind) = POLYGLOT_PIECES.find(symbol) else {
+                return Err(format!("{} isn't a standard chess piece", symbol));
+            };
+            let row: usize = 7usize.checked_sub(rank_from_top).ok_or("too many ranks")?;
+            if file > 7 {
+                return Err(String::from("too many files"));
+            }
+            squares[row * 8 + file] = Some(symbol);
+            output ^= keys[64 * kind + 8 * row + file];
+            file += 1;
+        }
+    }
+    for (letter, offset) in [('K', 768), ('Q', 769), ('k', 770), ('q', 771)] {
+        if fields
+            .get(2)
+            .is_some_and(|castling| castling.contains(letter))
+        {
+            output ^= keys[offset];
+        }
+    }
+    // Only counted if a pawn of the side to move stands next to the pawn that just passed.
+    if let Some(target) = fields.get(3).and_then(|ep| parse_square(ep)) {
+        let file: usize = (target % BOARDSIZE) as usize;
+        let (row, pawn) = if white_to_move { (4, 'P') } else { (3, 'p') };
+        let beside = |f: usize| squares[row * 8 + f] == Some(pawn);
+        if file > 0 && beside(file - 1) || file < 7 && beside(file + 1) {
+            output ^= keys[772 + file];
+        }
+    }
+    if white_to_move {
+        output ^= keys[780];
+    }
+    Ok(output)
+}
+
+// Polyglot moves are packed into 16 bits, and castling is written as the king taking its own rook.
+pub fn polyglot_move(raw_move: u16, fen: &str) -> String {
+    let square = |bits: u16| -> String {
+        format!(
+            "{}{}",
+            (b'a' + (bits & 7) as u8) as char,
+            ((bits >> 3) & 7) + 1
+        )
+    };
+    let from: String = square(raw_move >> 6);
+    let mut to: String = square(raw_move);
+    let board: &str = fen.split_whitespace().next().unwrap_or("");
+    let king: bool = parse_square(&from)
+        .and_then(|s| fen_board_symbol(board, s))
+        .is_some_and(|symbol| symbol.eq_ignore_ascii_case(&'k'));
+    if king {
+        to = match (from.as_str(), to.as_str()) {
+            ("e1", "h1") => String::from("g1"),
+            ("e1", "a1") => String::from("c1"),
+            ("e8", "h8") => String::from("g8"),
+            ("e8", "a8") => String::from("c8"),
+            _ => to,
+        };
+    }
+    let promotion: &str = match (raw_move >> 12) & 7 {
+        1 => "n",
+        2 => "b",
+        3 => "r",
+        4 => "q",
+        _ => "",
+    };
+    format!("{}{}{}", from, to, promotion)
+}
+
+fn fen_board_symbol(board: &str, square: u8) -> Option<char> {
+    let mut index: u8 = 0;
+    for symbol in board.chars().filter(|c| *c != '/') {
+        match symbol.to_digit(10) {
+            Some(empty) => index += empty as u8,
+            None => {
+                if index == square {
+                    return Some(symbol);
+                }
+                index += 1;
+            }
+        }
+        if index > square {
+            return None;
+        }
+    }
+    None
+}
+
+// A move in one of our own books, with Polyglot's weighting: two for every game the mover went on to win, one for a draw.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BookMove {
+    pub coordinate: String,
+    pub weight: u32,
+}
+
+// Our own book format for any pieces.json, keyed by Game.hash. The Zobrist keys only know piece symbols,
+// so the book is tagged with a fingerprint of the rules it was built under and refuses to open under others.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct VariantBook {
+    pub ruleset: String,
+    // The piece names, so a person can tell which pieces.json the book goes with.
+    pub pieces: Vec<String>,
+    pub games: usize,
+    pub max_plies: usize,
+    // Hashes written out in hex, since JSON keys have to be strings.
+    pub positions: BTreeMap<String, Vec<BookMove>>,
+}
+
+impl VariantBook {
+    // Every position in the first max_plies plies of the games. Games that can't be replayed are skipped and reported.
+    pub fn build<'a>(
+        logs: &[GameLog],
+        piece_symbol_map: &HashMap<char, &'a PieceType>,
+        standard_fen: &str,
+        max_plies: usize,
+    ) -> (VariantBook, Vec<String>) {
+        let mut weights: BTreeMap<String, BTreeMap<String, u32>> = BTreeMap::new();
+        let mut problems: Vec<String> = Vec::new();
+        let mut games: usize = 0;
+        for log in logs {
+            let mut game: Game<'a> = match Game::from_fen(
+                log.game_id,
+                &log.start_fen,
+                standard_fen,
+                piece_symbol_map.clone(),
+            ) {
+                Ok(game) => game,
+                Err(why) => {
+                    problems.push(format!("game {}: {}", log.game_id, why));
+                    continue;
+                }
+            };
+            let mut seen: Vec<(String, String, u32)> = Vec::new();
+            for ply in log.plies.iter().take(max_plies) {
+                let Some(available_move) = game.find_move(&ply.coordinate) else {
+                    problems.push(format!(
+                        "game {}: ply {} ({}) can't be played",
+                        log.game_id, ply.ply, ply.coordinate
+                    ));
+                    seen.clear();
+                    break;
+                };
+                let weight: u32 = match (log.result.as_deref(), game.active_color) {
+                    (Some("1-0"), 'w') | (Some("0-1"), 'b') => 2,
+                    (Some("1-0"), _) | (Some("0-1"), _) => 0,
+                    // Draws, and games nobody finished.
+                    _ => 1,
+                };
+                seen.push((
+                    format!("{:016x}", game.hash),
+                    ply.coordinate.clone(),
+                    weight,
+                ));
+                game.make_move(available_move);
+            }
+            if seen.is_empty() {
+                continue;
+            }
+            games += 1;
+            for (hash, coordinate, weight) in seen {
+                *weights
+                    .entry(hash)
+                    .or_default()
+                    .entry(coordinate)
+                    .or_default() += weight;
+            }
+        }
+        let mut positions: BTreeMap<String, Vec<BookMove>> = BTreeMap::new();
+        for (hash, moves) in weights {
+            let mut moves: Vec<BookMove> = moves
+                .into_iter()
+                .filter(|(_, weight)| *weight > 0)
+                .map(|(coordinate, weight)| BookMove { coordinate, weight })
+                .collect();
+            if moves.is_empty() {
+                continue;
+            }
+            moves.sort_by(|a, b| {
+                b.weight
+                    .cmp(&a.weight)
+                    .then(a.coordinate.cmp(&b.coordinate))
+            });
+            positions.insert(hash, moves);
+        }
+        let book: VariantBook = VariantBook {
+            ruleset: ruleset_tag(piece_symbol_map),
+            pieces: piece_names(piece_symbol_map),
+            games,
+            max_plies,
+            positions,
+        };
+        (book, problems)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text: String = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, text).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<VariantBook, String> {
+        let text: String = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    }
+
+    pub fn moves<'a>(&self, game: &mut Game<'a>) -> Vec<(AvailableMove<'a>, u32)> {
+        let Some(moves) = self.positions.get(&format!("{:016x}", game.hash)) else {
+            return Vec::new();
+        };
+        moves
+            .iter()
+            .filter_map(|m| game.find_move(&m.coordinate).map(|found| (found, m.weight)))
+            .collect()
+    }
+}
+
+// Either kind of book, picked by the file: .bin is Polyglot, anything else one of ours.
+#[derive(Debug, Clone)]
+pub enum OpeningBook {
+    Polyglot(PolyglotBook),
+    Variant(VariantBook),
+}
+
+impl OpeningBook {
+    // The game is only there to check the book goes with its rules.
+    pub fn open(path: &Path, game: &Game) -> Result<OpeningBook, String> {
+        let name: String = path.display().to_string();
+        if name.ends_with(".bin") {
+            let keys: Vec<u64> = std::fs::read_to_string(POLYGLOT_KEYS_FILE)
+                .map_err(|e| e.to_string())
+                .and_then(|text| polyglot_keys(&text))
+                .map_err(|why| format!("{}: {}", POLYGLOT_KEYS_FILE, why))?;
+            let bytes: Vec<u8> = std::fs::read(path).map_err(|e| format!("{}: {}", name, e))?;
+            return PolyglotBook::from_bytes(&bytes, keys)
+                .map(OpeningBook::Polyglot)
+                .map_err(|why| format!("{}: {}", name, why));
+        }
+        let book: VariantBook =
+            VariantBook::load(path).map_err(|why| format!("{}: {}", name, why))?;
+        if book.ruleset != ruleset_tag(&game.piece_symbol_map) {
+            return Err(format!(
+                "{} was built for other rules ({}), not this pieces.json",
+                name,
+                book.pieces.join(", ")
+            ));
+        }
+        Ok(OpeningBook::Variant(book))
+    }
+
+    pub fn moves<'a>(&self, game: &mut Game<'a>) -> Vec<(AvailableMove<'a>, u32)> {
+        match self {
+            OpeningBook::Polyglot(book) => book.moves(game),
+            OpeningBook::Variant(book) => book.moves(game),
+        }
+    }
+
+    // A book move picked at random in proportion to its weight, or None once the game has left the book.
+    pub fn choose<'a>(
+        &self,
+        game: &mut Game<'a>,
+        random: &mut Random,
+    ) -> Option<AvailableMove<'a>> {
+        let moves: Vec<(AvailableMove<'a>, u32)> = self.moves(game);
+        let total: u32 = moves.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick: u32 = random.below(total as usize) as u32;
+        for (available_move, weight) in moves {
+            if pick < weight {
+                return Some(available_move);
+            }
+            pick -= weight;
+        }
+        None
+    }
+}
+
+// A fingerprint of everything in pieces.json that changes how the game is played: the symbols,
+// every move string, promotions and royalty. Names, values and glyphs can change without it changing.
+pub fn ruleset_tag(piece_symbol_map: &HashMap<char, &PieceType>) -> String {
+    let mut piece_types: Vec<&PieceType> = piece_symbol_map.values().copied().collect();
+    piece_types.sort_by_key(|p| p.white_id);
+    piece_types.dedup_by_key(|p| p.white_id);
+    let mut rules: String = String::new();
+    for piece_type in piece_types {
+        rules.push_str(&format!(
+            "{}{}{}{}{}|",
+            piece_type.white_id,
+            piece_type.black_id,
+            piece_type.promotable,
+            piece_type.promotes_to,
+            piece_type.royal
+        ));
+        for m in &piece_type.moveset {
+            rules.push_str(&m.id);
+            rules.push('|');
+        }
+        rules.push('\n');
+    }
+    let mut output: u64 = 0;
+    for byte in rules.bytes() {
+        output = splitmix64(output ^ byte as u64);
+    }
+    format!("{:016x}", output)
+}
+
+fn piece_names(piece_symbol_map: &HashMap<char, &PieceType>) -> Vec<String> {
+    let mut names: Vec<String> = piece_symbol_map.values().map(|p| p.name.clone()).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::book::*;
+    use crate::summary::*;
+
+    // Stand-in Random64 numbers. The real ones don't ship with the repository.
+    fn made_up_keys() -> Vec<u64> {
+        (0..POLYGLOT_KEY_COUNT as u64)
+            .map(|i| splitmix64(i + 1))
+            .collect()
+    }
+
+    fn entry(key: u64, coordinate: &str, weight: u16) -> Vec<u8> {
+        let square = |name: &str| -> u16 {
+            let square: u8 = parse_square(name).unwrap();
+            ((7 - square / 8) * 8 + square % 8) as u16
+        };
+        let raw_move: u16 = (square(&coordinate[0..2]) << 6) | square(&coordinate[2..4]);
+        let mut bytes: Vec<u8> = key.to_be_bytes().to_vec();
+        bytes.extend(raw_move.to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend([0; 4]);
+        bytes
+    }
+
+    #[test]
+    fn polyglot_keys_and_moves() {
+        let keys: Vec<u64> = made_up_keys();
+        let start: u64 = polyglot_key(POLYGLOT_TEST_KEYS[0].0, &keys).unwrap();
+        // White to move and all four castling rights are in the key.
+        let without: u64 = polyglot_key(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b - - 0 1",
+            &keys,
+        )
+        .unwrap();
+        assert_eq!(
+            start ^ without,
+            keys[768] ^ keys[769] ^ keys[770] ^ keys[771] ^ keys[780]
+        );
+        // En passant only counts when a pawn can take.
+        let e3: &str = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        let no_e3: &str = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(polyglot_key(e3, &keys), polyglot_key(no_e3, &keys));
+        let d6: &str = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3";
+        let no_d6: &str = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3";
+        assert_eq!(
+            polyglot_key(d6, &keys).unwrap() ^ polyglot_key(no_d6, &keys).unwrap(),
+            keys[772 + 3]
+        );
+        assert!(polyglot_key("8/8/8/8/8/8/8/A7 w - - 0 1", &keys).is_err());
+        // Castling is stored as the king taking its rook.
+        let castle: u16 = (4 << 6) | 7;
+        assert_eq!(
+            polyglot_move(castle, "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"),
+            "e1g1"
+        );
+        assert_eq!(
+            polyglot_move(castle, "r3k2r/8/8/8/8/8/8/R3Q2R w - - 0 1"),
+            "e1h1"
+        );
+        assert_eq!(
+            polyglot_move((4 << 12) | (52 << 6) | 60, "8/4P3/8/8/8/8/8/8 w - - 0 1"),
+            "e7e8q"
+        );
+        // A made up table isn't taken for the real one, even if it gets the starting position right.
+        let text: String = keys.iter().map(|k| format!("0x{:016X},\n", k)).collect();
+        assert!(polyglot_keys(&text).unwrap_err().contains("wrong key"));
+        let mut fixed: Vec<u64> = keys.clone();
+        fixed[780] ^= start ^ POLYGLOT_TEST_KEYS[0].1;
+        assert_eq!(
+            polyglot_key(POLYGLOT_TEST_KEYS[0].0, &fixed),
+            Ok(POLYGLOT_TEST_KEYS[0].1)
+        );
+        let text: String = format!(
+            "U64 Random64[781] = {{ {} }};",
+            fixed
+                .iter()
+                .map(|k| format!("U64(0x{:016X})", k))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        assert!(polyglot_keys(&text)
+            .unwrap_err()
+            .contains(POLYGLOT_TEST_KEYS[1].0));
+        assert!(polyglot_keys("0x1234").unwrap_err().contains("found 1"));
+    }
+
+    #[test]
+    fn reads_polyglot_book() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let keys: Vec<u64> = made_up_keys();
+        let start: u64 = polyglot_key(POLYGLOT_TEST_KEYS[0].0, &keys).unwrap();
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend(entry(start, "e2e4", 3));
+        bytes.extend(entry(start, "d2d4", 1));
+        // Not legal, so never played.
+        bytes.extend(entry(start, "e2e5", 50));
+        bytes.extend(entry(start ^ 1, "a2a3", 50));
+        let book: OpeningBook =
+            OpeningBook::Polyglot(PolyglotBook::from_bytes(&bytes, keys).unwrap());
+        let mut game: Game = Game::from_fen(
+            0,
+            POLYGLOT_TEST_KEYS[0].0,
+            POLYGLOT_TEST_KEYS[0].0,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        assert_eq!(book.moves(&mut game).len(), 2);
+        let mut random: Random = Random::new(7);
+        let mut e4: usize = 0;
+        for _ in 0..400 {
+            match book
+                .choose(&mut game, &mut random)
+                .unwrap()
+                .coordinate_notation()
+                .as_str()
+            {
+                "e2e4" => e4 += 1,
+                other => assert_eq!(other, "d2d4"),
+            }
+        }
+        assert!((250..350).contains(&e4), "{}", e4);
+        let first: AvailableMove = game.find_move("e2e4").unwrap();
+        game.make_move(first);
+        assert!(book.choose(&mut game, &mut random).is_none());
+        assert!(PolyglotBook::from_bytes(&[0; 15], Vec::new()).is_err());
+    }
+
+    #[test]
+    fn builds_variant_book() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let piece_symbol_map: HashMap<char, &PieceType> = piece_list.map_piecetypes_to_symbols();
+        let position_name_map: HashMap<String, String> =
+            PositionListIntermediateRepresentation::from_file(Path::new(
+                "testfiles/standardStartingPositions.json",
+            ))
+            .map_positions_to_names();
+        let directory: std::path::PathBuf = std::env::temp_dir().join("piecetestbed-book");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("games.pgn"),
+            "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 1-0\n\n[Result \"0-1\"]\n\n1. e4 c5 0-1\n\n\
+            [Result \"1/2-1/2\"]\n\n1. d4 d5 1/2-1/2\n",
+        )
+        .unwrap();
+        let mut logs: Vec<GameLog> = Vec::new();
+        for (_, read) in read_games(&directory, &piece_symbol_map, &position_name_map).unwrap() {
+            logs.extend(read.unwrap());
+        }
+        let (book, problems) =
+            VariantBook::build(&logs, &piece_symbol_map, &position_name_map["standard"], 2);
+        assert!(problems.is_empty());
+        assert_eq!(book.games, 3);
+        assert!(book.pieces.contains(&String::from("knight")));
+        let first: &Vec<BookMove> = book.positions.values().find(|m| m.len() == 2).unwrap();
+        // e4 won once and lost once, d4 drew.
+        assert_eq!(
+            first,
+            &vec![
+                BookMove {
+                    coordinate: String::from("e2e4"),
+                    weight: 2
+                },
+                BookMove {
+                    coordinate: String::from("d2d4"),
+                    weight: 1
+                },
+            ]
+        );
+        // e5 lost so it's left out, c5 won. Plies past the limit aren't in the book.
+        assert_eq!(book.positions.len(), 3);
+        let file: std::path::PathBuf = directory.join("book.json");
+        book.save(&file).unwrap();
+        let mut game: Game = Game::from_fen(
+            0,
+            &position_name_map["standard"],
+            &position_name_map["standard"],
+            piece_symbol_map.clone(),
+        )
+        .unwrap();
+        let opened: OpeningBook = OpeningBook::open(&file, &game).unwrap();
+        let e4: AvailableMove = game.find_move("e2e4").unwrap();
+        game.make_move(e4);
+        let reply: AvailableMove = opened.choose(&mut game, &mut Random::new(1)).unwrap();
+        assert_eq!(reply.coordinate_notation(), "c7c5");
+        // Other rules, other fingerprint.
+        let mut changed: VariantBook = book.clone();
+        changed.ruleset = String::from("0");
+        changed.save(&file).unwrap();
+        assert!(OpeningBook::open(&file, &game)
+            .unwrap_err()
+            .contains("other rules"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
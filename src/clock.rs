@@ -0,0 +1,445 @@
+use crate::configuration::*;
+use crate::evaluation::*;
+use crate::game::*;
+use std::time::Duration;
+
+// A lone piece worth less than this can't mate a bare royal piece, like a bishop or a knight. A rook can.
+const MATING_VALUE: i32 = 400;
+
+// One stretch of a time control: a number of moves, or the rest of the game, in so much time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub moves: Option<u32>,
+    pub time: Duration,
+    // Fischer increment, added after every move.
+    pub increment: Duration,
+    // Bronstein delay, up to this much of the time a move took is given back.
+    pub delay: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControl {
+    pub periods: Vec<Period>,
+}
+
+impl TimeControl {
+    // Periods separated by commas, each [moves/]minutes[+increment seconds][d delay seconds]:
+    //   5                five minutes sudden death
+    //   3+2              three minutes with a two second increment
+    //   5d3              five minutes with a three second Bronstein delay
+    //   40/90+30,30+30   40 moves in 90 minutes then 30 minutes for the rest, 30 seconds increment throughout
+    // A last period with a number of moves repeats, so 40/120 is 120 minutes for every 40 moves.
+    pub fn parse(text: &str) -> Result<TimeControl, String> {
+        let mut periods: Vec<Period> = Vec::new();
+        for period in text.split(',') {
+            let (moves, rest) = match period.split_once('/') {
+                Some((moves, rest)) => (Some(parse_number(moves, "moves")?), rest),
+                None => (None, period),
+            };
+            let (rest, delay) = match rest.split_once('d') {
+                Some((rest, delay)) => (rest, seconds(delay, "delay")?),
+                None => (rest, Duration::ZERO),
+            };
+            let (minutes, increment) = match rest.split_once('+') {
+                Some((minutes, increment)) => (minutes, seconds(increment, "increment")?),
+                None => (rest, Duration::ZERO),
+            };
+            periods.push(Period {
+                moves,
+                time: seconds(minutes, "minutes")? * 60,
+                increment,
+                delay,
+            });
+        }
+        if periods.iter().any(|p| p.moves == Some(0)) {
+            return Err(format!("{}: a period needs at least one move", text));
+        }
+        Ok(TimeControl { periods })
+    }
+
+    // The PGN TimeControl tag, like 40/5400+30:1800+30. PGN has no way to write a delay so it's left out.
+    pub fn to_pgn(&self) -> String {
+        let periods: Vec<String> = self
+            .periods
+            .iter()
+            .map(|p| {
+                let mut output: String = String::new();
+                if let Some(moves) = p.moves {
+                    output.push_str(&format!("{}/", moves));
+                }
+                output.push_str(&p.time.as_secs().to_string());
+                if !p.increment.is_zero() {
+                    output.push_str(&format!("+{}", p.increment.as_secs()));
+                }
+                output
+            })
+            .collect();
+        periods.join(":")
+    }
+
+    // Reads the TimeControl tag back. Only the kinds to_pgn writes are understood.
+    pub fn from_pgn(text: &str) -> Result<TimeControl, String> {
+        let mut minutes: Vec<String> = Vec::new();
+        for period in text.split(':') {
+            let (moves, rest) = match period.split_once('/') {
+                Some((moves, rest)) => (format!("{}/", moves), rest),
+                None => (String::new(), period),
+            };
+            let (time, increment) = match rest.split_once('+') {
+                Some((time, increment)) => (time, format!("+{}", increment)),
+                None => (rest, String::new()),
+            };
+            let time: f64 = time
+                .parse()
+                .map_err(|_| format!("{} isn't a time control", text))?;
+            minutes.push(format!("{}{}{}", moves, time / 60.0, increment));
+        }
+        TimeControl::parse(&minutes.join(","))
+    }
+
+    // Periods past the end repeat the last one.
+    fn period(&self, index: usize) -> &Period {
+        &self.periods[index.min(self.periods.len() - 1)]
+    }
+}
+
+fn parse_number(text: &str, what: &str) -> Result<u32, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{} should be a whole number, got {}", what, text))
+}
+
+fn seconds(text: &str, what: &str) -> Result<Duration, String> {
+    match text.trim().parse::<f64>() {
+        Ok(number) if number >= 0.0 && number.is_finite() => Ok(Duration::from_secs_f64(number)),
+        _ => Err(format!("{} should be a number, got {}", what, text)),
+    }
+}
+
+// One player's side of the clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    pub remaining: Duration,
+    // Which period the player is in and how many moves they've made in it.
+    pub period: usize,
+    pub moves_in_period: u32,
+}
+
+// Both clocks, kept with the game. They only run when someone punches them, which whoever plays the moves does.
+#[derive(Debug, Clone)]
+pub struct Clocks {
+    pub control: TimeControl,
+    pub white: Clock,
+    pub black: Clock,
+    // What was left on the mover's clock after each move, for [%clk] comments.
+    pub times: Vec<Duration>,
+    // The mover and their clock before each move, for taking moves back.
+    history: Vec<(char, Clock)>,
+    // Set once a flag falls, with the clock as it was before.
+    flag: Option<(Outcome, char, Clock)>,
+}
+
+impl Clocks {
+    pub fn new(control: TimeControl) -> Clocks {
+        let clock: Clock = Clock {
+            remaining: control.period(0).time,
+            period: 0,
+            moves_in_period: 0,
+        };
+        Clocks {
+            control,
+            white: clock,
+            black: clock,
+            times: Vec::new(),
+            history: Vec::new(),
+            flag: None,
+        }
+    }
+
+    // Pick up from the [%clk] times of a saved game, alternating from first_mover.
+    pub fn resume(control: TimeControl, times: &[Duration], first_mover: char) -> Clocks {
+        let mut clocks: Clocks = Clocks::new(control);
+        let mut player: char = first_mover;
+        for &time in times {
+            let before: Clock = *clocks.clock(player);
+            clocks.count_move(player);
+            clocks.clock_mut(player).remaining = time;
+            clocks.history.push((player, before));
+            clocks.times.push(time);
+            player = opponent(player);
+        }
+        clocks
+    }
+
+    pub fn clock(&self, player: char) -> &Clock {
+        if player == 'w' {
+            &self.white
+        } else {
+            &self.black
+        }
+    }
+
+    fn clock_mut(&mut self, player: char) -> &mut Clock {
+        if player == 'w' {
+            &mut self.white
+        } else {
+            &mut self.black
+        }
+    }
+
+    pub fn remaining(&self, player: char) -> Duration {
+        self.clock(player).remaining
+    }
+
+    pub fn flag(&self) -> Option<Outcome> {
+        self.flag.map(|(outcome, _, _)| outcome)
+    }
+
+    // Moves left before the player's next time control, if their period has one.
+    pub fn moves_to_go(&self, player: char) -> Option<u32> {
+        let clock: &Clock = self.clock(player);
+        self.control
+            .period(clock.period)
+            .moves
+            .map(|moves| moves - clock.moves_in_period)
+    }
+
+    // What the player gets back per move, counting a delay like an increment since either is time that doesn't run out.
+    pub fn increment(&self, player: char) -> Duration {
+        let period: &Period = self.control.period(self.clock(player).period);
+        period.increment + period.delay
+    }
+
+    // Take elapsed off the player's clock for a move. Returns false, leaving the clock at zero, if that's more than they had.
+    pub fn punch(&mut self, player: char, elapsed: Duration) -> bool {
+        let before: Clock = *self.clock(player);
+        if elapsed > before.remaining {
+            self.clock_mut(player).remaining = Duration::ZERO;
+            return false;
+        }
+        let period: Period = *self.control.period(before.period);
+        let clock: &mut Clock = self.clock_mut(player);
+        clock.remaining = clock.remaining - elapsed + elapsed.min(period.delay) + period.increment;
+        self.count_move(player);
+        self.history.push((player, before));
+        self.times.push(self.remaining(player));
+        true
+    }
+
+    // One more move in the period, and the next period's time once enough have been made.
+    fn count_move(&mut self, player: char) {
+        let clock: Clock = *self.clock(player);
+        if self.control.period(clock.period).moves == Some(clock.moves_in_period + 1) {
+            let next: Duration = self.control.period(clock.period + 1).time;
+            let clock: &mut Clock = self.clock_mut(player);
+            clock.period += 1;
+            clock.moves_in_period = 0;
+            clock.remaining += next;
+        } else {
+            self.clock_mut(player).moves_in_period += 1;
+        }
+    }
+
+    // Put the clocks back to before the last move, and lift a fallen flag.
+    pub fn undo(&mut self) {
+        if let Some((_, player, clock)) = self.flag.take() {
+            *self.clock_mut(player) = clock;
+        }
+        if let Some((player, clock)) = self.history.pop() {
+            *self.clock_mut(player) = clock;
+            self.times.pop();
+        }
+    }
+}
+
+impl<'a> Game<'a> {
+    pub fn start_clocks(&mut self, control: TimeControl) {
+        self.clocks = Some(Clocks::new(control));
+    }
+
+    // Call with how long the side to move took, before making their move. Returns false if their flag fell,
+    // in which case the move doesn't count and the game is over: lost, or drawn if the opponent couldn't mate anyway.
+    // Does nothing without clocks.
+    pub fn punch_clock(&mut self, elapsed: Duration) -> bool {
+        let mover: char = self.active_color;
+        let outcome: Outcome = if can_mate(self, opponent(mover)) {
+            Outcome::Timeout(opponent(mover))
+        } else {
+            Outcome::TimeoutDraw
+        };
+        let clocks: &mut Clocks = match self.clocks.as_mut() {
+            Some(clocks) => clocks,
+            None => return true,
+        };
+        let before: Clock = *clocks.clock(mover);
+        if clocks.punch(mover, elapsed) {
+            return true;
+        }
+        clocks.flag = Some((outcome, mover, before));
+        false
+    }
+
+    // For after unmake_move.
+    pub fn undo_clock(&mut self) {
+        if let Some(clocks) = self.clocks.as_mut() {
+            clocks.undo();
+        }
+    }
+
+    // How long the engine should think: a share of what's on the clock, or nothing without clocks.
+    pub fn time_budget(&self) -> Option<Duration> {
+        let clocks: &Clocks = self.clocks.as_ref()?;
+        let player: char = self.active_color;
+        Some(crate::search::time_for_move(
+            clocks.remaining(player),
+            clocks.increment(player),
+            clocks.moves_to_go(player),
+        ))
+    }
+}
+
+// Whether the player has anything that could ever mate: something besides their royal pieces,
+// and not just one minor piece against nothing but royal pieces.
+pub fn can_mate(game: &Game, player: char) -> bool {
+    let material = |side: char| -> Vec<&Piece> {
+        game.list_of_pieces_ingame
+            .iter()
+            .filter(|p| !p.captured && p.player == side && !p.piece_type.royal)
+            .collect()
+    };
+    let ours: Vec<&Piece> = material(player);
+    match ours.as_slice() {
+        [] => false,
+        [piece] if material(opponent(player)).is_empty() => {
+            piece.piece_type.promotable || Material.piece_value(piece.piece_type) >= MATING_VALUE
+        }
+        _ => true,
+    }
+}
+
+// H:MM:SS, as in PGN [%clk] comments.
+pub fn format_clock(time: Duration) -> String {
+    let seconds: u64 = time.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// Every [%clk H:MM:SS] in a PGN, in order.
+pub fn pgn_clock_times(text: &str) -> Vec<Duration> {
+    let mut output: Vec<Duration> = Vec::new();
+    for (index, _) in text.match_indices("[%clk ") {
+        let time: &str = text[index + 6..].split(']').next().unwrap_or("");
+        let mut seconds: f64 = 0.0;
+        for part in time.trim().split(':') {
+            seconds = seconds * 60.0 + part.parse::<f64>().unwrap_or(0.0);
+        }
+        output.push(Duration::from_secs_f64(seconds));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::clock::*;
+    use std::path::Path;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn time_controls() {
+        let fischer: TimeControl = TimeControl::parse("3+2").unwrap();
+        let mut clocks: Clocks = Clocks::new(fischer.clone());
+        assert!(clocks.punch('w', secs(10)));
+        assert_eq!(clocks.remaining('w'), secs(172));
+        assert_eq!(fischer.to_pgn(), "180+2");
+        // Bronstein delay gives back up to the delay, never more than was used.
+        let mut clocks: Clocks = Clocks::new(TimeControl::parse("5d3").unwrap());
+        clocks.punch('w', secs(10));
+        clocks.punch('b', secs(1));
+        assert_eq!(clocks.remaining('w'), secs(293));
+        assert_eq!(clocks.remaining('b'), secs(300));
+        let classical: TimeControl = TimeControl::parse("40/90+30,30+30").unwrap();
+        assert_eq!(classical.to_pgn(), "40/5400+30:1800+30");
+        assert_eq!(
+            TimeControl::from_pgn("40/5400+30:1800+30"),
+            Ok(classical.clone())
+        );
+        let mut clocks: Clocks = Clocks::new(classical);
+        for _ in 0..39 {
+            clocks.punch('w', secs(30));
+        }
+        assert_eq!(clocks.moves_to_go('w'), Some(1));
+        assert_eq!(clocks.remaining('w'), secs(5400));
+        clocks.punch('w', secs(30));
+        assert_eq!(clocks.remaining('w'), secs(5400 + 1800));
+        assert_eq!(clocks.moves_to_go('w'), None);
+        clocks.undo();
+        assert_eq!(clocks.remaining('w'), secs(5400));
+        // A last period with moves repeats.
+        let mut clocks: Clocks = Clocks::new(TimeControl::parse("2/1").unwrap());
+        clocks.punch('w', secs(10));
+        clocks.punch('w', secs(10));
+        assert_eq!(clocks.remaining('w'), secs(100));
+        assert_eq!(clocks.moves_to_go('w'), Some(2));
+        assert!(TimeControl::parse("0/5").is_err());
+        assert!(TimeControl::parse("five").is_err());
+    }
+
+    #[test]
+    fn flag_falls() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        game.start_clocks(TimeControl::parse("1").unwrap());
+        assert!(game.punch_clock(secs(5)));
+        let e4: AvailableMove = game.find_move("e2e4").unwrap();
+        game.make_move(e4);
+        assert!(!game.punch_clock(secs(61)));
+        assert_eq!(game.outcome(), Some(Outcome::Timeout('w')));
+        assert_eq!(game.clocks.as_ref().unwrap().remaining('b'), Duration::ZERO);
+        game.unmake_move();
+        game.undo_clock();
+        assert_eq!(game.outcome(), None);
+        assert_eq!(game.clocks.as_ref().unwrap().remaining('w'), secs(60));
+        assert_eq!(game.clocks.as_ref().unwrap().remaining('b'), secs(60));
+        // A knight can't mate a bare king, so running out against it is a draw.
+        let mut game: Game = Game::from_fen(
+            0,
+            "4k3/8/8/8/8/8/8/1N2K3 b - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        game.start_clocks(TimeControl::parse("1").unwrap());
+        assert!(!game.punch_clock(secs(61)));
+        assert_eq!(game.outcome(), Some(Outcome::TimeoutDraw));
+        assert!(game.time_budget().is_some());
+    }
+
+    #[test]
+    fn clock_comments() {
+        assert_eq!(format_clock(secs(5397)), "1:29:57");
+        assert_eq!(
+            pgn_clock_times("1. e4 {[%clk 0:04:58]} 1... e5 {[%clk 1:00:01.5]}"),
+            vec![secs(298), Duration::from_millis(3_601_500)]
+        );
+        let clocks: Clocks = Clocks::resume(
+            TimeControl::parse("5").unwrap(),
+            &[secs(298), secs(290)],
+            'w',
+        );
+        assert_eq!(clocks.remaining('w'), secs(298));
+        assert_eq!(clocks.remaining('b'), secs(290));
+        assert_eq!(clocks.times.len(), 2);
+    }
+}
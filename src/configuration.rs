@@ -16,7 +16,7 @@ use std::collections::HashMap;
 // ###### HANDLING PIECES ######
 
 //An actual piece on the board.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Piece<'a>{
     pub id: u8,
     pub player: char,
@@ -24,6 +24,7 @@ pub struct Piece<'a>{
     pub position: u8,
     pub piece_type: &'a PieceType,
     pub has_castled: bool,
+    pub captured: bool,
     pub list_of_moves: Vec<String>
 }
 
@@ -52,7 +53,13 @@ pub struct PieceType {
     pub black_id: char,
     pub moveset: Vec<Move>,
     pub promotable: bool,
-    pub promotes_to: String
+    pub promotes_to: String,
+    // Royal pieces (the king in standard chess) may never be left in check.
+    pub royal: bool,
+    // Material value in centipawns, for evaluators that want one. Optional, pieces.json doesn't have to say.
+    pub value: Option<i32>,
+    // Unicode glyphs for the white and black piece, for boards drawn in the terminal. Falls back on the symbols.
+    pub glyphs: Option<(char, char)>
 }
 
 //Intermediate Piece List, again for handling serde's output.
@@ -68,7 +75,13 @@ struct PieceIntermediateRepresentation {
     id: String,
     moves: Vec<String>,
     promotable: bool,
-    promotes_to: String
+    promotes_to: String,
+    #[serde(default)]
+    royal: bool,
+    #[serde(default)]
+    value: Option<i32>,
+    #[serde(default)]
+    glyph: Option<String>
 }
 
 // Data Structure for Each Move
@@ -104,23 +117,20 @@ pub struct Move {
 }
 
 // Accept the Pieces.json file, reading all of the pieces contained within.
-fn parse_pieces_json() -> Result<Vec<PieceIntermediateRepresentation>> {
-    // Create a path to the desired file
-    let path = Path::new("pieces.json");
+fn parse_pieces_json(path: &Path) -> Result<Vec<PieceIntermediateRepresentation>> {
     let display = path.display();
 
     // Open the path in read-only mode, returns `io::Result<File>`
-    let mut file = match File::open(&path) {
+    let mut file = match File::open(path) {
         Err(why) => panic!("Couldn't open {}: {}", display, why),
         Ok(file) => file,
     };
 
     // Read the file contents into a string, returns `io::Result<usize>`
     let mut s = String::new();
-    match file.read_to_string(&mut s) {
-        Err(why) => panic!("couldn't read {}: {}", display, why),
-        // Uncomment this to check what the file contains.
-        Ok(_) => (), //print!("{} contains:\n{}", display, s),
+    // Add `print!("{} contains:\n{}", display, s);` after this to check what the file contains.
+    if let Err(why) = file.read_to_string(&mut s) {
+        panic!("couldn't read {}: {}", display, why)
     }
     //Convert that String into a str.
     s = s.to_owned();
@@ -151,6 +161,16 @@ fn parse_pieces_json() -> Result<Vec<PieceIntermediateRepresentation>> {
     Ok(output)
 }
 
+// "glyph" is written like "id", white then black. One character is used for both sides.
+fn parse_glyphs(glyph: Option<String>) -> Option<(char, char)> {
+    let chars: Vec<char> = glyph?.chars().collect();
+    match chars.len() {
+        1 => Some((chars[0], chars[0])),
+        2 => Some((chars[0], chars[1])),
+        _ => None
+    }
+}
+
 // Function for handling the moveset of each piece.
 fn parse_moveset(moveslist: Vec<String>) -> Result<Vec<Move>> {
     let mut output: Vec<Move> = Vec::new();
@@ -216,22 +236,14 @@ fn parse_moveset(moveslist: Vec<String>) -> Result<Vec<Move>> {
                 }.unwrap(),
                 piece_makes_previous_move: false, // We can say this because of the length of the string.
                 previous_move: None,
-                requires_target_piece: if (m_s_chars[16], m_s_chars[17], m_s_chars[18], m_s_chars[19], m_s_chars[20], m_s_chars[21]) == ('0','0', '0', '0', '0', '0') {
-                    false
-                } else {
-                    true
-                },
+                requires_target_piece: (m_s_chars[16], m_s_chars[17], m_s_chars[18], m_s_chars[19], m_s_chars[20], m_s_chars[21]) != ('0','0', '0', '0', '0', '0'),
                 target_piece_player: m_s_chars[16],
                 target_piece_id: m_s_chars[17],
-                target_piece_relative_location: (m_s_chars[0].to_digit(18).unwrap().try_into().unwrap(), m_s_chars[1].to_digit(19).unwrap().try_into().unwrap(), m_s_chars[1].to_digit(20).unwrap().try_into().unwrap(), m_s_chars[1].to_digit(21).unwrap().try_into().unwrap()),
-                castles: if (m_s_chars[24], m_s_chars[25], m_s_chars[26], m_s_chars[27], m_s_chars[28], m_s_chars[29]) == ('0','0', '0', '0', '0', '0') {
-                    false
-                } else {
-                    true
-                },
+                target_piece_relative_location: (m_s_chars[18].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[19].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[20].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[21].to_digit(10).unwrap().try_into().unwrap()),
+                castles: (m_s_chars[24], m_s_chars[25], m_s_chars[26], m_s_chars[27], m_s_chars[28], m_s_chars[29]) != ('0','0', '0', '0', '0', '0'),
                 castle_target_piece_player: m_s_chars[24],
                 castle_target_piece_id: m_s_chars[25],
-                castle_target_piece_relative_location: (m_s_chars[0].to_digit(26).unwrap().try_into().unwrap(), m_s_chars[1].to_digit(27).unwrap().try_into().unwrap(), m_s_chars[1].to_digit(28).unwrap().try_into().unwrap(), m_s_chars[1].to_digit(29).unwrap().try_into().unwrap()),
+                castle_target_piece_relative_location: (m_s_chars[26].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[27].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[28].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[29].to_digit(10).unwrap().try_into().unwrap()),
                 castle_target_piece_movement: (m_s_chars[31].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[32].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[33].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[34].to_digit(10).unwrap().try_into().unwrap()),
                 castle_target_piece_cannot_move: match m_s_chars[35] {
                     '0' => Ok(false),
@@ -307,19 +319,11 @@ fn parse_moveset(moveslist: Vec<String>) -> Result<Vec<Move>> {
                     }.unwrap(),
                     piece_makes_previous_move: false, // We can say this because of the length of the string.
                     previous_move: None,
-                    requires_target_piece: if (m_s_chars[16], m_s_chars[17], m_s_chars[18], m_s_chars[19], m_s_chars[20], m_s_chars[21]) == ('0','0', '0', '0', '0', '0') {
-                        false
-                    } else {
-                        true
-                    },
+                    requires_target_piece: (m_s_chars[16], m_s_chars[17], m_s_chars[18], m_s_chars[19], m_s_chars[20], m_s_chars[21]) != ('0','0', '0', '0', '0', '0'),
                     target_piece_player: m_s_chars[16],
                     target_piece_id: m_s_chars[17],
                     target_piece_relative_location: (m_s_chars[18].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[19].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[20].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[21].to_digit(10).unwrap().try_into().unwrap()),
-                    castles: if (m_s_chars[24], m_s_chars[25], m_s_chars[26], m_s_chars[27], m_s_chars[28], m_s_chars[29]) == ('0','0', '0', '0', '0', '0') {
-                        false
-                    } else {
-                        true
-                    },
+                    castles: (m_s_chars[24], m_s_chars[25], m_s_chars[26], m_s_chars[27], m_s_chars[28], m_s_chars[29]) != ('0','0', '0', '0', '0', '0'),
                     castle_target_piece_player: m_s_chars[24],
                     castle_target_piece_id: m_s_chars[25],
                     castle_target_piece_relative_location: (m_s_chars[26].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[27].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[28].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[29].to_digit(10).unwrap().try_into().unwrap()),
@@ -348,7 +352,7 @@ fn parse_moveset(moveslist: Vec<String>) -> Result<Vec<Move>> {
                 };
                 output.push(m);
             } else if (m_s_chars[m_s_chars.len() - 3], m_s_chars[m_s_chars.len() - 2], m_s_chars[m_s_chars.len() - 1]) == ('M', '0', '!') { // Now handle cases where we only need to deal with a previous move. This means that the last characters are M0!, indicating no move specified in the en passant condition (would appear as M0!! or with more exclamation points depending on nesting).
-                let mut end_index = 0; // Use this to determine the end of the previous move's string.
+                let end_index; // Use this to determine the end of the previous move's string.
                 let m:Move = Move {
                     id: m_s_chars.clone().into_iter().collect(),
                     // Read char as digit, unwrap the result as u32, convert u32 to u8, unwrap the result.
@@ -424,19 +428,11 @@ fn parse_moveset(moveslist: Vec<String>) -> Result<Vec<Move>> {
                             output_previous_move_string
                         }
                     ),
-                    requires_target_piece: if (m_s_chars[end_index + 2], m_s_chars[end_index + 3], m_s_chars[end_index + 4], m_s_chars[end_index + 5], m_s_chars[end_index + 6], m_s_chars[end_index + 7]) == ('0','0', '0', '0', '0', '0') {
-                        false
-                    } else {
-                        true
-                    },
+                    requires_target_piece: (m_s_chars[end_index + 2], m_s_chars[end_index + 3], m_s_chars[end_index + 4], m_s_chars[end_index + 5], m_s_chars[end_index + 6], m_s_chars[end_index + 7]) != ('0','0', '0', '0', '0', '0'),
                     target_piece_player: m_s_chars[end_index + 2],
                     target_piece_id: m_s_chars[end_index + 3],
                     target_piece_relative_location: (m_s_chars[end_index + 4].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[end_index + 5].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[end_index + 6].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[end_index + 7].to_digit(10).unwrap().try_into().unwrap()),
-                    castles: if (m_s_chars[end_index + 10], m_s_chars[end_index + 11], m_s_chars[end_index + 12], m_s_chars[end_index + 13], m_s_chars[end_index + 14], m_s_chars[end_index + 15]) == ('0','0', '0', '0', '0', '0') {
-                        false
-                    } else {
-                        true
-                    },
+                    castles: (m_s_chars[end_index + 10], m_s_chars[end_index + 11], m_s_chars[end_index + 12], m_s_chars[end_index + 13], m_s_chars[end_index + 14], m_s_chars[end_index + 15]) != ('0','0', '0', '0', '0', '0'),
                     castle_target_piece_player: m_s_chars[end_index + 10],
                     castle_target_piece_id: m_s_chars[end_index + 11],
                     castle_target_piece_relative_location: (m_s_chars[end_index + 12].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[end_index + 13].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[end_index + 14].to_digit(10).unwrap().try_into().unwrap(), m_s_chars[end_index + 15].to_digit(10).unwrap().try_into().unwrap()),
@@ -462,10 +458,15 @@ fn parse_moveset(moveslist: Vec<String>) -> Result<Vec<Move>> {
 
 // Load in the piece list based on the appropriate json file.
 pub fn load_piece_list() -> Result<PieceList> {
+    load_piece_list_from(Path::new("pieces.json"))
+}
+
+// Load in the piece list from any json file laid out like pieces.json, e.g. the ones in testfiles.
+pub fn load_piece_list_from(path: &Path) -> Result<PieceList> {
     let mut output_piece_list: PieceList = PieceList {
         pieces: Vec::new()
     };
-    match parse_pieces_json() {
+    match parse_pieces_json(path) {
         Err(why) => panic!("Failed to parse pieces.json because: {}", why),
         Ok(piece_intermediate_representation_vector) => {
             for piece_intermediate_representation in piece_intermediate_representation_vector {
@@ -476,7 +477,10 @@ pub fn load_piece_list() -> Result<PieceList> {
                     black_id: temp[1],
                     moveset: parse_moveset(piece_intermediate_representation.moves).unwrap(),
                     promotable: piece_intermediate_representation.promotable,
-                    promotes_to: piece_intermediate_representation.promotes_to
+                    promotes_to: piece_intermediate_representation.promotes_to,
+                    royal: piece_intermediate_representation.royal,
+                    value: piece_intermediate_representation.value,
+                    glyphs: parse_glyphs(piece_intermediate_representation.glyph)
                 };
                 output_piece_list.pieces.push(piece);
             }
@@ -485,6 +489,42 @@ pub fn load_piece_list() -> Result<PieceList> {
     Ok(output_piece_list)
 }
 
+// Set a piece's value in a json file laid out like pieces.json, leaving everything else as it was written.
+// The value goes where it is already, or just before promotable if the piece doesn't have one yet.
+// serde_json keeps the keys in the order they were read (the preserve_order feature), and writes with the same four space indent.
+pub fn write_piece_value(path: &Path, name: &str, value: i32) -> std::result::Result<(), String> {
+    let text: String = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut json: serde_json::Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+    let piece: &mut serde_json::Map<String, serde_json::Value> = json["pieces"].as_array_mut()
+        .and_then(|pieces| pieces.iter_mut().find(|p| p["name"] == name))
+        .and_then(|p| p.as_object_mut())
+        .ok_or_else(|| format!("there's no piece called {} in {}", name, path.display()))?;
+    if let Some(old) = piece.get_mut("value") {
+        *old = serde_json::Value::from(value);
+    } else {
+        // Map can't insert in the middle, so put the fields back one at a time.
+        let fields: serde_json::Map<String, serde_json::Value> = std::mem::take(piece);
+        let promotable: bool = fields.contains_key("promotable");
+        for (key, field) in fields {
+            if key == "promotable" {
+                piece.insert(String::from("value"), serde_json::Value::from(value));
+            }
+            piece.insert(key, field);
+        }
+        if !promotable {
+            piece.insert(String::from("value"), serde_json::Value::from(value));
+        }
+    }
+    let mut output: Vec<u8> = Vec::new();
+    let mut serializer = serde_json::Serializer::with_formatter(&mut output, serde_json::ser::PrettyFormatter::with_indent(b"    "));
+    json.serialize(&mut serializer).map_err(|e| e.to_string())?;
+    // Keep a newline at the end if the file had one.
+    if text.ends_with('\n') {
+        output.push(b'\n');
+    }
+    std::fs::write(path, output).map_err(|e| e.to_string())
+}
+
 // ###### HANDLING POSITIONS ######
 //Intermediate Position List, again for handling serde's output.
 #[derive(Deserialize, Debug)]
@@ -494,8 +534,13 @@ pub struct PositionListIntermediateRepresentation {
 
 impl PositionListIntermediateRepresentation {
     pub fn new() -> PositionListIntermediateRepresentation {
+        PositionListIntermediateRepresentation::from_file(Path::new("startingPositions.json"))
+    }
+
+    // Same as new(), but for a positions file somewhere other than startingPositions.json.
+    pub fn from_file(path: &Path) -> PositionListIntermediateRepresentation {
         PositionListIntermediateRepresentation {
-            positions: parse_starting_positions_json().unwrap()
+            positions: parse_starting_positions_json(path).unwrap()
         }
     }
 
@@ -518,23 +563,20 @@ struct PositionIntermediateRepresentation {
 }
 
 // Accept the startingPositions.json file, reading all of the partial FEN notations contained within.
-fn parse_starting_positions_json() -> Result<Vec<PositionIntermediateRepresentation>> {
-    // Create a path to the desired file
-    let path = Path::new("startingPositions.json");
+fn parse_starting_positions_json(path: &Path) -> Result<Vec<PositionIntermediateRepresentation>> {
     let display = path.display();
 
     // Open the path in read-only mode, returns `io::Result<File>`
-    let mut file = match File::open(&path) {
+    let mut file = match File::open(path) {
         Err(why) => panic!("Couldn't open {}: {}", display, why),
         Ok(file) => file,
     };
 
     // Read the file contents into a string, returns `io::Result<usize>`
     let mut s = String::new();
-    match file.read_to_string(&mut s) {
-        Err(why) => panic!("couldn't read {}: {}", display, why),
-        // Uncomment this to check what the file contains.
-        Ok(_) => (), //print!("{} contains:\n{}", display, s),
+    // Add `print!("{} contains:\n{}", display, s);` after this to check what the file contains.
+    if let Err(why) = file.read_to_string(&mut s) {
+        panic!("couldn't read {}: {}", display, why)
     }
     //Convert that String into a str.
     s = s.to_owned();
@@ -561,17 +603,15 @@ mod tests {
         let display = path.display();
     
         // Open the path in read-only mode, returns `io::Result<File>`
-        let mut file = match File::open(&path) {
+        let mut file = match File::open(path) {
             Err(why) => panic!("Couldn't open {}: {}", display, why),
             Ok(file) => file,
         };
     
         // Read the file contents into a string, returns `io::Result<usize>`
         let mut s = String::new();
-        match file.read_to_string(&mut s) {
-            Err(why) => panic!("couldn't read {}: {}", display, why),
-            // Uncomment this to check what the file contains.
-            Ok(_) => (), //print!("{} contains:\n{}", display, s),
+        if let Err(why) = file.read_to_string(&mut s) {
+            panic!("couldn't read {}: {}", display, why)
         }
         //Convert that String into a str.
         s = s.to_owned();
@@ -604,7 +644,10 @@ mod tests {
                             black_id: temp[1],
                             moveset: parse_moveset(piece_intermediate_representation.moves).unwrap(),
                             promotable: piece_intermediate_representation.promotable,
-                            promotes_to: piece_intermediate_representation.promotes_to
+                            promotes_to: piece_intermediate_representation.promotes_to,
+                            royal: piece_intermediate_representation.royal,
+                            value: piece_intermediate_representation.value,
+                            glyphs: parse_glyphs(piece_intermediate_representation.glyph)
                         };
                         output_piece_list.pieces.push(piece);
                     }
@@ -620,4 +663,31 @@ mod tests {
         }
         assert_eq!(output, "PpRrNnBbQqKk");
     }
+
+    #[test]
+    fn writes_piece_values() {
+        let path: std::path::PathBuf = std::env::temp_dir().join(format!("piecetestbed-values-{}.json", std::process::id()));
+        // The knight has a value before its name, the bishop has none, and the rook has none and no promotable either.
+        let original: &str = "{\n    \"pieces\": [\n        {\n            \"value\": 300,\n            \"name\": \"knight\",\n            \"id\": \"Nn\",\n            \"promotable\": false\n        },\n        {\n            \"name\": \"bishop\",\n            \"id\": \"Bb\",\n            \"promotable\": false,\n            \"promotes_to\": \"0\"\n        },\n        {\n            \"name\": \"rook\",\n            \"id\": \"Rr\"\n        }\n    ]\n}\n";
+        std::fs::write(&path, original).unwrap();
+        write_piece_value(&path, "knight", 320).unwrap();
+        write_piece_value(&path, "bishop", 330).unwrap();
+        write_piece_value(&path, "rook", 500).unwrap();
+        let written: String = std::fs::read_to_string(&path).unwrap();
+        assert!(write_piece_value(&path, "queen", 900).is_err());
+        std::fs::remove_file(&path).unwrap();
+        let expected: String = original
+            .replace("\"value\": 300", "\"value\": 320")
+            .replace("\"Bb\",\n", "\"Bb\",\n            \"value\": 330,\n")
+            .replace("\"Rr\"\n", "\"Rr\",\n            \"value\": 500\n");
+        assert_eq!(written, expected);
+        // The standard pieces come back out exactly as they went in.
+        let path: std::path::PathBuf = std::env::temp_dir().join(format!("piecetestbed-standard-{}.json", std::process::id()));
+        let standard: String = std::fs::read_to_string("testfiles/standardPieces.json").unwrap();
+        std::fs::write(&path, &standard).unwrap();
+        write_piece_value(&path, "pawn", 100).unwrap();
+        let written: String = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, standard);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,278 @@
+use crate::configuration::*;
+use crate::game::*;
+use crate::random::*;
+use crate::BOARDSIZE;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Something that can score a position. Scores are in centipawns from the point of view of the side to move.
+// Evaluators are shared between searches, and later between threads, so they can't hold anything mutable.
+pub trait Evaluator: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn evaluate(&self, game: &Game) -> i32;
+
+    // Rough worth of a piece, which the search uses to try the best captures first.
+    fn piece_value(&self, piece_type: &PieceType) -> i32 {
+        piece_value(piece_type)
+    }
+}
+
+// Plain material count, using the values given in pieces.json.
+// Pieces without a value there fall back on piece_value.
+pub struct Material;
+
+impl Evaluator for Material {
+    fn name(&self) -> &'static str {
+        "material"
+    }
+
+    fn evaluate(&self, game: &Game) -> i32 {
+        material(game, |piece_type| self.piece_value(piece_type))
+    }
+
+    fn piece_value(&self, piece_type: &PieceType) -> i32 {
+        if piece_type.royal {
+            return 0;
+        }
+        piece_type.value.unwrap_or_else(|| piece_value(piece_type))
+    }
+}
+
+// Material, but each PieceType is worth however many moves it had on average over a set of random games.
+// Nothing about the values comes from pieces.json, so it works the same for pieces nobody has an opinion on yet.
+pub struct AverageMobility {
+    values: HashMap<String, i32>,
+}
+
+impl AverageMobility {
+    // Play random games from the given position and count the legal moves each PieceType had whenever it was its side's turn.
+    // The game is put back the way it was afterwards.
+    pub fn from_random_games(
+        game: &mut Game,
+        games: usize,
+        plies: usize,
+        seed: u64,
+    ) -> AverageMobility {
+        let mut random: Random = Random::new(seed);
+        // Moves counted and times the PieceType was looked at, by name.
+        let mut counts: HashMap<String, (u64, u64)> = HashMap::new();
+        for _ in 0..games {
+            let mut played: usize = 0;
+            while played < plies {
+                let moves: Vec<AvailableMove> = game.scan_available_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                for piece in game
+                    .list_of_pieces_ingame
+                    .iter()
+                    .filter(|p| !p.captured && p.player == game.active_color)
+                {
+                    counts.entry(piece.piece_type.name.clone()).or_default().1 += 1;
+                }
+                for available_move in &moves {
+                    let name: &String = &game.piece(available_move.piece_id).piece_type.name;
+                    counts.entry(name.clone()).or_default().0 += 1;
+                }
+                game.make_move(moves[random.below(moves.len())]);
+                played += 1;
+            }
+            for _ in 0..played {
+                game.unmake_move();
+            }
+        }
+        // Same scale as piece_value, so the two can be compared.
+        let values: HashMap<String, i32> = counts
+            .into_iter()
+            .filter(|(_, (_, samples))| *samples > 0)
+            .map(|(name, (moves, samples))| (name, 20 + (30 * moves / samples) as i32))
+            .collect();
+        AverageMobility { values }
+    }
+
+    pub fn value_of(&self, name: &str) -> Option<i32> {
+        self.values.get(name).copied()
+    }
+}
+
+impl Evaluator for AverageMobility {
+    fn name(&self) -> &'static str {
+        "average-mobility"
+    }
+
+    fn evaluate(&self, game: &Game) -> i32 {
+        material(game, |piece_type| self.piece_value(piece_type))
+    }
+
+    fn piece_value(&self, piece_type: &PieceType) -> i32 {
+        if piece_type.royal {
+            return 0;
+        }
+        self.value_of(&piece_type.name)
+            .unwrap_or_else(|| piece_value(piece_type))
+    }
+}
+
+// No material at all: the side that attacks more squares and more enemy pieces is better off.
+// For finding out whether a PieceType needs an intrinsic value to be played sensibly.
+pub struct Mobility;
+
+impl Evaluator for Mobility {
+    fn name(&self) -> &'static str {
+        "mobility"
+    }
+
+    fn evaluate(&self, game: &Game) -> i32 {
+        let mover: char = game.active_color;
+        let ours: Vec<u8> = game.attack_bitmap(mover);
+        let theirs: Vec<u8> = game.attack_bitmap(opponent(mover));
+        let mobility: i32 = ours.iter().map(|&a| a as i32).sum::<i32>()
+            - theirs.iter().map(|&a| a as i32).sum::<i32>();
+        let mut threats: i32 = 0;
+        for piece in game.list_of_pieces_ingame.iter().filter(|p| !p.captured) {
+            if piece.player == mover && theirs[piece.position as usize] == 1 {
+                threats -= 1;
+            } else if piece.player != mover && ours[piece.position as usize] == 1 {
+                threats += 1;
+            }
+        }
+        10 * mobility + 30 * threats
+    }
+}
+
+// Pick an evaluator by the name given on the command line.
+// average-mobility learns its values from random games starting at the given game's position.
+pub fn evaluator_by_name(name: &str, game: &mut Game) -> Result<Arc<dyn Evaluator>, String> {
+    match name {
+        "material" => Ok(Arc::new(Material)),
+        "average-mobility" => Ok(Arc::new(AverageMobility::from_random_games(
+            game, 32, 100, game.hash,
+        ))),
+        "mobility" => Ok(Arc::new(Mobility)),
+        _ => Err(format!(
+            "unknown evaluator {}, expected material, average-mobility or mobility",
+            name
+        )),
+    }
+}
+
+// Sum of the values of the side to move's pieces minus the opponent's.
+fn material(game: &Game, value: impl Fn(&PieceType) -> i32) -> i32 {
+    let mut output: i32 = 0;
+    for piece in game.list_of_pieces_ingame.iter().filter(|p| !p.captured) {
+        if piece.player == game.active_color {
+            output += value(piece.piece_type);
+        } else {
+            output -= value(piece.piece_type);
+        }
+    }
+    output
+}
+
+// When nothing better is known, a piece is worth about as much as the number of squares
+// it attacks from the middle of an empty board. Royal pieces are never traded, so they count for nothing.
+pub fn piece_value(piece_type: &PieceType) -> i32 {
+    if piece_type.royal {
+        return 0;
+    }
+    let size: i32 = BOARDSIZE as i32;
+    let centre: i32 = size / 2;
+    let mut attacked: Vec<(i32, i32)> = Vec::new();
+    for m in piece_type
+        .moveset
+        .iter()
+        .filter(|m| m.captures && !m.castles && !m.enpassant)
+    {
+        for (dx, dy) in move_vectors(m) {
+            let mut k: i32 = 1;
+            loop {
+                let square: (i32, i32) = (centre + k * dx, centre + k * dy);
+                if square.0 < 0 || square.1 < 0 || square.0 >= size || square.1 >= size {
+                    break;
+                }
+                if !attacked.contains(&square) {
+                    attacked.push(square);
+                }
+                if !m.any_multiple {
+                    break;
+                }
+                k += 1;
+            }
+        }
+    }
+    20 + 30 * attacked.len() as i32
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::evaluation::*;
+    use std::path::Path;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn piece_values_follow_mobility() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let values: Vec<i32> = piece_list.pieces.iter().map(piece_value).collect();
+        // Pawn, rook, knight, bishop, queen, king.
+        assert!(values[0] < values[2] && values[2] < values[3] && values[3] < values[1]);
+        assert!(values[1] < values[4]);
+        assert_eq!(values[5], 0);
+    }
+
+    #[test]
+    fn material_uses_values_from_pieces_json() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let start: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        assert_eq!(Material.evaluate(&start), 0);
+        let queen_up: Game = Game::from_fen(
+            1,
+            "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        assert_eq!(Material.evaluate(&queen_up), -900);
+    }
+
+    #[test]
+    fn average_mobility_ranks_the_standard_pieces() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let start: u64 = game.hash;
+        let evaluator: AverageMobility = AverageMobility::from_random_games(&mut game, 4, 60, 3);
+        assert_eq!(game.hash, start);
+        let value = |name: &str| evaluator.value_of(name).unwrap();
+        assert!(value("pawn") < value("knight"));
+        assert!(value("knight") < value("queen"));
+        assert!(value("rook") < value("queen"));
+        assert_eq!(evaluator.evaluate(&game), 0);
+    }
+
+    #[test]
+    fn mobility_ignores_material() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let start: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        assert_eq!(Mobility.evaluate(&start), 0);
+        // Four pawns outweigh a bishop, but the bishop covers more of the board and hits b2.
+        let game: Game = Game::from_fen(
+            1,
+            "7k/8/8/4b3/8/8/PPPP4/K7 w - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        assert!(Material.evaluate(&game) > 0);
+        assert!(Mobility.evaluate(&game) < 0);
+    }
+}
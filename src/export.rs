@@ -0,0 +1,623 @@
+use crate::configuration::*;
+use crate::recorder::*;
+use crate::BOARDSIZE;
+use std::time::Duration;
+
+// Column names for the per-ply CSV. Scores are from the mover's side, times in milliseconds, empty means not known.
+pub const PLY_COLUMNS: [&str; 20] = [
+    "game",
+    "ply",
+    "mover",
+    "fen",
+    "san",
+    "coordinate",
+    "moveid",
+    "piece_type",
+    "captured",
+    "promotion",
+    "legal_moves",
+    "time_ms",
+    "depth",
+    "score",
+    "win_rate",
+    "pv",
+    "nodes",
+    "tt_hit_rate",
+    "white",
+    "black",
+];
+
+pub const GAME_COLUMNS: [&str; 11] = [
+    "game",
+    "white",
+    "black",
+    "start_fen",
+    "result",
+    "reason",
+    "plies",
+    "white_time_ms",
+    "black_time_ms",
+    "white_nodes",
+    "black_nodes",
+];
+
+// Numeric columns of the plies matrix in the .mat file. mover is 1 for White and -1 for Black, score_white is from White's side.
+const MAT_PLY_COLUMNS: [&str; 10] = [
+    "game",
+    "ply",
+    "mover",
+    "legal_moves",
+    "time_ms",
+    "depth",
+    "score_white",
+    "win_rate",
+    "nodes",
+    "tt_hit_rate",
+];
+
+const MAT_GAME_COLUMNS: [&str; 5] = [
+    "game",
+    "plies",
+    "white_score",
+    "white_time_ms",
+    "black_time_ms",
+];
+
+// Quoted only when it has to be.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        String::from(text)
+    }
+}
+
+fn csv_row(fields: &[String]) -> String {
+    let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+    fields.join(",") + "\n"
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map_or(String::new(), |v| v.to_string())
+}
+
+// One row per ply of every game, long format, so R's read.csv or MATLAB's readtable take it as it is.
+pub fn plies_csv(logs: &[GameLog]) -> String {
+    let mut output: String = PLY_COLUMNS.join(",") + "\n";
+    for log in logs {
+        for ply in &log.plies {
+            let engine: Option<&EngineState> = ply.engine.as_ref();
+            output.push_str(&csv_row(&[
+                log.game_id.to_string(),
+                ply.ply.to_string(),
+                ply.mover.to_string(),
+                ply.fen.clone(),
+                ply.san.clone(),
+                ply.coordinate.clone(),
+                ply.moveid.clone(),
+                ply.piece_type.clone(),
+                optional(ply.captured.as_ref()),
+                optional(ply.promotion.as_ref()),
+                ply.legal_moves.to_string(),
+                ply.time.as_millis().to_string(),
+                optional(engine.map(|e| e.depth)),
+                optional(engine.and_then(|e| e.score)),
+                optional(engine.and_then(|e| e.win_rate)),
+                engine.map_or(String::new(), |e| e.principal_variation.join(" ")),
+                optional(engine.map(|e| e.nodes)),
+                optional(engine.and_then(|e| e.tt_hit_rate)),
+                log.white.clone(),
+                log.black.clone(),
+            ]));
+        }
+    }
+    output
+}
+
+// One row per game.
+pub fn games_csv(logs: &[GameLog]) -> String {
+    let mut output: String = GAME_COLUMNS.join(",") + "\n";
+    for log in logs {
+        let total = |player: char, value: &dyn Fn(&PlyRecord) -> u64| -> u64 {
+            log.plies
+                .iter()
+                .filter(|p| p.mover == player)
+                .map(value)
+                .sum()
+        };
+        let time = |p: &PlyRecord| p.time.as_millis() as u64;
+        let nodes = |p: &PlyRecord| p.engine.as_ref().map_or(0, |e| e.nodes);
+        output.push_str(&csv_row(&[
+            log.game_id.to_string(),
+            log.white.clone(),
+            log.black.clone(),
+            log.start_fen.clone(),
+            optional(log.result.as_ref()),
+            optional(log.reason.as_ref()),
+            log.plies.len().to_string(),
+            total('w', &time).to_string(),
+            total('b', &time).to_string(),
+            total('w', &nodes).to_string(),
+            total('b', &nodes).to_string(),
+        ]));
+    }
+    output
+}
+
+// The piece placement of a FEN as one number per square, a8 first: 0 for empty,
+// otherwise the PieceType's place in the piece list counting from 1, negative for Black.
+pub fn board_codes(fen: &str, piece_list: &PieceList) -> Vec<f64> {
+    let mut output: Vec<f64> = Vec::new();
+    let mut empty: String = String::new();
+    for c in fen.split(' ').next().unwrap_or("").chars() {
+        if c.is_ascii_digit() {
+            empty.push(c);
+            continue;
+        }
+        // Runs of empty squares can be more than one digit on big boards.
+        let run: usize = empty.parse().unwrap_or(0);
+        output.extend(std::iter::repeat_n(0.0, run));
+        empty.clear();
+        if c == '/' {
+            continue;
+        }
+        let code: f64 = piece_list
+            .pieces
+            .iter()
+            .position(|p| p.white_id == c || p.black_id == c)
+            .map_or(0.0, |index| {
+                let number: f64 = (index + 1) as f64;
+                if piece_list.pieces[index].white_id == c {
+                    number
+                } else {
+                    -number
+                }
+            });
+        output.push(code);
+    }
+    output.extend(std::iter::repeat_n(0.0, empty.parse().unwrap_or(0)));
+    output.resize(BOARDSIZE as usize * BOARDSIZE as usize, 0.0);
+    output
+}
+
+// Everything numeric in a MAT-file (level 5) that MATLAB's load or R's R.matlab::readMat can open:
+//   plies         one row per ply, columns named in ply_columns
+//   board         one row per ply, the 64 squares from board_codes before the move
+//   evaluation    White's score before each move in centipawns, NaN when no engine gave one
+//   piece_counts  one row per ply, how many of each piece type White then Black has, named in piece_count_columns
+//   piece_types   the names board codes refer to, in order
+//   games         one row per game, columns named in game_columns. white_score is 1, 0.5 or 0, NaN if unfinished
+pub fn games_mat(logs: &[GameLog], piece_list: &PieceList) -> Vec<u8> {
+    let mut plies: Vec<Vec<f64>> = Vec::new();
+    let mut board: Vec<Vec<f64>> = Vec::new();
+    let mut evaluation: Vec<Vec<f64>> = Vec::new();
+    let mut piece_counts: Vec<Vec<f64>> = Vec::new();
+    let types: usize = piece_list.pieces.len();
+    for log in logs {
+        for ply in &log.plies {
+            let engine: Option<&EngineState> = ply.engine.as_ref();
+            let side: f64 = if ply.mover == 'w' { 1.0 } else { -1.0 };
+            let score_white: f64 = engine
+                .and_then(|e| e.score)
+                .map_or(f64::NAN, |s| s as f64 * side);
+            plies.push(vec![
+                log.game_id as f64,
+                ply.ply as f64,
+                side,
+                ply.legal_moves as f64,
+                ply.time.as_secs_f64() * 1000.0,
+                engine.map_or(f64::NAN, |e| e.depth as f64),
+                score_white,
+                engine.and_then(|e| e.win_rate).unwrap_or(f64::NAN),
+                engine.map_or(f64::NAN, |e| e.nodes as f64),
+                engine.and_then(|e| e.tt_hit_rate).unwrap_or(f64::NAN),
+            ]);
+            let codes: Vec<f64> = board_codes(&ply.fen, piece_list);
+            let mut counts: Vec<f64> = vec![0.0; types * 2];
+            for &code in &codes {
+                if code > 0.0 {
+                    counts[code as usize - 1] += 1.0;
+                } else if code < 0.0 {
+                    counts[types + (-code) as usize - 1] += 1.0;
+                }
+            }
+            board.push(codes);
+            evaluation.push(vec![score_white]);
+            piece_counts.push(counts);
+        }
+    }
+    let games: Vec<Vec<f64>> = logs
+        .iter()
+        .map(|log| {
+            let time_used = |player: char| -> f64 {
+                log.plies
+                    .iter()
+                    .filter(|p| p.mover == player)
+                    .map(|p| p.time.as_secs_f64() * 1000.0)
+                    .sum()
+            };
+            vec![
+                log.game_id as f64,
+                log.plies.len() as f64,
+                match log.result.as_deref() {
+                    Some("1-0") => 1.0,
+                    Some("0-1") => 0.0,
+                    Some("1/2-1/2") => 0.5,
+                    _ => f64::NAN,
+                },
+                time_used('w'),
+                time_used('b'),
+            ]
+        })
+        .collect();
+    let names: Vec<String> = piece_list.pieces.iter().map(|p| p.name.clone()).collect();
+    let mut count_columns: Vec<String> = names.iter().map(|n| format!("white_{}", n)).collect();
+    count_columns.extend(names.iter().map(|n| format!("black_{}", n)));
+    let mut file: MatFile = MatFile::new();
+    file.add_matrix("plies", &plies, MAT_PLY_COLUMNS.len());
+    file.add_text("ply_columns", &MAT_PLY_COLUMNS.map(String::from));
+    file.add_matrix("board", &board, BOARDSIZE as usize * BOARDSIZE as usize);
+    file.add_matrix("evaluation", &evaluation, 1);
+    file.add_matrix("piece_counts", &piece_counts, types * 2);
+    file.add_text("piece_count_columns", &count_columns);
+    file.add_text("piece_types", &names);
+    file.add_matrix("games", &games, MAT_GAME_COLUMNS.len());
+    file.add_text("game_columns", &MAT_GAME_COLUMNS.map(String::from));
+    file.bytes
+}
+
+// Writes prefix.csv, prefix-games.csv and prefix.mat, returning their names.
+pub fn export_logs(
+    logs: &[GameLog],
+    piece_list: &PieceList,
+    prefix: &str,
+) -> std::io::Result<Vec<String>> {
+    let files: Vec<(String, Vec<u8>)> = vec![
+        (format!("{}.csv", prefix), plies_csv(logs).into_bytes()),
+        (
+            format!("{}-games.csv", prefix),
+            games_csv(logs).into_bytes(),
+        ),
+        (format!("{}.mat", prefix), games_mat(logs, piece_list)),
+    ];
+    for (name, contents) in &files {
+        std::fs::write(name, contents)?;
+    }
+    Ok(files.into_iter().map(|(name, _)| name).collect())
+}
+
+// Rows of a CSV file as written above: fields quoted when they have commas, quotes or newlines in them.
+pub fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut row: Vec<String> = Vec::new();
+    let mut field: String = String::new();
+    let mut quoted: bool = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => row.push(std::mem::take(&mut field)),
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            '\r' if !quoted => {}
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+// Reads back the logs in a per-ply CSV, with results from the matching per-game CSV if there is one.
+// Columns are found by name, so files from older versions with fewer columns still load.
+pub fn logs_from_csv(plies: &str, games: Option<&str>) -> Result<Vec<GameLog>, String> {
+    let rows: Vec<Vec<String>> = parse_csv(plies);
+    let header: &Vec<String> = rows.first().ok_or("the file is empty")?;
+    let column = |name: &str| header.iter().position(|h| h == name);
+    for required in ["game", "mover", "fen", "coordinate"] {
+        if column(required).is_none() {
+            return Err(format!("there's no {} column", required));
+        }
+    }
+    let mut logs: Vec<GameLog> = Vec::new();
+    for (line, row) in rows.iter().enumerate().skip(1) {
+        let field = |name: &str| -> &str {
+            column(name)
+                .and_then(|i| row.get(i))
+                .map_or("", |f| f.as_str())
+        };
+        let text = |name: &str| -> Option<String> {
+            Some(field(name))
+                .filter(|f| !f.is_empty())
+                .map(String::from)
+        };
+        let game_id: u64 = field("game")
+            .parse()
+            .map_err(|_| format!("line {}: bad game number", line + 1))?;
+        // A new game starts whenever the number changes or the plies start again.
+        if field("ply") == "1" || logs.last().is_none_or(|log| log.game_id != game_id) {
+            logs.push(GameLog {
+                game_id,
+                white: field("white").to_string(),
+                black: field("black").to_string(),
+                start_fen: field("fen").to_string(),
+                ..GameLog::default()
+            });
+        }
+        let log: &mut GameLog = logs.last_mut().unwrap();
+        let engine: Option<EngineState> = field("depth").parse().ok().map(|depth| EngineState {
+            depth,
+            score: field("score").parse().ok(),
+            win_rate: field("win_rate").parse().ok(),
+            principal_variation: field("pv").split_whitespace().map(String::from).collect(),
+            nodes: field("nodes").parse().unwrap_or(0),
+            tt_hit_rate: field("tt_hit_rate").parse().ok(),
+        });
+        log.plies.push(PlyRecord {
+            ply: field("ply").parse().unwrap_or(log.plies.len() + 1),
+            fen: field("fen").to_string(),
+            mover: field("mover").chars().next().unwrap_or('w'),
+            san: field("san").to_string(),
+            coordinate: field("coordinate").to_string(),
+            moveid: field("moveid").to_string(),
+            piece_type: field("piece_type").to_string(),
+            captured: text("captured"),
+            promotion: text("promotion"),
+            legal_moves: field("legal_moves").parse().unwrap_or(0),
+            time: Duration::from_millis(field("time_ms").parse().unwrap_or(0)),
+            engine,
+        });
+    }
+    let rows: Vec<Vec<String>> = games.map(parse_csv).unwrap_or_default();
+    if let Some(header) = rows.first() {
+        let column = |name: &str| header.iter().position(|h| h == name);
+        for row in &rows[1..] {
+            let field = |name: &str| -> Option<String> {
+                column(name)
+                    .and_then(|i| row.get(i))
+                    .filter(|f| !f.is_empty())
+                    .cloned()
+            };
+            let game_id: Option<u64> = field("game").and_then(|g| g.parse().ok());
+            for log in logs.iter_mut().filter(|log| Some(log.game_id) == game_id) {
+                log.result = field("result");
+                log.reason = field("reason");
+            }
+        }
+    }
+    Ok(logs)
+}
+
+// MAT-file data types and array classes, from MathWorks' "MAT-File Format" document.
+const MI_INT8: u32 = 1;
+const MI_UINT16: u32 = 4;
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_DOUBLE: u32 = 9;
+const MI_MATRIX: u32 = 14;
+const MX_CHAR_CLASS: u32 = 4;
+const MX_DOUBLE_CLASS: u32 = 6;
+
+// A little-endian level 5 MAT-file built up in memory, one variable at a time.
+struct MatFile {
+    bytes: Vec<u8>,
+}
+
+impl MatFile {
+    fn new() -> MatFile {
+        let mut header: Vec<u8> = format!(
+            "MATLAB 5.0 MAT-file, Platform: {}, Created by: piecetestbed",
+            std::env::consts::OS
+        )
+        .into_bytes();
+        header.resize(116, b' ');
+        // No subsystem data, version 0x0100, and IM to say the file is little-endian.
+        header.extend([0; 8]);
+        header.extend(0x0100u16.to_le_bytes());
+        header.extend(b"IM");
+        MatFile { bytes: header }
+    }
+
+    // A tag then the data, padded out to a multiple of 8 bytes.
+    fn element(data_type: u32, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::new();
+        output.extend(data_type.to_le_bytes());
+        output.extend((data.len() as u32).to_le_bytes());
+        output.extend(data);
+        output.resize(output.len().next_multiple_of(8), 0);
+        output
+    }
+
+    fn add_array(&mut self, name: &str, class: u32, rows: usize, columns: usize, data: Vec<u8>) {
+        let mut contents: Vec<u8> = Vec::new();
+        let flags: Vec<u8> = [class, 0].iter().flat_map(|w| w.to_le_bytes()).collect();
+        contents.extend(MatFile::element(MI_UINT32, &flags));
+        let dimensions: Vec<u8> = [rows as i32, columns as i32]
+            .iter()
+            .flat_map(|d| d.to_le_bytes())
+            .collect();
+        contents.extend(MatFile::element(MI_INT32, &dimensions));
+        contents.extend(MatFile::element(MI_INT8, name.as_bytes()));
+        contents.extend(MatFile::element(
+            if class == MX_CHAR_CLASS {
+                MI_UINT16
+            } else {
+                MI_DOUBLE
+            },
+            &data,
+        ));
+        self.bytes.extend(MatFile::element(MI_MATRIX, &contents));
+    }
+
+    // Rows of numbers. MATLAB stores matrices a column at a time.
+    fn add_matrix(&mut self, name: &str, rows: &[Vec<f64>], columns: usize) {
+        let mut data: Vec<u8> = Vec::new();
+        for column in 0..columns {
+            for row in rows {
+                data.extend(row[column].to_le_bytes());
+            }
+        }
+        self.add_array(name, MX_DOUBLE_CLASS, rows.len(), columns, data);
+    }
+
+    // Strings as the rows of a char matrix, padded with spaces like MATLAB's char() does.
+    fn add_text(&mut self, name: &str, lines: &[String]) {
+        let lines: Vec<Vec<u16>> = lines.iter().map(|l| l.encode_utf16().collect()).collect();
+        let width: usize = lines.iter().map(|l| l.len()).max().unwrap_or(0);
+        let mut data: Vec<u8> = Vec::new();
+        for column in 0..width {
+            for line in &lines {
+                data.extend(
+                    line.get(column)
+                        .copied()
+                        .unwrap_or(b' ' as u16)
+                        .to_le_bytes(),
+                );
+            }
+        }
+        self.add_array(name, MX_CHAR_CLASS, lines.len(), width, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::export::*;
+    use std::path::Path;
+    use std::time::Duration;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn sample_log() -> GameLog {
+        let ply =
+            |number: usize, mover: char, fen: &str, san: &str, engine: Option<EngineState>| {
+                PlyRecord {
+                    ply: number,
+                    fen: String::from(fen),
+                    mover,
+                    san: String::from(san),
+                    coordinate: String::from("e2e4"),
+                    moveid: String::from("01..!"),
+                    piece_type: String::from("pawn"),
+                    captured: None,
+                    promotion: None,
+                    legal_moves: 20,
+                    time: Duration::from_millis(15),
+                    engine,
+                }
+            };
+        GameLog {
+            game_id: 7,
+            white: String::from("engine:depth=2"),
+            black: String::from("random, seeded"),
+            start_fen: String::from(START),
+            plies: vec![
+                ply(
+                    1,
+                    'w',
+                    START,
+                    "e4",
+                    Some(EngineState {
+                        depth: 2,
+                        score: Some(30),
+                        win_rate: None,
+                        principal_variation: vec![String::from("e4"), String::from("e5")],
+                        nodes: 100,
+                        tt_hit_rate: Some(0.25),
+                    }),
+                ),
+                ply(
+                    2,
+                    'b',
+                    "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                    "e5",
+                    None,
+                ),
+            ],
+            result: Some(String::from("1-0")),
+            reason: Some(String::from("Black resigns")),
+        }
+    }
+
+    #[test]
+    fn writes_csv() {
+        let csv: String = plies_csv(&[sample_log()]);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], PLY_COLUMNS.join(","));
+        assert_eq!(
+            lines[1],
+            format!("7,1,w,{},e4,e2e4,01..!,pawn,,,20,15,2,30,,e4 e5,100,0.25,engine:depth=2,\"random, seeded\"", START)
+        );
+        assert!(lines[2].starts_with("7,2,b,"));
+        assert_eq!(lines[2].split(',').count(), PLY_COLUMNS.len() + 1);
+        let games: String = games_csv(&[sample_log()]);
+        assert_eq!(
+            games.lines().nth(1),
+            Some(
+                format!(
+                    "7,engine:depth=2,\"random, seeded\",{},1-0,Black resigns,2,15,15,100,0",
+                    START
+                )
+                .as_str()
+            )
+        );
+    }
+
+    #[test]
+    fn writes_mat() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let codes: Vec<f64> = board_codes(START, &piece_list);
+        assert_eq!(codes.len(), 64);
+        assert_eq!(codes[16..48], [0.0; 32]);
+        let pawn: f64 = (piece_list
+            .pieces
+            .iter()
+            .position(|p| p.name == "pawn")
+            .unwrap()
+            + 1) as f64;
+        assert_eq!((codes[8], codes[48]), (-pawn, pawn));
+        let bytes: Vec<u8> = games_mat(&[sample_log()], &piece_list);
+        assert!(bytes.starts_with(b"MATLAB 5.0 MAT-file"));
+        assert_eq!(&bytes[124..128], &[0x00, 0x01, b'I', b'M']);
+        // Walk the variables: each is a miMATRIX holding flags, dimensions, name and data.
+        let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+        let mut at: usize = 128;
+        let mut variables: Vec<(String, usize, usize)> = Vec::new();
+        while at < bytes.len() {
+            assert_eq!(word(at), MI_MATRIX as usize);
+            let size: usize = word(at + 4);
+            let rows: usize = word(at + 8 + 16 + 8);
+            let columns: usize = word(at + 8 + 16 + 12);
+            let name_length: usize = word(at + 8 + 32 + 4);
+            let name: String =
+                String::from_utf8(bytes[at + 48..at + 48 + name_length].to_vec()).unwrap();
+            variables.push((name, rows, columns));
+            at += 8 + size;
+        }
+        assert_eq!(at, bytes.len());
+        let types: usize = piece_list.pieces.len();
+        assert_eq!(
+            variables,
+            vec![
+                (String::from("plies"), 2, 10),
+                (String::from("ply_columns"), 10, 11),
+                (String::from("board"), 2, 64),
+                (String::from("evaluation"), 2, 1),
+                (String::from("piece_counts"), 2, types * 2),
+                (String::from("piece_count_columns"), types * 2, 12),
+                (String::from("piece_types"), types, 6),
+                (String::from("games"), 1, 5),
+                (String::from("game_columns"), 5, 13),
+            ]
+        );
+    }
+}
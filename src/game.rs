@@ -1,7 +1,101 @@
+use crate::clock::*;
 use crate::configuration::*;
+use crate::zobrist::*;
+use crate::BOARDSIZE;
 use std::collections::HashMap;
 
+// Placed into a piece's list_of_moves when a FEN tells us it has moved, but not how.
+pub const SETUP_MOVE: &str = "FEN";
+
+// A move found by scan_available_moves, with everything needed to play it and take it back.
+#[derive(Debug, Clone, Copy)]
+pub struct AvailableMove<'a> {
+    pub piece_id: u8,
+    pub from: u8,
+    pub to: u8,
+    // The Move from the piece's moveset (and so the MOVEID) that allowed this.
+    pub move_type: &'a Move,
+    pub captured_piece_id: Option<u8>,
+    // Symbol of the piece being promoted to, if any.
+    pub promotion: Option<char>,
+    // Castling-like moves drag a second piece along: its id, where it starts and where it ends up.
+    pub castle_target: Option<(u8, u8, u8)>,
+}
+
+impl<'a> AvailableMove<'a> {
+    // Coordinate notation as used by UCI and most perft tools, e.g. e2e4 or e7e8q.
+    pub fn coordinate_notation(&self) -> String {
+        let mut output: String = format!("{}{}", square_name(self.from), square_name(self.to));
+        if let Some(symbol) = self.promotion {
+            output.push(symbol.to_ascii_lowercase());
+        }
+        output
+    }
+
+    // Enough to tell two moves apart in the same position, without holding on to the Move.
+    pub fn key(&self) -> (u8, u8, Option<char>) {
+        (self.from, self.to, self.promotion)
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.move_type.captures && self.captured_piece_id.is_some()
+    }
+}
+
+// A played move plus whatever make_move overwrote, so that unmake_move can put it back.
+#[derive(Debug, Clone)]
+pub struct MoveRecord<'a> {
+    pub played: AvailableMove<'a>,
+    pub previous_white_check: bool,
+    pub previous_black_check: bool,
+    pub previous_halfmove_clock: u32,
+    pub previous_has_castled: bool,
+    pub promoted_from: Option<(char, &'a PieceType)>,
+    pub previous_hash: u64,
+}
+
+// How a game ended, by the rules alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    // The winner, 'w' or 'b'.
+    Checkmate(char),
+    Stalemate,
+    FiftyMoves,
+    Repetition,
+    // The winner, when the other side's flag fell.
+    Timeout(char),
+    // A flag fell but the other side had nothing left to mate with.
+    TimeoutDraw,
+}
+
+impl Outcome {
+    // As written at the end of a PGN.
+    pub fn result(&self) -> &'static str {
+        match self {
+            Outcome::Checkmate('w') => "1-0",
+            Outcome::Checkmate(_) => "0-1",
+            Outcome::Timeout('w') => "1-0",
+            Outcome::Timeout(_) => "0-1",
+            _ => "1/2-1/2",
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Outcome::Checkmate('w') => "White mates",
+            Outcome::Checkmate(_) => "Black mates",
+            Outcome::Stalemate => "Stalemate",
+            Outcome::FiftyMoves => "Fifty move rule",
+            Outcome::Repetition => "Threefold repetition",
+            Outcome::Timeout('w') => "Black ran out of time",
+            Outcome::Timeout(_) => "White ran out of time",
+            Outcome::TimeoutDraw => "Out of time, but the other side can't mate",
+        }
+    }
+}
+
 // Game Data Structure
+#[derive(Clone)]
 pub struct Game<'a> {
     pub id: u64,
     pub active_color: char,
@@ -9,7 +103,32 @@ pub struct Game<'a> {
     pub black_check: bool,
     pub position: Vec<u8>,
     pub list_of_pieces_ingame: Vec<Piece<'a>>,
-    pub list_of_moves: Vec<(u8, String)>,
+    pub list_of_moves: Vec<MoveRecord<'a>>,
+    // How many records at the start of list_of_moves were made up from a FEN rather than played.
+    pub setup_moves: usize,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    pub piece_symbol_map: HashMap<char, &'a PieceType>,
+    // Zobrist hash of the position, kept up to date by make_move and unmake_move.
+    pub hash: u64,
+    // Ids of the pieces each player has taken, in the order they were taken.
+    pub white_captures: Vec<u8>,
+    pub black_captures: Vec<u8>,
+    // None for untimed games.
+    pub clocks: Option<Clocks>,
+}
+
+// Where a castling-like move stands for one royal piece.
+#[derive(Debug, Clone, Copy)]
+pub struct CastlingStatus<'a> {
+    pub royal_id: u8,
+    pub move_type: &'a Move,
+    // FEN letter for the side the target piece is on, K or Q, lower case for black.
+    pub letter: char,
+    // Neither piece has moved, so the right is still there.
+    pub available: bool,
+    // And nothing stops it being played right now.
+    pub playable: bool,
 }
 
 impl<'a> Game<'a> {
@@ -19,39 +138,56 @@ impl<'a> Game<'a> {
         piece_hashmap: HashMap<char, &'a PieceType>,
         position_hashmap: HashMap<String, String>,
     ) -> Game<'a> {
+        let fen: &String = position_hashmap
+            .get(&starting_position_key)
+            .expect("No starting position with that name in startingPositions.json");
+        Game::from_fen(game_id, fen, fen, piece_hashmap).unwrap()
+    }
+
+    // Set up a game from a FEN. Only the piece placement is required, the other fields default to a fresh game.
+    // Pieces standing where starting_fen has a piece of the same symbol count as not having moved yet.
+    pub fn from_fen(
+        game_id: u64,
+        fen: &str,
+        starting_fen: &str,
+        piece_hashmap: HashMap<char, &'a PieceType>,
+    ) -> Result<Game<'a>, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.is_empty() {
+            return Err(String::from("Empty FEN"));
+        }
+        let board: Vec<Option<char>> = parse_fen_board(fields[0])?;
+        let starting_board: Vec<Option<char>> =
+            parse_fen_board(starting_fen.split_whitespace().next().unwrap_or(""))?;
         let mut piece_counter: u8 = 1;
-        // Check that all the IDs are found in the piece hashmap, otherwise give up lol
         // Hold the pieces and collect them.
         let mut temp_pieces: Vec<Piece> = Vec::new();
         // Position vector as a list of 64 numbers.
         let mut position_vector: Vec<u8> = Vec::new();
-        // Fill in empty spaces in FEN notation.
-        for i in position_hashmap
-            .get(&starting_position_key)
-            .unwrap()
-            .chars()
-        {
-            // println!("{}", i.clone()); This helps to debug.
-            match i {
-                '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => {
-                    let mut c: u32 = 0;
-                    while c < i.to_digit(10).unwrap() {
-                        position_vector.push(0);
-                        c += 1;
-                    }
-                }
-                // Ignore slashes in FEN notation.
-                '/' => {}
+        for (square, contents) in board.iter().enumerate() {
+            match contents {
+                None => position_vector.push(0),
                 // Place pieces into the position vector as their IDs, place them into the piece collection, and increment the piece counter.
-                a => {
+                Some(a) => {
+                    let piece_type: &PieceType = match piece_hashmap.get(a) {
+                        Some(piece_type) => piece_type,
+                        None => {
+                            return Err(format!("No piece in pieces.json uses the symbol {}", a))
+                        }
+                    };
                     let p: Piece = Piece {
                         id: piece_counter,
-                        position: piece_counter - 1,
+                        position: square as u8,
                         player: if a.is_uppercase() { 'w' } else { 'b' },
-                        symbol: a,
-                        piece_type: piece_hashmap.get(&a).unwrap(),
+                        symbol: *a,
+                        piece_type,
                         has_castled: false,
-                        list_of_moves: Vec::new(),
+                        captured: false,
+                        list_of_moves: if starting_board.get(square) == Some(contents) {
+                            Vec::new()
+                        } else {
+                            vec![String::from(SETUP_MOVE)]
+                        },
                     };
                     piece_counter += 1;
                     position_vector.push(p.id);
@@ -59,22 +195,237 @@ impl<'a> Game<'a> {
                 }
             }
         }
-        let game: Game = Game {
+        let mut game: Game = Game {
             id: game_id,
-            active_color: 'w',
+            active_color: match fields.get(1) {
+                None | Some(&"w") => 'w',
+                Some(&"b") => 'b',
+                Some(other) => return Err(format!("Unknown side to move {}", other)),
+            },
             white_check: false,
             black_check: false,
             position: position_vector,
             list_of_pieces_ingame: temp_pieces,
             list_of_moves: Vec::new(),
+            setup_moves: 0,
+            halfmove_clock: fields.get(4).and_then(|n| n.parse().ok()).unwrap_or(0),
+            fullmove_number: fields.get(5).and_then(|n| n.parse().ok()).unwrap_or(1),
+            piece_symbol_map: piece_hashmap,
+            hash: 0,
+            white_captures: Vec::new(),
+            black_captures: Vec::new(),
+            clocks: None,
+        };
+        if let Some(castling) = fields.get(2) {
+            game.apply_fen_castling(castling);
+        }
+        if let Some(enpassant) = fields.get(3) {
+            if *enpassant != "-" {
+                match parse_square(enpassant) {
+                    Some(square) => game.apply_fen_enpassant(square),
+                    None => return Err(format!("Invalid en passant square {}", enpassant)),
+                }
+            }
+        }
+        game.white_check = game.royal_in_check('w');
+        game.black_check = game.royal_in_check('b');
+        game.hash = game.compute_hash();
+        Ok(game)
+    }
+
+    // FEN castling letters only say which side of the royal piece the target is on: K for the right, Q for the left.
+    fn apply_fen_castling(&mut self, castling: &str) {
+        for index in 0..self.list_of_pieces_ingame.len() {
+            let royal: &Piece = &self.list_of_pieces_ingame[index];
+            let piece_type: &'a PieceType = royal.piece_type;
+            if !piece_type.royal {
+                continue;
+            }
+            for m in piece_type.moveset.iter().filter(|m| m.castles) {
+                let royal: &Piece = &self.list_of_pieces_ingame[index];
+                let target_square: u8 = match self.relative_square(
+                    royal.position,
+                    m.castle_target_piece_relative_location,
+                    royal.player,
+                ) {
+                    Some(square) => square,
+                    None => continue,
+                };
+                let mut letter: char = if target_square % BOARDSIZE > royal.position % BOARDSIZE {
+                    'K'
+                } else {
+                    'Q'
+                };
+                if royal.player == 'b' {
+                    letter = letter.to_ascii_lowercase();
+                }
+                let target_id: u8 = self.position[target_square as usize];
+                if castling.contains(letter) && target_id != 0 {
+                    // The FEN knows better than our guess from the starting position.
+                    for id in [royal.id, target_id] {
+                        self.list_of_pieces_ingame[piece_index(id)]
+                            .list_of_moves
+                            .retain(|s| s != SETUP_MOVE);
+                    }
+                } else if !castling.contains(letter) {
+                    if m.castle_target_piece_cannot_move && target_id != 0 {
+                        let target: &mut Piece =
+                            &mut self.list_of_pieces_ingame[piece_index(target_id)];
+                        if target.list_of_moves.is_empty() {
+                            target.list_of_moves.push(String::from(SETUP_MOVE));
+                        }
+                    } else {
+                        self.list_of_pieces_ingame[index].has_castled = true;
+                    }
+                }
+            }
+        }
+    }
+
+    // The position as a FEN, the other way round from from_fen.
+    pub fn to_fen(&self) -> String {
+        let mut board: String = String::new();
+        for row in 0..BOARDSIZE {
+            let mut empty: u8 = 0;
+            for column in 0..BOARDSIZE {
+                let id: u8 = self.position[(row * BOARDSIZE + column) as usize];
+                if id == 0 {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    board.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                board.push(self.piece(id).symbol);
+            }
+            if empty > 0 {
+                board.push_str(&empty.to_string());
+            }
+            if row + 1 < BOARDSIZE {
+                board.push('/');
+            }
+        }
+        let mut castling: Vec<char> = Vec::new();
+        for (royal_id, m) in self.castling_rights() {
+            if let Some(letter) = self.castling_letter(royal_id, m) {
+                castling.push(letter);
+            }
+        }
+        castling.sort_by_key(|c| (c.is_lowercase(), *c));
+        castling.dedup();
+        let castling: String = if castling.is_empty() {
+            String::from("-")
+        } else {
+            castling.into_iter().collect()
+        };
+        // The square the last move passed over, if it was one an en passant-like move waits for.
+        let enpassant: String = match self.list_of_moves.last() {
+            Some(record) if self.opens_enpassant() => {
+                match squares_between(record.played.from, record.played.to).first() {
+                    Some(square) => square_name(*square),
+                    None => String::from("-"),
+                }
+            }
+            _ => String::from("-"),
+        };
+        format!(
+            "{} {} {} {} {} {}",
+            board,
+            self.active_color,
+            castling,
+            enpassant,
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+
+    // FEN letter for a castling-like move: K if the target piece is to the right of the royal piece, Q if to the left.
+    fn castling_letter(&self, royal_id: u8, m: &Move) -> Option<char> {
+        let royal: &Piece = self.piece(royal_id);
+        let target_square: u8 = self.relative_square(
+            royal.position,
+            m.castle_target_piece_relative_location,
+            royal.player,
+        )?;
+        let letter: char = if target_square % BOARDSIZE > royal.position % BOARDSIZE {
+            'K'
+        } else {
+            'Q'
         };
-        game
+        Some(if royal.player == 'b' {
+            letter.to_ascii_lowercase()
+        } else {
+            letter
+        })
     }
 
-    pub fn map_pieces_to_ids(&self) -> HashMap<u8, &Piece> {
+    // A FEN en passant square means the last move passed over it. Work out which move that was and record it.
+    fn apply_fen_enpassant(&mut self, square: u8) {
+        let mut enpassant_previous_moves: Vec<&String> = Vec::new();
+        for piece_type in self.piece_symbol_map.values() {
+            for m in &piece_type.moveset {
+                if let Some(previous) = &m.enpassant_target_piece_previous_move {
+                    enpassant_previous_moves.push(previous);
+                }
+            }
+        }
+        let last_mover: char = opponent(self.active_color);
+        let mut found: Option<AvailableMove<'a>> = None;
+        for piece in &self.list_of_pieces_ingame {
+            if piece.captured || piece.player != last_mover {
+                continue;
+            }
+            let piece_type: &'a PieceType = piece.piece_type;
+            for m in &piece_type.moveset {
+                if !enpassant_previous_moves.contains(&&m.id) {
+                    continue;
+                }
+                for (dx, dy) in move_vectors(m) {
+                    if let Some(origin) = self.offset_square(piece.position, -dx, -dy, piece.player)
+                    {
+                        if self.position[origin as usize] == 0
+                            && squares_between(origin, piece.position).contains(&square)
+                        {
+                            found = Some(AvailableMove {
+                                piece_id: piece.id,
+                                from: origin,
+                                to: piece.position,
+                                move_type: m,
+                                captured_piece_id: None,
+                                promotion: None,
+                                castle_target: None,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(played) = found {
+            self.list_of_pieces_ingame[piece_index(played.piece_id)]
+                .list_of_moves
+                .push(played.move_type.id.clone());
+            self.list_of_moves.push(MoveRecord {
+                played,
+                previous_white_check: false,
+                previous_black_check: false,
+                previous_halfmove_clock: 0,
+                previous_has_castled: false,
+                promoted_from: None,
+                previous_hash: 0,
+            });
+            self.setup_moves = self.list_of_moves.len();
+        }
+    }
+
+    pub fn piece(&self, id: u8) -> &Piece<'a> {
+        &self.list_of_pieces_ingame[piece_index(id)]
+    }
+
+    pub fn map_pieces_to_ids(&self) -> HashMap<u8, &Piece<'a>> {
         let mut output: HashMap<u8, &Piece> = HashMap::new();
         for i in &self.list_of_pieces_ingame {
-            output.insert(i.id, &i);
+            output.insert(i.id, i);
         }
         // for i in &game.list_of_pieces_ingame {
         // println!("{}", i.id);
@@ -84,260 +435,879 @@ impl<'a> Game<'a> {
         output
     }
 
-    pub fn print_piece_id_map(&self) {
-        println!("Piece ID Map");
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            self.position[0],
-            self.position[1],
-            self.position[2],
-            self.position[3],
-            self.position[4],
-            self.position[5],
-            self.position[6],
-            self.position[7]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            self.position[8],
-            self.position[9],
-            self.position[10],
-            self.position[11],
-            self.position[12],
-            self.position[13],
-            self.position[14],
-            self.position[15]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            self.position[16],
-            self.position[17],
-            self.position[18],
-            self.position[19],
-            self.position[20],
-            self.position[21],
-            self.position[22],
-            self.position[23]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            self.position[24],
-            self.position[25],
-            self.position[26],
-            self.position[27],
-            self.position[27],
-            self.position[28],
-            self.position[29],
-            self.position[30]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            self.position[32],
-            self.position[33],
-            self.position[34],
-            self.position[35],
-            self.position[36],
-            self.position[37],
-            self.position[38],
-            self.position[39]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            self.position[40],
-            self.position[41],
-            self.position[42],
-            self.position[43],
-            self.position[44],
-            self.position[45],
-            self.position[46],
-            self.position[47]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            self.position[48],
-            self.position[49],
-            self.position[50],
-            self.position[51],
-            self.position[52],
-            self.position[53],
-            self.position[54],
-            self.position[55]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            self.position[56],
-            self.position[57],
-            self.position[58],
-            self.position[59],
-            self.position[60],
-            self.position[61],
-            self.position[62],
-            self.position[63]
-        );
-    }
-
-    pub fn print_piece_symbol_map(&self) {
-        let temp = self.map_pieces_to_ids();
-        println!("Current Board");
-        let mut output: Vec<char> = Vec::new();
-        for i in &self.position {
-            if *i != 0 {
-                output.push(temp.get(i).unwrap().symbol);
-            } else {
-                output.push('0');
+    // GENERATE MOVES
+    // Every legal move for the side to move. Pseudo-legal moves are played out and dropped if they leave a royal piece in check.
+    pub fn scan_available_moves(&mut self) -> Vec<AvailableMove<'a>> {
+        let mover: char = self.active_color;
+        let mut output: Vec<AvailableMove<'a>> = Vec::new();
+        for available_move in self.scan_pseudo_legal_moves() {
+            self.apply_move(available_move);
+            if !self.royal_in_check(mover) {
+                output.push(available_move);
             }
+            self.unmake_move();
         }
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            output[0], output[1], output[2], output[3], output[4], output[5], output[6], output[7]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            output[8],
-            output[9],
-            output[10],
-            output[11],
-            output[12],
-            output[13],
-            output[14],
-            output[15]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            output[16],
-            output[17],
-            output[18],
-            output[19],
-            output[20],
-            output[21],
-            output[22],
-            output[23]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            output[24],
-            output[25],
-            output[26],
-            output[27],
-            output[27],
-            output[28],
-            output[29],
-            output[30]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            output[32],
-            output[33],
-            output[34],
-            output[35],
-            output[36],
-            output[37],
-            output[38],
-            output[39]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            output[40],
-            output[41],
-            output[42],
-            output[43],
-            output[44],
-            output[45],
-            output[46],
-            output[47]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            output[48],
-            output[49],
-            output[50],
-            output[51],
-            output[52],
-            output[53],
-            output[54],
-            output[55]
-        );
-        println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}",
-            output[56],
-            output[57],
-            output[58],
-            output[59],
-            output[60],
-            output[61],
-            output[62],
-            output[63]
-        );
+        output
     }
 
-    // GENERATE MOVES<
-    pub fn scan_available_moves(&self, id_map: &HashMap<u8, &Piece>) -> Vec<(u8, u8)> {
-        let mut output: Vec<(u8, u8)> = Vec::new();
+    // Every move the side to move could make if it were allowed to leave its royal pieces in check.
+    pub fn scan_pseudo_legal_moves(&self) -> Vec<AvailableMove<'a>> {
+        let mut output: Vec<AvailableMove<'a>> = Vec::new();
         // Iterate over all pieces in the game.
         for i in &self.list_of_pieces_ingame {
             // Verify piece is correct color
-            if &i.player == &self.active_color {
-                // Alternative but annoying way of accessing piece location.
-                // Search position vector for its location.
-                // let current_location: u8 = self.position.clone().into_iter().position(|n: u8| n == i.id).unwrap().try_into().unwrap();
+            if !i.captured && i.player == self.active_color {
+                self.scan_piece_moves(i, &mut output);
+            }
+        }
+        output
+    }
 
-                // For each relevant piece, iterate over all of the moves.
-                for j in &i.piece_type.moveset {
+    // Read each MOVEID in the piece's moveset and add the squares it reaches.
+    fn scan_piece_moves(&self, piece: &Piece<'a>, output: &mut Vec<AvailableMove<'a>>) {
+        let piece_type: &'a PieceType = piece.piece_type;
+        for m in &piece_type.moveset {
+            if !self.move_preconditions_met(piece, m) {
+                continue;
+            }
+            if m.castles {
+                self.scan_castling_move(piece, m, output);
+                continue;
+            }
+            for (dx, dy) in move_vectors(m) {
+                if m.any_multiple {
+                    // Keep going along [H V] until we leave the board or, for non-jumping moves, run into a piece.
+                    let mut k: i32 = 1;
+                    while let Some(to) =
+                        self.offset_square(piece.position, k * dx, k * dy, piece.player)
+                    {
+                        self.scan_landing_square(piece, m, to, output);
+                        if !m.jump && self.position[to as usize] != 0 {
+                            break;
+                        }
+                        k += 1;
+                    }
+                } else if let Some(to) = self.offset_square(piece.position, dx, dy, piece.player) {
+                    if m.jump || self.path_is_clear(piece.position, dx, dy, piece.player) {
+                        self.scan_landing_square(piece, m, to, output);
+                    }
+                }
+            }
+        }
+    }
 
+    // The part of the move that only cares about what is on the destination square.
+    fn scan_landing_square(
+        &self,
+        piece: &Piece<'a>,
+        m: &'a Move,
+        to: u8,
+        output: &mut Vec<AvailableMove<'a>>,
+    ) {
+        let occupant: u8 = self.position[to as usize];
+        if m.enpassant {
+            // En passant-like moves land on an empty square and capture somewhere else.
+            if occupant == 0 {
+                if let Some(captured_id) = self.enpassant_target(piece, m) {
+                    self.push_with_promotions(piece, m, to, Some(captured_id), output);
                 }
-                // Create the read_move method() for the moves.
             }
+        } else if occupant == 0 {
+            if m.moves {
+                self.push_with_promotions(piece, m, to, None, output);
+            }
+        } else if m.captures && self.piece(occupant).player != piece.player {
+            self.push_with_promotions(piece, m, to, Some(occupant), output);
         }
-        
-        // For each piece, iterate over each move.
-        // Examine the move, and calculate bit maps based on it.
-        output
     }
 
-    //Just read the moveid into a bitmap ignoring checks
-    pub fn read_moveid_into_bitmap_ignoring_checks(&self, current_location: u8, move_pointer: Move) -> Vec<Vec<u8>> {
-        let mut string_wrapper: Vec<Vec<u8>> = Vec::new();
-        let mut output:Vec<u8> = Vec::new();
+    // Promotable pieces reaching the far rank turn into each of the pieces in promotes_to, one move apiece.
+    fn push_with_promotions(
+        &self,
+        piece: &Piece<'a>,
+        m: &'a Move,
+        to: u8,
+        captured_piece_id: Option<u8>,
+        output: &mut Vec<AvailableMove<'a>>,
+    ) {
+        let mut available_move: AvailableMove<'a> = AvailableMove {
+            piece_id: piece.id,
+            from: piece.position,
+            to,
+            move_type: m,
+            captured_piece_id,
+            promotion: None,
+            castle_target: None,
+        };
+        let last_row: u8 = if piece.player == 'w' {
+            0
+        } else {
+            BOARDSIZE - 1
+        };
+        if piece.piece_type.promotable && to / BOARDSIZE == last_row {
+            for symbol in piece.piece_type.promotes_to.chars() {
+                if let Some(piece_type) = self.piece_symbol_map.get(&symbol) {
+                    available_move.promotion = Some(if piece.player == 'w' {
+                        piece_type.white_id
+                    } else {
+                        piece_type.black_id
+                    });
+                    output.push(available_move);
+                }
+            }
+        } else {
+            output.push(available_move);
+        }
+    }
 
-        fn go_through_the_motions(bitmap: String) -> String {
-            let mut output: String = String::new();
+    // Castling-like moves: the piece and the target must be unmoved, nothing may stand between them,
+    // and the piece may not start in, pass through or land in check.
+    fn scan_castling_move(
+        &self,
+        piece: &Piece<'a>,
+        m: &'a Move,
+        output: &mut Vec<AvailableMove<'a>>,
+    ) {
+        if !piece.list_of_moves.is_empty() || piece.has_castled || self.royal_in_check(piece.player)
+        {
+            return;
+        }
+        let target_id: u8 = match self.matching_piece_at(
+            piece,
+            m.castle_target_piece_player,
+            m.castle_target_piece_id,
+            m.castle_target_piece_relative_location,
+        ) {
+            Some(target_id) => target_id,
+            None => return,
+        };
+        let target: &Piece = self.piece(target_id);
+        if m.castle_target_piece_cannot_move && !target.list_of_moves.is_empty() {
+            return;
+        }
+        let target_to: u8 = match self.relative_square(
+            target.position,
+            m.castle_target_piece_movement,
+            piece.player,
+        ) {
+            Some(square) => square,
+            None => return,
+        };
+        if squares_between(piece.position, target.position)
+            .iter()
+            .any(|s| self.position[*s as usize] != 0)
+        {
+            return;
+        }
+        for (dx, dy) in move_vectors(m) {
+            let to: u8 = match self.offset_square(piece.position, dx, dy, piece.player) {
+                Some(square) => square,
+                None => continue,
+            };
+            // Both landing squares must be free, apart from the two castling pieces themselves.
+            if [to, target_to].iter().any(|s| {
+                let occupant: u8 = self.position[*s as usize];
+                occupant != 0 && occupant != piece.id && occupant != target_id
+            }) {
+                continue;
+            }
+            let mut crossed: Vec<u8> = squares_between(piece.position, to);
+            crossed.push(to);
+            if crossed
+                .iter()
+                .any(|s| self.square_attacked_by(*s, opponent(piece.player)))
+            {
+                continue;
+            }
+            output.push(AvailableMove {
+                piece_id: piece.id,
+                from: piece.position,
+                to,
+                move_type: m,
+                captured_piece_id: None,
+                promotion: None,
+                castle_target: Some((target_id, target.position, target_to)),
+            });
+        }
+    }
 
-            // Verify that a capture can occur.
+    // Whether one of the side to move's pieces could take en passant right now, leaving check aside.
+    pub fn can_capture_enpassant(&self) -> bool {
+        let mut output: Vec<AvailableMove<'a>> = Vec::new();
+        self.list_of_pieces_ingame
+            .iter()
+            .filter(|p| !p.captured && p.player == self.active_color)
+            .filter(|p| p.piece_type.moveset.iter().any(|m| m.enpassant))
+            .any(|p| {
+                output.clear();
+                self.scan_piece_moves(p, &mut output);
+                output.iter().any(|m| m.move_type.enpassant)
+            })
+    }
 
-            output
+    // The piece captured by an en passant-like move, if the last move played allows it.
+    // The target must have just made the required move, and either stand on the given location or have passed over it.
+    fn enpassant_target(&self, piece: &Piece<'a>, m: &Move) -> Option<u8> {
+        let last: &AvailableMove = &self.list_of_moves.last()?.played;
+        if Some(&last.move_type.id) != m.enpassant_target_piece_previous_move.as_ref() {
+            return None;
+        }
+        let target: &Piece = self.piece(last.piece_id);
+        let player_matches: bool = match m.enpassant_target_piece_player {
+            Some('F') => target.player == piece.player,
+            Some('f') => target.player != piece.player,
+            _ => true,
+        };
+        let id_matches: bool = match m.enpassant_target_piece_id {
+            Some(id) if id != '0' => target.symbol.eq_ignore_ascii_case(&id),
+            _ => true,
+        };
+        let square: u8 = self.relative_square(
+            piece.position,
+            m.enpassant_target_piece_relative_location?,
+            piece.player,
+        )?;
+        if player_matches
+            && id_matches
+            && (target.position == square || squares_between(last.from, last.to).contains(&square))
+        {
+            Some(target.id)
+        } else {
+            None
         }
+    }
 
-        if move_pointer.reflections.0 {
-            //Create bitmap to translation!
-            let mut temp:Vec<u8> = Vec::new();
-            let mut counter: u8 = 0;
-            // Fill 0s before.
-            while counter < {current_location + move_pointer.translation.0 + 8 * move_pointer.translation.1} {
-                temp.push(0);
-                counter += 1;
+    // Checks the parts of a MOVEID that depend on the piece's history and surroundings rather than where it lands.
+    fn move_preconditions_met(&self, piece: &Piece<'a>, m: &Move) -> bool {
+        if m.only_first_move && !piece.list_of_moves.is_empty() {
+            return false;
+        }
+        if m.once && piece.list_of_moves.contains(&m.id) {
+            return false;
+        }
+        if m.piece_makes_previous_move {
+            match &m.previous_move {
+                Some(previous) if piece.list_of_moves.contains(previous) => {}
+                _ => return false,
             }
-            // 1 on the actual index.
-            temp.push(1);
-            counter += 1;
-            // Fill 0s after.
-            while counter < 64 {
-                temp.push(0);
-                counter += 1;
+        }
+        if m.requires_target_piece
+            && self
+                .matching_piece_at(
+                    piece,
+                    m.target_piece_player,
+                    m.target_piece_id,
+                    m.target_piece_relative_location,
+                )
+                .is_none()
+        {
+            return false;
+        }
+        true
+    }
+
+    // The id of the piece at a location relative to `piece`, provided it is on the right team (F, f or 0) and has the right symbol.
+    fn matching_piece_at(
+        &self,
+        piece: &Piece<'a>,
+        friendly: char,
+        id: char,
+        location: (u8, u8, u8, u8),
+    ) -> Option<u8> {
+        let square: u8 = self.relative_square(piece.position, location, piece.player)?;
+        let target_id: u8 = self.position[square as usize];
+        if target_id == 0 {
+            return None;
+        }
+        let target: &Piece = self.piece(target_id);
+        let team_matches: bool = match friendly {
+            'F' => target.player == piece.player,
+            'f' => target.player != piece.player,
+            _ => true,
+        };
+        let id_matches: bool = id == '0' || target.symbol.eq_ignore_ascii_case(&id);
+        if team_matches && id_matches {
+            Some(target_id)
+        } else {
+            None
+        }
+    }
+
+    // EXPLAINING MOVES
+
+    // Every Move of the piece on `from` whose shape reaches `to`, with the MOVEID field that stops it and why.
+    // None means nothing about the piece or the board stops it, so it can only be illegal because of check.
+    // Goes through the same steps as scan_piece_moves, in the same order.
+    pub fn diagnose_move(
+        &self,
+        from: u8,
+        to: u8,
+    ) -> Vec<(&'a Move, Option<(&'static str, String)>)> {
+        let mut output: Vec<(&'a Move, Option<(&'static str, String)>)> = Vec::new();
+        let id: u8 = self.position[from as usize];
+        if id == 0 {
+            return output;
+        }
+        let piece: &Piece<'a> = self.piece(id);
+        let name: &str = &piece.piece_type.name;
+        let piece_type: &'a PieceType = piece.piece_type;
+        for m in &piece_type.moveset {
+            // How far along [H V] the move has to go to reach `to`, if it can at all.
+            let mut reach: Option<(i32, i32, i32)> = None;
+            for (dx, dy) in move_vectors(m) {
+                let limit: i32 = if m.any_multiple && !m.castles {
+                    BOARDSIZE as i32
+                } else {
+                    1
+                };
+                for k in 1..=limit {
+                    if self.offset_square(from, k * dx, k * dy, piece.player) == Some(to) {
+                        reach = Some((dx, dy, k));
+                    }
+                }
             }
+            let (dx, dy, k) = match reach {
+                Some(reach) => reach,
+                None => continue,
+            };
+            let reason: Option<(&'static str, String)> = if m.only_first_move
+                && !piece.list_of_moves.is_empty()
+            {
+                Some((
+                    "only_first_move",
+                    format!(
+                        "only allowed on the {}'s first move, and it has moved",
+                        name
+                    ),
+                ))
+            } else if m.once && piece.list_of_moves.contains(&m.id) {
+                Some((
+                    "once",
+                    format!("only allowed once, and the {} has already made it", name),
+                ))
+            } else if m.piece_makes_previous_move
+                && !m
+                    .previous_move
+                    .as_ref()
+                    .is_some_and(|p| piece.list_of_moves.contains(p))
+            {
+                Some((
+                    "previous_move",
+                    format!(
+                        "the {} has to make {} first",
+                        name,
+                        m.previous_move.as_deref().unwrap_or("another move")
+                    ),
+                ))
+            } else if m.requires_target_piece
+                && self
+                    .matching_piece_at(
+                        piece,
+                        m.target_piece_player,
+                        m.target_piece_id,
+                        m.target_piece_relative_location,
+                    )
+                    .is_none()
+            {
+                Some(("requires_target_piece", format!("needs a {} piece with symbol {} at a set square next to the {}, and there isn't one", describe_team(m.target_piece_player), m.target_piece_id, name)))
+            } else if m.castles {
+                self.diagnose_castling(piece, m, to)
+            } else if !m.jump && !self.path_is_clear_to(from, m, (dx, dy, k), piece.player) {
+                Some(("jump", String::from("can't jump, and the path is blocked")))
+            } else {
+                let occupant: u8 = self.position[to as usize];
+                if m.enpassant {
+                    if occupant != 0 {
+                        Some((
+                            "enpassant",
+                            String::from("en passant-like moves land on an empty square"),
+                        ))
+                    } else if self.enpassant_target(piece, m).is_none() {
+                        Some((
+                            "enpassant_target_piece_previous_move",
+                            format!(
+                                "the piece to take has to have just made {}",
+                                m.enpassant_target_piece_previous_move
+                                    .as_deref()
+                                    .unwrap_or("its move")
+                            ),
+                        ))
+                    } else {
+                        None
+                    }
+                } else if occupant == 0 {
+                    if m.moves {
+                        None
+                    } else {
+                        Some((
+                            "moves",
+                            format!("only captures, and {} is empty", square_name(to)),
+                        ))
+                    }
+                } else if self.piece(occupant).player == piece.player {
+                    Some((
+                        "captures",
+                        format!("{} holds one of your own pieces", square_name(to)),
+                    ))
+                } else if m.captures {
+                    None
+                } else {
+                    Some((
+                        "captures",
+                        format!(
+                            "can't capture, and {} holds an enemy piece",
+                            square_name(to)
+                        ),
+                    ))
+                }
+            };
+            output.push((m, reason));
         }
-        string_wrapper
+        output
+    }
+
+    // The castling steps of scan_castling_move, one at a time.
+    fn diagnose_castling(
+        &self,
+        piece: &Piece<'a>,
+        m: &Move,
+        to: u8,
+    ) -> Option<(&'static str, String)> {
+        let name: &str = &piece.piece_type.name;
+        if !piece.list_of_moves.is_empty() || piece.has_castled {
+            return Some(("castles", format!("the {} has already moved", name)));
+        }
+        if self.royal_in_check(piece.player) {
+            return Some(("castles", String::from("can't castle out of check")));
+        }
+        let target_id: u8 = match self.matching_piece_at(
+            piece,
+            m.castle_target_piece_player,
+            m.castle_target_piece_id,
+            m.castle_target_piece_relative_location,
+        ) {
+            Some(target_id) => target_id,
+            None => {
+                return Some((
+                    "castle_target_piece_id",
+                    String::from("the piece to castle with isn't there"),
+                ))
+            }
+        };
+        let target: &Piece = self.piece(target_id);
+        if m.castle_target_piece_cannot_move && !target.list_of_moves.is_empty() {
+            return Some((
+                "castle_target_piece_cannot_move",
+                format!("the {} to castle with has moved", target.piece_type.name),
+            ));
+        }
+        let target_to: u8 = match self.relative_square(
+            target.position,
+            m.castle_target_piece_movement,
+            piece.player,
+        ) {
+            Some(square) => square,
+            None => {
+                return Some((
+                    "castle_target_piece_movement",
+                    format!(
+                        "the {} to castle with would land off the board",
+                        target.piece_type.name
+                    ),
+                ))
+            }
+        };
+        if squares_between(piece.position, target.position)
+            .iter()
+            .any(|s| self.position[*s as usize] != 0)
+        {
+            return Some(("castles", String::from("there are pieces in the way")));
+        }
+        if let Some(taken) = [to, target_to].into_iter().find(|s| {
+            let occupant: u8 = self.position[*s as usize];
+            occupant != 0 && occupant != piece.id && occupant != target_id
+        }) {
+            return Some((
+                "castles",
+                format!(
+                    "{} is taken, and castling doesn't capture",
+                    square_name(taken)
+                ),
+            ));
+        }
+        let mut crossed: Vec<u8> = squares_between(piece.position, to);
+        crossed.push(to);
+        if crossed
+            .iter()
+            .any(|s| self.square_attacked_by(*s, opponent(piece.player)))
+        {
+            return Some((
+                "castles",
+                format!(
+                    "the {} would pass through or land on an attacked square",
+                    name
+                ),
+            ));
+        }
+        None
+    }
+
+    // The squares scan_piece_moves checks on the way to the k-th step along [H V].
+    // Moves with n only look at the multiples of [H V] in between, the others use path_is_clear.
+    fn path_is_clear_to(
+        &self,
+        from: u8,
+        m: &Move,
+        (dx, dy, k): (i32, i32, i32),
+        player: char,
+    ) -> bool {
+        if !m.any_multiple {
+            return self.path_is_clear(from, dx, dy, player);
+        }
+        (1..k).all(|step| {
+            self.offset_square(from, step * dx, step * dy, player)
+                .is_some_and(|square| self.position[square as usize] == 0)
+        })
+    }
+
+    // CHECKS AND ATTACKS
+
+    pub fn square_attacked_by(&self, square: u8, attacker: char) -> bool {
+        self.list_of_pieces_ingame
+            .iter()
+            .filter(|p| !p.captured && p.player == attacker)
+            .any(|p| self.piece_attacks_square(p, square))
+    }
+
+    // Whether the piece could capture on the square if an enemy stood there. Castling and en passant never capture onto their landing square.
+    fn piece_attacks_square(&self, piece: &Piece<'a>, square: u8) -> bool {
+        for m in &piece.piece_type.moveset {
+            if !m.captures || m.castles || m.enpassant || !self.move_preconditions_met(piece, m) {
+                continue;
+            }
+            for (dx, dy) in move_vectors(m) {
+                if m.any_multiple {
+                    let mut k: i32 = 1;
+                    while let Some(to) =
+                        self.offset_square(piece.position, k * dx, k * dy, piece.player)
+                    {
+                        if to == square {
+                            return true;
+                        }
+                        if !m.jump && self.position[to as usize] != 0 {
+                            break;
+                        }
+                        k += 1;
+                    }
+                } else if self.offset_square(piece.position, dx, dy, piece.player) == Some(square)
+                    && (m.jump || self.path_is_clear(piece.position, dx, dy, piece.player))
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    pub fn royal_in_check(&self, player: char) -> bool {
+        self.list_of_pieces_ingame
+            .iter()
+            .filter(|p| !p.captured && p.player == player && p.piece_type.royal)
+            .any(|p| self.square_attacked_by(p.position, opponent(player)))
+    }
+
+    // Squares attacked by a player, 1 for attacked and 0 for not.
+    pub fn attack_bitmap(&self, attacker: char) -> Vec<u8> {
+        (0..BOARDSIZE * BOARDSIZE)
+            .map(|square| u8::from(self.square_attacked_by(square, attacker)))
+            .collect()
     }
 
     pub fn enemy_attack_bitmap(&self) -> String {
-        let mut output:String = String::new();
-        for i in &self.list_of_pieces_ingame {
-            if &i.player != &self.active_color {
-                
+        Game::convert_bitmap_to_string(&self.attack_bitmap(opponent(self.active_color)))
+    }
+
+    // MAKE AND UNMAKE MOVES
+
+    // The legal move written as e2e4 or e7e8q, if there is one.
+    pub fn find_move(&mut self, coordinate: &str) -> Option<AvailableMove<'a>> {
+        let coordinate: String = coordinate.to_ascii_lowercase();
+        self.scan_available_moves()
+            .into_iter()
+            .find(|m| m.coordinate_notation() == coordinate)
+    }
+
+    pub fn make_move(&mut self, available_move: AvailableMove<'a>) {
+        self.apply_move(available_move);
+        self.white_check = self.royal_in_check('w');
+        self.black_check = self.royal_in_check('b');
+    }
+
+    // make_move without updating the check flags, for trying out moves while generating them.
+    fn apply_move(&mut self, available_move: AvailableMove<'a>) {
+        let index: usize = piece_index(available_move.piece_id);
+        let mut record: MoveRecord<'a> = MoveRecord {
+            played: available_move,
+            previous_white_check: self.white_check,
+            previous_black_check: self.black_check,
+            previous_halfmove_clock: self.halfmove_clock,
+            previous_has_castled: self.list_of_pieces_ingame[index].has_castled,
+            promoted_from: None,
+            previous_hash: self.hash,
+        };
+        // Castling rights and en passant can change in ways that are easier to recompute than to follow.
+        let previous_extras: u64 = self.castling_hash() ^ self.enpassant_hash();
+        if let Some(captured_id) = available_move.captured_piece_id {
+            let captured: &mut Piece = &mut self.list_of_pieces_ingame[piece_index(captured_id)];
+            captured.captured = true;
+            self.position[captured.position as usize] = 0;
+            self.hash ^= piece_square_key(captured.symbol, captured.position);
+            if self.active_color == 'w' {
+                self.white_captures.push(captured_id);
+            } else {
+                self.black_captures.push(captured_id);
+            }
+        }
+        // Lift both pieces before putting either down, the target may land where the mover started.
+        self.position[available_move.from as usize] = 0;
+        if let Some((_, target_from, _)) = available_move.castle_target {
+            self.position[target_from as usize] = 0;
+        }
+        self.position[available_move.to as usize] = available_move.piece_id;
+        if let Some((target_id, _, target_to)) = available_move.castle_target {
+            self.position[target_to as usize] = target_id;
+            let target: &mut Piece = &mut self.list_of_pieces_ingame[piece_index(target_id)];
+            self.hash ^= piece_square_key(target.symbol, target.position)
+                ^ piece_square_key(target.symbol, target_to);
+            target.position = target_to;
+            target
+                .list_of_moves
+                .push(available_move.move_type.id.clone());
+        }
+        let piece: &mut Piece = &mut self.list_of_pieces_ingame[index];
+        // Captures and moves by promotable (pawn-like) pieces reset the fifty move rule.
+        if available_move.captured_piece_id.is_some() || piece.piece_type.promotable {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        self.hash ^= piece_square_key(piece.symbol, piece.position);
+        piece.position = available_move.to;
+        piece
+            .list_of_moves
+            .push(available_move.move_type.id.clone());
+        if available_move.move_type.castles {
+            piece.has_castled = true;
+        }
+        if let Some(symbol) = available_move.promotion {
+            record.promoted_from = Some((piece.symbol, piece.piece_type));
+            piece.symbol = symbol;
+            piece.piece_type = self.piece_symbol_map[&symbol];
+        }
+        self.hash ^= piece_square_key(piece.symbol, piece.position) ^ SIDE_TO_MOVE_KEY;
+        if self.active_color == 'b' {
+            self.fullmove_number += 1;
+        }
+        self.active_color = opponent(self.active_color);
+        self.list_of_moves.push(record);
+        self.hash ^= previous_extras ^ self.castling_hash() ^ self.enpassant_hash();
+        debug_assert_eq!(self.hash, self.compute_hash());
+    }
+
+    // Take back the last move. Moves made up while reading a FEN can't be taken back.
+    pub fn unmake_move(&mut self) {
+        if self.list_of_moves.len() <= self.setup_moves {
+            return;
+        }
+        let record: MoveRecord<'a> = self.list_of_moves.pop().unwrap();
+        let played: AvailableMove<'a> = record.played;
+        self.active_color = opponent(self.active_color);
+        if self.active_color == 'b' {
+            self.fullmove_number -= 1;
+        }
+        self.halfmove_clock = record.previous_halfmove_clock;
+        self.hash = record.previous_hash;
+        self.white_check = record.previous_white_check;
+        self.black_check = record.previous_black_check;
+        let piece: &mut Piece = &mut self.list_of_pieces_ingame[piece_index(played.piece_id)];
+        piece.position = played.from;
+        piece.list_of_moves.pop();
+        piece.has_castled = record.previous_has_castled;
+        if let Some((symbol, piece_type)) = record.promoted_from {
+            piece.symbol = symbol;
+            piece.piece_type = piece_type;
+        }
+        self.position[played.to as usize] = 0;
+        if let Some((target_id, target_from, target_to)) = played.castle_target {
+            self.position[target_to as usize] = 0;
+            let target: &mut Piece = &mut self.list_of_pieces_ingame[piece_index(target_id)];
+            target.position = target_from;
+            target.list_of_moves.pop();
+            self.position[target_from as usize] = target_id;
+        }
+        self.position[played.from as usize] = played.piece_id;
+        if let Some(captured_id) = played.captured_piece_id {
+            let captured: &mut Piece = &mut self.list_of_pieces_ingame[piece_index(captured_id)];
+            captured.captured = false;
+            self.position[captured.position as usize] = captured_id;
+            if self.active_color == 'w' {
+                self.white_captures.pop();
+            } else {
+                self.black_captures.pop();
+            }
+        }
+        debug_assert_eq!(self.hash, self.compute_hash());
+    }
+
+    // How many times the current position has been seen before, as far back as the last capture or pawn-like move.
+    pub fn repetitions(&self) -> usize {
+        self.list_of_moves
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            .filter(|record| record.previous_hash == self.hash)
+            .count()
+    }
+
+    // Whether the game is over, and how. A fallen flag comes first, then a side with no legal moves is mated if a royal piece is attacked, stalemated otherwise.
+    pub fn outcome(&mut self) -> Option<Outcome> {
+        if let Some(flag) = self.clocks.as_ref().and_then(|c| c.flag()) {
+            return Some(flag);
+        }
+        if self.scan_available_moves().is_empty() {
+            return Some(if self.royal_in_check(self.active_color) {
+                Outcome::Checkmate(opponent(self.active_color))
+            } else {
+                Outcome::Stalemate
+            });
+        }
+        if self.halfmove_clock >= 100 {
+            Some(Outcome::FiftyMoves)
+        } else if self.repetitions() >= 2 {
+            Some(Outcome::Repetition)
+        } else {
+            None
+        }
+    }
+
+    // Castling-like moves that are still allowed at some point in the game, whether or not they are legal right now.
+    // The royal piece must not have moved or castled, and the target must be in place and, if the move says so, unmoved.
+    pub fn castling_rights(&self) -> Vec<(u8, &'a Move)> {
+        let mut output: Vec<(u8, &'a Move)> = Vec::new();
+        for royal in &self.list_of_pieces_ingame {
+            if royal.captured || royal.has_castled || !royal.list_of_moves.is_empty() {
+                continue;
+            }
+            let piece_type: &'a PieceType = royal.piece_type;
+            for m in piece_type.moveset.iter().filter(|m| m.castles) {
+                if let Some(target_id) = self.matching_piece_at(
+                    royal,
+                    m.castle_target_piece_player,
+                    m.castle_target_piece_id,
+                    m.castle_target_piece_relative_location,
+                ) {
+                    if !m.castle_target_piece_cannot_move
+                        || self.piece(target_id).list_of_moves.is_empty()
+                    {
+                        output.push((royal.id, m));
+                    }
+                }
             }
         }
         output
     }
 
+    // Every castling-like MOVEID of every royal piece still on the board, with whether it can still be played at all and right now.
+    pub fn castling_availability(&mut self) -> Vec<CastlingStatus<'a>> {
+        let rights: Vec<(u8, &'a Move)> = self.castling_rights();
+        let legal: Vec<AvailableMove<'a>> = self.scan_available_moves();
+        let mut output: Vec<CastlingStatus<'a>> = Vec::new();
+        for royal in self
+            .list_of_pieces_ingame
+            .iter()
+            .filter(|p| !p.captured && p.piece_type.royal)
+        {
+            let piece_type: &'a PieceType = royal.piece_type;
+            for m in piece_type.moveset.iter().filter(|m| m.castles) {
+                let letter: char = match self.castling_letter(royal.id, m) {
+                    Some(letter) => letter,
+                    None => continue,
+                };
+                output.push(CastlingStatus {
+                    royal_id: royal.id,
+                    move_type: m,
+                    letter,
+                    available: rights
+                        .iter()
+                        .any(|(id, right)| *id == royal.id && right.id == m.id),
+                    playable: legal
+                        .iter()
+                        .any(|l| l.piece_id == royal.id && l.move_type.id == m.id),
+                });
+            }
+        }
+        output
+    }
+
+    // The pieces a player has taken, in the order they were taken.
+    pub fn captured_by(&self, player: char) -> Vec<&Piece<'a>> {
+        let ids: &Vec<u8> = if player == 'w' {
+            &self.white_captures
+        } else {
+            &self.black_captures
+        };
+        ids.iter().map(|&id| self.piece(id)).collect()
+    }
+
+    // BOARD GEOMETRY
+
+    // Moves are written from the mover's point of view: up is towards rank 8 for white and towards rank 1 for black.
+    // Right is towards the h file for both, so castling-like moves keep working for black.
+    pub fn offset_square(&self, from: u8, dx: i32, dy: i32, player: char) -> Option<u8> {
+        let size: i32 = BOARDSIZE as i32;
+        let column: i32 = from as i32 % size + dx;
+        let row: i32 = from as i32 / size + if player == 'w' { -dy } else { dy };
+        if column < 0 || row < 0 || column >= size || row >= size {
+            None
+        } else {
+            Some((row * size + column) as u8)
+        }
+    }
+
+    // Locations in MOVEIDs are written as spaces (up, down, left, right).
+    pub fn relative_square(
+        &self,
+        from: u8,
+        location: (u8, u8, u8, u8),
+        player: char,
+    ) -> Option<u8> {
+        let (up, down, left, right) = location;
+        self.offset_square(
+            from,
+            right as i32 - left as i32,
+            up as i32 - down as i32,
+            player,
+        )
+    }
+
+    // Block checking for moves that are not any_multiple. Straight and diagonal moves check the line between,
+    // anything else checks H steps then V steps, and failing that V steps then H steps.
+    fn path_is_clear(&self, from: u8, dx: i32, dy: i32, player: char) -> bool {
+        let is_empty = |(x, y): (i32, i32)| match self.offset_square(from, x, y, player) {
+            Some(square) => self.position[square as usize] == 0,
+            None => false,
+        };
+        if dx == 0 || dy == 0 || dx.abs() == dy.abs() {
+            let steps: i32 = dx.abs().max(dy.abs());
+            return (1..steps).all(|k| is_empty((k * dx.signum(), k * dy.signum())));
+        }
+        let horizontal_first: bool = (1..=dx.abs()).all(|k| is_empty((k * dx.signum(), 0)))
+            && (1..dy.abs()).all(|k| is_empty((dx, k * dy.signum())));
+        let vertical_first: bool = (1..=dy.abs()).all(|k| is_empty((0, k * dy.signum())))
+            && (1..dx.abs()).all(|k| is_empty((k * dx.signum(), dy)));
+        horizontal_first || vertical_first
+    }
+
     // GENERATE BITMAPS
 
     pub fn convert_bitmap_to_string(bitmap: &Vec<u8>) -> String {
@@ -383,16 +1353,119 @@ impl<'a> Game<'a> {
         }
         output
     }
+}
 
+// The other player.
+pub fn opponent(player: char) -> char {
+    if player == 'w' {
+        'b'
+    } else {
+        'w'
+    }
 }
 
-#[cfg(test)]
+// F and f from a MOVEID in words.
+fn describe_team(friendly: char) -> &'static str {
+    match friendly {
+        'F' => "friendly",
+        'f' => "enemy",
+        _ => "any",
+    }
+}
+
+// Pieces are numbered from 1 in the order they appear in the FEN, so this is their place in list_of_pieces_ingame.
+pub fn piece_index(id: u8) -> usize {
+    (id - 1) as usize
+}
 
+// Every direction a move goes in once its reflections are applied, as (right, up).
+pub fn move_vectors(m: &Move) -> Vec<(i32, i32)> {
+    let (h, v) = (m.translation.0 as i32, m.translation.1 as i32);
+    let mut output: Vec<(i32, i32)> = Vec::new();
+    for (reflected, vector) in [
+        (m.reflections.0, (h, v)),
+        (m.reflections.1, (-h, v)),
+        (m.reflections.2, (-h, -v)),
+        (m.reflections.3, (h, -v)),
+    ] {
+        if reflected && !output.contains(&vector) {
+            output.push(vector);
+        }
+    }
+    output
+}
+
+// The squares strictly between two squares on the same rank, file or diagonal. Empty otherwise.
+pub fn squares_between(a: u8, b: u8) -> Vec<u8> {
+    let size: i32 = BOARDSIZE as i32;
+    let (a_row, a_column) = (a as i32 / size, a as i32 % size);
+    let (row_change, column_change) = (b as i32 / size - a_row, b as i32 % size - a_column);
+    if !(row_change == 0 || column_change == 0 || row_change.abs() == column_change.abs()) {
+        return Vec::new();
+    }
+    let steps: i32 = row_change.abs().max(column_change.abs());
+    (1..steps)
+        .map(|k| {
+            ((a_row + k * row_change.signum()) * size + a_column + k * column_change.signum()) as u8
+        })
+        .collect()
+}
+
+// Index 0 is a8 and index 63 is h1, the same order squares appear in a FEN.
+pub fn square_name(square: u8) -> String {
+    format!(
+        "{}{}",
+        (b'a' + square % BOARDSIZE) as char,
+        BOARDSIZE - square / BOARDSIZE
+    )
+}
+
+pub fn parse_square(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    let file: char = chars.next()?;
+    let rank: u8 = chars.as_str().parse().ok()?;
+    if !file.is_ascii_lowercase()
+        || (file as u8 - b'a') >= BOARDSIZE
+        || rank == 0
+        || rank > BOARDSIZE
+    {
+        return None;
+    }
+    Some((BOARDSIZE - rank) * BOARDSIZE + (file as u8 - b'a'))
+}
+
+// The piece placement field of a FEN, one entry per square.
+pub fn parse_fen_board(board: &str) -> Result<Vec<Option<char>>, String> {
+    let mut output: Vec<Option<char>> = Vec::new();
+    // Fill in empty spaces in FEN notation.
+    for i in board.chars() {
+        match i {
+            '1'..='9' => {
+                for _ in 0..i.to_digit(10).unwrap() {
+                    output.push(None);
+                }
+            }
+            // Ignore slashes in FEN notation.
+            '/' => {}
+            a => output.push(Some(a)),
+        }
+    }
+    if output.len() != (BOARDSIZE as usize) * (BOARDSIZE as usize) {
+        return Err(format!(
+            "FEN {} does not describe {} squares",
+            board,
+            BOARDSIZE * BOARDSIZE
+        ));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
 mod tests {
 
-    use std::collections::HashMap;
     use crate::configuration::*;
     use crate::game::*;
+    use std::collections::HashMap;
 
     // CONFIGURATION JSONS NEED TO MATCH THE ONES IN TESTFILES FOR THESE TESTS TO WORK.
     // Actually I think only the pieces one does.
@@ -401,19 +1474,18 @@ mod tests {
     fn verify_piece_color_bitmaps() {
         // JUNK TO IMITATE STARTING UP OVER AGAIN
         // Set global variables, namely the game_counter and the hashmaps.
-        let mut game_counter: u64 = 0;
+        let game_counter: u64 = 0;
         // Load in the pieces from the configuration file.
         let piece_list: PieceList = load_piece_list().unwrap();
         // Create the hashmap which pairs PieceTypes and their symbols for recognition.
-        let mut piece_symbol_map: HashMap<char, &PieceType> =
-            piece_list.map_piecetypes_to_symbols();
+        let piece_symbol_map: HashMap<char, &PieceType> = piece_list.map_piecetypes_to_symbols();
         // dbg!(&piece_symbol_map); // Had some issues before, this was for debugging.
         // piece_list_console_diagnostics(piece_list);
         // Load in the positions provided in the configuration file. Must be done after loading in the PieceTypes since the FEN has symbols that correspond to pieces.
         let position_list: PositionListIntermediateRepresentation =
             PositionListIntermediateRepresentation::new();
         // Create the hashmap which pairs the names of positions with their FENs.
-        let mut position_name_map: HashMap<String, String> = position_list.map_positions_to_names();
+        let position_name_map: HashMap<String, String> = position_list.map_positions_to_names();
         // Initialize a game.
         let game: Game = Game::new(
             game_counter,
@@ -422,11 +1494,96 @@ mod tests {
             position_name_map,
         );
         // Fill a HashMap with the piece IDs and references to the pieces.
-        let mut piece_id_map: HashMap<u8, &Piece> = game.map_pieces_to_ids();
+        let piece_id_map: HashMap<u8, &Piece> = game.map_pieces_to_ids();
         println!("{}", game.generate_white_piece_bitmap(&piece_id_map));
-        assert_eq!("0000000000000000000000000000000000000000000000001111111111111111", game.generate_white_piece_bitmap(&piece_id_map));
+        assert_eq!(
+            "0000000000000000000000000000000000000000000000001111111111111111",
+            game.generate_white_piece_bitmap(&piece_id_map)
+        );
         println!("{}", game.generate_black_piece_bitmap(&piece_id_map));
-        assert_eq!("1111111111111111000000000000000000000000000000000000000000000000", game.generate_black_piece_bitmap(&piece_id_map));
+        assert_eq!(
+            "1111111111111111000000000000000000000000000000000000000000000000",
+            game.generate_black_piece_bitmap(&piece_id_map)
+        );
+    }
+
+    #[test]
+    fn captures_and_castling_availability() {
+        let piece_list: PieceList =
+            load_piece_list_from(std::path::Path::new("testfiles/standardPieces.json")).unwrap();
+        let fen: &str = "r3k2r/1n6/8/8/8/8/6b1/R3K2R w KQkq - 0 1";
+        let mut game: Game =
+            Game::from_fen(0, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        // The bishop on g2 covers f1, so White can only castle long for now, and Black has to wait for their turn.
+        let rights: Vec<(char, bool, bool)> = game
+            .castling_availability()
+            .iter()
+            .map(|s| (s.letter, s.available, s.playable))
+            .collect();
+        for expected in [
+            ('K', true, false),
+            ('Q', true, true),
+            ('k', true, false),
+            ('q', true, false),
+        ] {
+            assert!(rights.contains(&expected), "{:?}", expected);
+        }
+        for coordinate in ["a1b1", "g2h1", "b1b7"] {
+            let available_move: AvailableMove = game.find_move(coordinate).unwrap();
+            game.make_move(available_move);
+        }
+        let symbols = |game: &Game, player: char| -> Vec<char> {
+            game.captured_by(player).iter().map(|p| p.symbol).collect()
+        };
+        assert_eq!(
+            (symbols(&game, 'w'), symbols(&game, 'b')),
+            (vec!['n'], vec!['R'])
+        );
+        game.unmake_move();
+        assert!(game.captured_by('w').is_empty());
+        // White's rooks have both gone from their corners, Black's haven't moved.
+        let available: Vec<(char, bool)> = game
+            .castling_availability()
+            .iter()
+            .map(|s| (s.letter, s.available))
+            .collect();
+        assert_eq!(available.len(), 4);
+        for expected in [('K', false), ('Q', false), ('k', true), ('q', true)] {
+            assert!(available.contains(&expected), "{:?}", expected);
+        }
     }
 
+    #[test]
+    fn diagnoses_where_castling_lands() {
+        let mut piece_list: PieceList =
+            load_piece_list_from(std::path::Path::new("testfiles/standardPieces.json")).unwrap();
+        let fen: &str = "4k3/8/8/8/8/8/8/R2QK2R w KQ - 0 1";
+        // Short castling with the rook sent somewhere else: past the king onto the queen, then off the board.
+        let mut reasons: Vec<String> = Vec::new();
+        for movement in [(0, 0, 4, 0), (0, 0, 0, 5)] {
+            let king: &mut PieceType = piece_list
+                .pieces
+                .iter_mut()
+                .find(|p| p.name == "king")
+                .unwrap();
+            king.moveset
+                .iter_mut()
+                .filter(|m| m.castles && m.castle_target_piece_relative_location.3 == 3)
+                .for_each(|m| m.castle_target_piece_movement = movement);
+            let mut game: Game =
+                Game::from_fen(0, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+            assert!(game.find_move("e1g1").is_none());
+            let (_, reason) =
+                &game.diagnose_move(parse_square("e1").unwrap(), parse_square("g1").unwrap())[0];
+            let (field, explanation) = reason.clone().unwrap();
+            reasons.push(format!("{} ({})", explanation, field));
+        }
+        assert_eq!(
+            reasons,
+            vec![
+                "d1 is taken, and castling doesn't capture (castles)",
+                "the rook to castle with would land off the board (castle_target_piece_movement)",
+            ]
+        );
+    }
 }
@@ -0,0 +1,278 @@
+use crate::game::*;
+use crate::render::*;
+
+// The legal moves of the piece on a square, split into quiet moves and captures.
+pub fn hint<'a>(
+    game: &mut Game<'a>,
+    square: u8,
+) -> Result<(Vec<AvailableMove<'a>>, Vec<AvailableMove<'a>>), String> {
+    let id: u8 = game.position[square as usize];
+    if id == 0 {
+        return Err(format!("There's no piece on {}.", square_name(square)));
+    }
+    if game.piece(id).player != game.active_color {
+        return Err(format!(
+            "The {} on {} isn't yours to move.",
+            game.piece(id).piece_type.name,
+            square_name(square)
+        ));
+    }
+    let (captures, quiet): (Vec<AvailableMove<'a>>, Vec<AvailableMove<'a>>) = game
+        .scan_available_moves()
+        .into_iter()
+        .filter(|m| m.from == square)
+        .partition(|m| m.is_capture());
+    Ok((quiet, captures))
+}
+
+// The board with a piece's moves marked, then the moves in SAN.
+pub fn show_hint(game: &mut Game, renderer: &Renderer, square: &str) -> String {
+    let square: u8 = match parse_square(square) {
+        Some(square) => square,
+        None => return format!("{} isn't a square.", square),
+    };
+    let (quiet, captures) = match hint(game, square) {
+        Ok(moves) => moves,
+        Err(why) => return why,
+    };
+    let mut marks: Vec<(u8, Mark)> = vec![(square, Mark::Selected)];
+    marks.extend(quiet.iter().map(|m| (m.to, Mark::Quiet)));
+    marks.extend(captures.iter().map(|m| (m.to, Mark::Capture)));
+    let mut output: String = renderer.render_marked(game, &marks);
+    for (label, moves) in [("Quiet moves", quiet), ("Captures", captures)] {
+        let mut sans: Vec<String> = Vec::new();
+        for available_move in moves {
+            sans.push(game.san(available_move));
+        }
+        output.push_str(&format!(
+            "\n{}: {}",
+            label,
+            if sans.is_empty() {
+                String::from("none")
+            } else {
+                sans.join(" ")
+            }
+        ));
+    }
+    output
+}
+
+// Every square the opponent attacks, from enemy_attack_bitmap.
+pub fn threats(game: &Game) -> Vec<u8> {
+    game.enemy_attack_bitmap()
+        .chars()
+        .enumerate()
+        .filter(|(_, c)| *c == '1')
+        .map(|(square, _)| square as u8)
+        .collect()
+}
+
+// The board with the opponent's attacks marked, then which of the side to move's pieces are under attack.
+pub fn show_threats(game: &Game, renderer: &Renderer) -> String {
+    let attacked: Vec<u8> = threats(game);
+    let marks: Vec<(u8, Mark)> = attacked.iter().map(|&s| (s, Mark::Attacked)).collect();
+    let mut output: String = renderer.render_marked(game, &marks);
+    let hanging: Vec<String> = attacked
+        .iter()
+        .filter(|&&s| {
+            let id: u8 = game.position[s as usize];
+            id != 0 && game.piece(id).player == game.active_color
+        })
+        .map(|&s| {
+            format!(
+                "{} on {}",
+                game.piece(game.position[s as usize]).piece_type.name,
+                square_name(s)
+            )
+        })
+        .collect();
+    output.push_str(&format!(
+        "\nUnder attack: {}",
+        if hanging.is_empty() {
+            String::from("nothing")
+        } else {
+            hanging.join(", ")
+        }
+    ));
+    output
+}
+
+// Why a move can't be played, down to the MOVEID field responsible.
+// Takes coordinates (e2e4) or SAN (Nf3). SAN that matches nothing is read as best it can: the piece letter and the destination.
+pub fn why(game: &mut Game, text: &str) -> String {
+    let text: &str = text.trim();
+    if let Ok(available_move) = game.parse_move(text) {
+        return format!("{} is legal.", game.san(available_move));
+    }
+    let coordinates: Option<(u8, u8)> = match (
+        text.get(0..2).and_then(parse_square),
+        text.get(2..4).and_then(parse_square),
+    ) {
+        (Some(from), Some(to)) => Some((from, to)),
+        _ => None,
+    };
+    let (froms, to): (Vec<u8>, u8) = match coordinates {
+        Some((from, to)) => (vec![from], to),
+        None => match san_candidates(game, text) {
+            Some(candidates) => candidates,
+            None => return game.parse_move(text).unwrap_err(),
+        },
+    };
+    if froms.len() == 1 {
+        // No piece, or the other side's: the usual explanation says it best.
+        let id: u8 = game.position[froms[0] as usize];
+        if id == 0 || game.piece(id).player != game.active_color {
+            return game.explain_illegal(froms[0], to);
+        }
+    }
+    let mut lines: Vec<String> = Vec::new();
+    let mut checks: usize = 0;
+    for &from in &froms {
+        let name: String = game
+            .piece(game.position[from as usize])
+            .piece_type
+            .name
+            .clone();
+        for (m, reason) in game.diagnose_move(from, to) {
+            // Nothing in the MOVEID stops it, so it's check.
+            let (field, explanation): (&str, String) = reason.unwrap_or_else(|| {
+                checks += 1;
+                (
+                    "check",
+                    format!("that would leave your {} in check", game.royal_name()),
+                )
+            });
+            lines.push(format!(
+                "The {} on {} using {}: {} ({}).",
+                name,
+                square_name(from),
+                m.id,
+                explanation,
+                field
+            ));
+        }
+    }
+    if checks == 1 && lines.len() == 1 {
+        return format!("That would leave your {} in check.", game.royal_name());
+    }
+    if lines.is_empty() {
+        return match froms.as_slice() {
+            [from] => format!(
+                "No move of the {} goes from {} to {}.",
+                game.piece(game.position[*from as usize]).piece_type.name,
+                square_name(*from),
+                square_name(to)
+            ),
+            _ => format!(
+                "None of your pieces like that can ever reach {}.",
+                square_name(to)
+            ),
+        };
+    }
+    lines.join("\n")
+}
+
+// The squares of the side to move's pieces that SAN could mean, and the destination.
+fn san_candidates(game: &Game, text: &str) -> Option<(Vec<u8>, u8)> {
+    let text: &str = text.trim_end_matches(['+', '#', '!', '?']);
+    let text: &str = match text.find('=') {
+        Some(index) => &text[..index],
+        None => text,
+    };
+    let to: u8 = parse_square(text.get(text.len().checked_sub(2)?..)?)?;
+    let letter: Option<char> = text.chars().next().filter(|c| c.is_ascii_uppercase());
+    let froms: Vec<u8> = game
+        .list_of_pieces_ingame
+        .iter()
+        .filter(|p| !p.captured && p.player == game.active_color)
+        .filter(|p| match letter {
+            Some(letter) => p.piece_type.white_id == letter,
+            // No letter means a pawn-like piece.
+            None => p.piece_type.promotable,
+        })
+        .map(|p| p.position)
+        .collect();
+    if froms.is_empty() {
+        None
+    } else {
+        Some((froms, to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::hints::*;
+    use std::path::Path;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn hints_and_threats() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game = Game::from_fen(
+            0,
+            "4k3/8/8/3p4/8/8/8/3QK3 w - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        let output: String = show_hint(&mut game, &Renderer::default(), "d1");
+        assert!(output.starts_with(" 8  . . . . k . . .\n"));
+        assert!(output.contains(" 5  . . .xp . . . *\n"));
+        assert!(output.contains(" 4  * . . * . . * .\n"));
+        assert!(output.contains(" 1  * * *>Q K . . .\n"));
+        assert!(output.ends_with("\nCaptures: Qxd5"));
+        assert_eq!(
+            show_hint(&mut game, &Renderer::default(), "d5"),
+            "The pawn on d5 isn't yours to move."
+        );
+        let output: String = show_threats(&game, &Renderer::default());
+        assert!(output.contains(" 4  . . ! . ! . . .\n"));
+        assert!(output.ends_with("Under attack: nothing"));
+    }
+
+    #[test]
+    fn why_names_the_field() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        assert_eq!(why(&mut game, "e4"), "e4 is legal.");
+        assert!(why(&mut game, "a1a3").contains("(jump)"));
+        assert!(why(&mut game, "Bc4").contains("can't jump, and the path is blocked (jump)"));
+        assert!(why(&mut game, "e2d3").contains("only captures, and d3 is empty (moves)"));
+        for coordinate in ["e2e3", "a7a6"] {
+            let available_move: AvailableMove = game.find_move(coordinate).unwrap();
+            game.make_move(available_move);
+        }
+        assert!(why(&mut game, "e3e5").contains("(only_first_move)"));
+        assert!(why(&mut game, "Kd1").contains("holds one of your own pieces (captures)"));
+        let mut pinned: Game = Game::from_fen(
+            1,
+            "4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        assert_eq!(
+            why(&mut pinned, "Bd3"),
+            "That would leave your king in check."
+        );
+        // With another rook that can't get there, the pinned one is still listed.
+        let mut two_rooks: Game = Game::from_fen(
+            2,
+            "4k3/4r3/8/8/8/8/RP2R3/4K3 w - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        let explanation: String = why(&mut two_rooks, "Rd2");
+        assert!(explanation.contains("The rook on e2 using"));
+        assert!(explanation.contains("that would leave your king in check (check)."));
+        assert!(explanation.contains("The rook on a2 using"));
+        assert!(explanation.contains("(jump)"));
+    }
+}
@@ -1,55 +1,966 @@
 // HashMap for easily accessing named things with ids, including the starting positions.
 use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::sync::{Arc, Mutex};
 
 // Declare some modules.
+mod balance;
+use crate::balance::*;
+mod book;
+use crate::book::*;
+mod clock;
+use crate::clock::*;
 mod configuration;
 use crate::configuration::*;
+mod evaluation;
+use crate::evaluation::*;
+mod export;
+use crate::export::*;
 mod game;
 use crate::game::*;
+mod hints;
+mod matches;
+use crate::matches::*;
+mod mcts;
+use crate::mcts::*;
+mod notation;
+use crate::notation::*;
+mod perft;
+use crate::perft::*;
+mod player;
+use crate::player::*;
+mod random;
+mod render;
+use crate::render::*;
+mod recorder;
+use crate::recorder::*;
+mod repl;
+use crate::repl::*;
+mod search;
+use crate::search::*;
+mod selfplay;
+use crate::selfplay::*;
+mod solver;
+use crate::solver::*;
+mod summary;
+use crate::summary::*;
+mod tablebase;
+use crate::tablebase::*;
+mod tournament;
+use crate::tournament::*;
+mod transposition;
+use crate::transposition::*;
+mod tui;
+use crate::tui::*;
+mod uci;
+use crate::uci::*;
+mod xboard;
+use crate::xboard::*;
+mod zobrist;
 
 fn main() {
-    println!("Hello, world!");
     // Set global variables, namely the game_counter and the hashmaps.
     let mut game_counter: u64 = 0;
     // Load in the pieces from the configuration file.
     let piece_list: PieceList = load_piece_list().unwrap();
     // Create the hashmap which pairs PieceTypes and their symbols for recognition.
-    let mut piece_symbol_map: HashMap<char, &PieceType> = piece_list.map_piecetypes_to_symbols();
+    let piece_symbol_map: HashMap<char, &PieceType> = piece_list.map_piecetypes_to_symbols();
     // dbg!(&piece_symbol_map); // Had some issues before, this was for debugging.
-    // piece_list_console_diagnostics(piece_list);
     // Load in the positions provided in the configuration file. Must be done after loading in the PieceTypes since the FEN has symbols that correspond to pieces.
     let position_list: PositionListIntermediateRepresentation =
         PositionListIntermediateRepresentation::new();
     // Create the hashmap which pairs the names of positions with their FENs.
-    let mut position_name_map: HashMap<String, String> = position_list.map_positions_to_names();
-    // Initialize a game.
-    let game: Game = Game::new(
-        game_counter,
-        String::from("standard"),
-        piece_symbol_map,
-        position_name_map,
-    );
-    // Fill a HashMap with the piece IDs and references to the pieces.
-    let mut piece_id_map: HashMap<u8, &Piece> = game.map_pieces_to_ids();
-    game.print_piece_id_map();
-    game.print_piece_symbol_map();
+    let position_name_map: HashMap<String, String> = position_list.map_positions_to_names();
+    // Read what we've been asked to do from the command line.
+    let mut args: Vec<String> = std::env::args().collect();
+    // --eval <name> can go anywhere and picks the evaluator the engines use.
+    let evaluator_name: String =
+        take_option(&mut args, "--eval").unwrap_or_else(|| String::from("material"));
+    // --playout random|heuristic is how the mcts command plays games out. Only heuristic playouts use --eval.
+    let playout: Playout = match playout_by_name(
+        &take_option(&mut args, "--playout").unwrap_or_else(|| String::from("random")),
+    ) {
+        Ok(playout) => playout,
+        Err(why) => {
+            println!("--playout: {}", why);
+            return;
+        }
+    };
+    // --hash <MB> and --tt-replace depth|always set up the alpha-beta engine's transposition table.
+    let hash_megabytes: usize = match take_option(&mut args, "--hash").map(|h| h.parse()) {
+        Some(Ok(megabytes)) => megabytes,
+        Some(Err(_)) => {
+            println!("--hash takes a size in MB");
+            return;
+        }
+        None => DEFAULT_HASH_MB,
+    };
+    let replacement: Replacement = match take_option(&mut args, "--tt-replace").as_deref() {
+        None | Some("depth") => Replacement::DepthPreferred,
+        Some("always") => Replacement::AlwaysReplace,
+        Some(other) => {
+            println!(
+                "Unknown replacement scheme {}, expected depth or always",
+                other
+            );
+            return;
+        }
+    };
+    // --white and --black pick the players for play: human, engine[:depth=N,...], mcts[:iterations=N,...] or random.
+    let white_spec: String =
+        take_option(&mut args, "--white").unwrap_or_else(|| String::from("human"));
+    let black_spec: String =
+        take_option(&mut args, "--black").unwrap_or_else(|| String::from("human"));
+    // --export <prefix> writes play's game log to prefix.csv, prefix-games.csv and prefix.mat.
+    let export_prefix: Option<String> = take_option(&mut args, "--export");
+    // --games <N>, --out <directory> and --resume <directory> are for selfplay.
+    let games: Option<String> = take_option(&mut args, "--games");
+    let out_directory: String =
+        take_option(&mut args, "--out").unwrap_or_else(|| String::from("selfplay"));
+    let resume_directory: Option<String> = take_option(&mut args, "--resume");
+    // --threads <N> plays that many selfplay games at once, one per core by default.
+    let threads: usize = match take_option(&mut args, "--threads").map(|t| t.parse()) {
+        Some(Ok(threads)) => threads,
+        Some(Err(_)) => {
+            println!("--threads takes a number");
+            return;
+        }
+        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    // --random-plies <N> starts selfplay, tournament and balance games with N random moves, so engines don't repeat themselves.
+    let random_plies: usize = match take_option(&mut args, "--random-plies").map(|p| p.parse()) {
+        Some(Ok(plies)) => plies,
+        Some(Err(_)) => {
+            println!("--random-plies takes a number");
+            return;
+        }
+        None => RANDOM_PLIES,
+    };
+    // --rounds <N>, --openings <name,name>, --sprt <elo0,elo1[,alpha,beta]>, --json <file> and --gauntlet are for tournament.
+    let rounds: Option<String> = take_option(&mut args, "--rounds");
+    let openings: Option<String> = take_option(&mut args, "--openings");
+    let sprt: Option<String> = take_option(&mut args, "--sprt");
+    let json_file: String =
+        take_option(&mut args, "--json").unwrap_or_else(|| String::from("tournament.json"));
+    let gauntlet: bool = take_flag(&mut args, "--gauntlet");
+    // --replace <piece>, --engine <spec> and --write are for balance, which also takes --games, --openings and --threads.
+    let replace: Option<String> = take_option(&mut args, "--replace");
+    let engine_spec: Option<String> = take_option(&mut args, "--engine");
+    let write_values: bool = take_flag(&mut args, "--write");
+    // --tb <directory> is where tablebase saves its tables and search looks them up.
+    let tb_directory: Option<String> = take_option(&mut args, "--tb");
+    // --book-plies <N> is how deep into each game build-book goes.
+    let book_plies: usize = match take_option(&mut args, "--book-plies").map(|p| p.parse()) {
+        Some(Ok(plies)) => plies,
+        Some(Err(_)) => {
+            println!("--book-plies takes a number");
+            return;
+        }
+        None => 20,
+    };
+    // --mate <N> is the stipulation for solve, mate in N moves.
+    let mate_in: Option<String> = take_option(&mut args, "--mate");
+    // --time <control> puts play and tui games on the clock, like 5+3 or 40/90+30,30+30.
+    let time_control: Option<TimeControl> =
+        match take_option(&mut args, "--time").map(|t| TimeControl::parse(&t)) {
+            Some(Ok(control)) => Some(control),
+            Some(Err(why)) => {
+                println!("--time: {}", why);
+                return;
+            }
+            None => None,
+        };
+    match args.get(1).map(|s| s.as_str()) {
+        // perft <depth> [position name or FEN], divide takes the same arguments.
+        Some(command @ ("perft" | "divide")) => {
+            let depth: u32 = match args.get(2).map(|d| d.parse()) {
+                Some(Ok(depth)) => depth,
+                _ => {
+                    println!("Usage: {} <depth> [position name or FEN]", command);
+                    return;
+                }
+            };
+            let position: String = if args.len() > 3 {
+                args[3..].join(" ")
+            } else {
+                String::from("standard")
+            };
+            let mut game: Game = match setup_game(
+                game_counter,
+                &position,
+                piece_symbol_map,
+                &position_name_map,
+            ) {
+                Ok(game) => game,
+                Err(why) => {
+                    println!("Couldn't set up {}: {}", position, why);
+                    return;
+                }
+            };
+            if command == "divide" {
+                print!("{}", format_divide(&divide(&mut game, depth)));
+            } else {
+                println!("Nodes searched: {}", perft(&mut game, depth));
+            }
+        }
+        // search <depth> [position name or FEN]
+        Some("search") => {
+            let depth: u32 = match args.get(2).map(|d| d.parse()) {
+                Some(Ok(depth)) => depth,
+                _ => {
+                    println!("Usage: search <depth> [position name or FEN]");
+                    return;
+                }
+            };
+            let position: String = if args.len() > 3 {
+                args[3..].join(" ")
+            } else {
+                String::from("standard")
+            };
+            let mut game: Game = match setup_game(
+                game_counter,
+                &position,
+                piece_symbol_map,
+                &position_name_map,
+            ) {
+                Ok(game) => game,
+                Err(why) => {
+                    println!("Couldn't set up {}: {}", position, why);
+                    return;
+                }
+            };
+            let limits: SearchLimits = SearchLimits {
+                depth: Some(depth),
+                ..SearchLimits::default()
+            };
+            let mut searcher: Searcher = Searcher::new();
+            searcher.table = TranspositionTable::new(hash_megabytes, replacement);
+            searcher.evaluator = match evaluator_by_name(&evaluator_name, &mut game) {
+                Ok(evaluator) => evaluator,
+                Err(why) => {
+                    println!("{}", why);
+                    return;
+                }
+            };
+            println!("info string evaluator {}", searcher.evaluator.name());
+            if let Some(directory) = &tb_directory {
+                match Tablebases::open(std::path::Path::new(directory), &game.piece_symbol_map) {
+                    Ok(tablebases) => {
+                        println!("info string {} tablebases", tablebases.len());
+                        searcher.tablebases = Some(Arc::new(tablebases));
+                    }
+                    Err(why) => println!("info string no tablebases: {}", why),
+                }
+            }
+            let result: SearchResult = searcher.search(&mut game, limits, &mut |info| {
+                println!("{}", format_info(info))
+            });
+            println!("{}", format_table_stats(&result.table));
+            match result.best_move {
+                Some(best_move) => println!("bestmove {}", best_move.coordinate_notation()),
+                None => println!("bestmove (none)"),
+            }
+        }
+        // mcts <iterations> [position name or FEN], with --playout and --eval.
+        Some("mcts") => {
+            let iterations: u64 = match args.get(2).map(|d| d.parse()) {
+                Some(Ok(iterations)) => iterations,
+                _ => {
+                    println!("Usage: mcts <iterations> [position name or FEN]");
+                    return;
+                }
+            };
+            let position: String = if args.len() > 3 {
+                args[3..].join(" ")
+            } else {
+                String::from("standard")
+            };
+            let mut game: Game = match setup_game(
+                game_counter,
+                &position,
+                piece_symbol_map,
+                &position_name_map,
+            ) {
+                Ok(game) => game,
+                Err(why) => {
+                    println!("Couldn't set up {}: {}", position, why);
+                    return;
+                }
+            };
+            let limits: MctsLimits = MctsLimits {
+                iterations: Some(iterations),
+                ..MctsLimits::default()
+            };
+            let mut mcts: Mcts = Mcts::new(None);
+            mcts.evaluator = match evaluator_by_name(&evaluator_name, &mut game) {
+                Ok(evaluator) => evaluator,
+                Err(why) => {
+                    println!("{}", why);
+                    return;
+                }
+            };
+            mcts.playout = playout;
+            println!("info string evaluator {}", mcts.evaluator.name());
+            let result: MctsResult = mcts.search(&mut game, limits);
+            println!("{}", format_mcts_info(&result));
+            match result.best_move {
+                Some(best_move) => println!("bestmove {}", best_move.coordinate_notation()),
+                None => println!("bestmove (none)"),
+            }
+        }
+        // Talk UCI on stdin and stdout until told to quit.
+        Some("uci") => {
+            let output: SharedOutput = Arc::new(Mutex::new(std::io::stdout()));
+            match UciEngine::new("pieces.json", "startingPositions.json", output) {
+                Ok(mut engine) => engine.run(std::io::stdin().lock()),
+                Err(why) => println!("Couldn't start UCI mode: {}", why),
+            }
+        }
+        // Talk the XBoard/WinBoard protocol on stdin and stdout.
+        Some("xboard") => {
+            let output: SharedOutput = Arc::new(Mutex::new(std::io::stdout()));
+            match XBoardEngine::new("pieces.json", "startingPositions.json", output) {
+                Ok(mut engine) => engine.run(std::io::stdin().lock()),
+                Err(why) => println!("Couldn't start XBoard mode: {}", why),
+            }
+        }
+        // play [position name or FEN]. Two humans get the REPL, anything else plays a Match.
+        Some("play") => {
+            let output: SharedOutput = Arc::new(Mutex::new(std::io::stdout()));
+            // Colours and glyphs only when someone is looking, so piping the output stays readable.
+            let renderer: Renderer = if std::io::stdout().is_terminal() {
+                Renderer::terminal()
+            } else {
+                Renderer::default()
+            };
+            if white_spec == "human" && black_spec == "human" {
+                let mut repl: Repl =
+                    match Repl::new("pieces.json", "startingPositions.json", output) {
+                        Ok(repl) => repl,
+                        Err(why) => {
+                            println!("Couldn't start a game: {}", why);
+                            return;
+                        }
+                    };
+                repl.renderer = renderer;
+                if args.len() > 2 {
+                    let position: String = args[2..].join(" ");
+                    if let Err(why) = repl.set_position(&position) {
+                        println!("Couldn't set up {}: {}", position, why);
+                        return;
+                    }
+                }
+                if let Some(control) = time_control {
+                    repl.set_time_control(control);
+                }
+                repl.run(std::io::stdin().lock());
+                if let Some(prefix) = export_prefix {
+                    match export_logs(&[repl.recorder.log.clone()], &piece_list, &prefix) {
+                        Ok(files) => println!("Wrote {}", files.join(", ")),
+                        Err(why) => println!("Couldn't export to {}: {}", prefix, why),
+                    }
+                }
+                return;
+            }
+            let position: String = if args.len() > 2 {
+                args[2..].join(" ")
+            } else {
+                String::from("standard")
+            };
+            let mut game: Game = match setup_game(
+                game_counter,
+                &position,
+                piece_symbol_map,
+                &position_name_map,
+            ) {
+                Ok(game) => game,
+                Err(why) => {
+                    println!("Couldn't set up {}: {}", position, why);
+                    return;
+                }
+            };
+            let mut players: Vec<Box<dyn Player>> = Vec::new();
+            for spec in [&white_spec, &black_spec] {
+                match player_from_spec(spec, &mut game, &evaluator_name, output.clone()) {
+                    Ok(player) => players.push(player),
+                    Err(why) => {
+                        println!("{}", why);
+                        return;
+                    }
+                }
+            }
+            let black: Box<dyn Player> = players.pop().unwrap();
+            let white: Box<dyn Player> = players.pop().unwrap();
+            println!("{} (White) vs {} (Black)", white.name(), black.name());
+            let mut played: Match = if position_name_map.contains_key(&position) {
+                match Match::from_position(
+                    white,
+                    black,
+                    game_counter,
+                    &position,
+                    &position_name_map,
+                    game.piece_symbol_map.clone(),
+                ) {
+                    Ok(played) => played,
+                    Err(why) => {
+                        println!("Couldn't set up {}: {}", position, why);
+                        return;
+                    }
+                }
+            } else {
+                Match::new(white, black, game)
+            };
+            if let Some(control) = time_control {
+                played.game.start_clocks(control);
+            }
+            let ending: Ending = played.play(&mut |game, san| {
+                let mover: char = opponent(game.active_color);
+                println!("{} plays {}", player_name(mover), san);
+            });
+            println!("{}", renderer.render(&played.game));
+            println!("Game over: {} {{{}}}", ending.result(), ending.reason());
+            if let Some(prefix) = export_prefix {
+                match export_logs(&[played.recorder.log.clone()], &piece_list, &prefix) {
+                    Ok(files) => println!("Wrote {}", files.join(", ")),
+                    Err(why) => println!("Couldn't export to {}: {}", prefix, why),
+                }
+            }
+        }
+        Some("tui") => {
+            let position: String = if args.len() > 2 {
+                args[2..].join(" ")
+            } else {
+                String::from("standard")
+            };
+            let mut game: Game = match setup_game(
+                game_counter,
+                &position,
+                piece_symbol_map,
+                &position_name_map,
+            ) {
+                Ok(game) => game,
+                Err(why) => {
+                    println!("Couldn't set up {}: {}", position, why);
+                    return;
+                }
+            };
+            let evaluator: Arc<dyn Evaluator> = match evaluator_by_name(&evaluator_name, &mut game)
+            {
+                Ok(evaluator) => evaluator,
+                Err(why) => {
+                    println!("{}", why);
+                    return;
+                }
+            };
+            let mut tui: Tui = Tui::new(game, evaluator);
+            tui.renderer.unicode = true;
+            if let Some(control) = time_control {
+                tui.game.start_clocks(control);
+            }
+            if let Err(why) = tui.run() {
+                println!("The terminal went wrong: {}", why);
+            }
+            if let Some(prefix) = export_prefix {
+                match export_logs(&[tui.recorder.log.clone()], &piece_list, &prefix) {
+                    Ok(files) => println!("Wrote {}", files.join(", ")),
+                    Err(why) => println!("Couldn't export to {}: {}", prefix, why),
+                }
+            }
+        }
+        // selfplay --games N [position name or FEN] with --white and --black, or selfplay --resume <directory>.
+        Some("selfplay") => {
+            let started: Result<SelfPlay, String> = match (&resume_directory, &games) {
+                // --games with --resume changes how many games the run is for.
+                (Some(directory), games) => {
+                    SelfPlay::resume(std::path::Path::new(directory)).and_then(|mut run| {
+                        match games.as_ref().map(|g| g.parse()) {
+                            Some(Ok(games)) => run.checkpoint.games = games,
+                            Some(Err(_)) => return Err(String::from("--games takes a number")),
+                            None => {}
+                        }
+                        Ok(run)
+                    })
+                }
+                (None, Some(games)) => match games.parse() {
+                    Ok(games) => SelfPlay::start(
+                        std::path::Path::new(&out_directory),
+                        Checkpoint {
+                            games,
+                            white: white_spec,
+                            black: black_spec,
+                            position: if args.len() > 2 {
+                                args[2..].join(" ")
+                            } else {
+                                String::from("standard")
+                            },
+                            evaluator: evaluator_name,
+                            time_control: time_control.map(|c| c.to_pgn()),
+                            random_plies,
+                            completed: 0,
+                            plies_bytes: 0,
+                            games_bytes: 0,
+                        },
+                    ),
+                    Err(_) => Err(String::from("--games takes a number")),
+                },
+                (None, None) => Err(String::from(
+                    "Usage: selfplay --games N [position] --white PLAYER --black PLAYER [--out DIRECTORY], or selfplay --resume DIRECTORY",
+                )),
+            };
+            let mut run: SelfPlay = match started {
+                Ok(run) => run,
+                Err(why) => {
+                    println!("{}", why);
+                    return;
+                }
+            };
+            if let Err(why) = run.handle_interrupts() {
+                println!("Couldn't catch Ctrl-C: {}", why);
+            }
+            println!(
+                "{} (White) vs {} (Black), game {} of {}, logging to {}",
+                run.checkpoint.white,
+                run.checkpoint.black,
+                run.checkpoint.completed + 1,
+                run.checkpoint.games,
+                run.directory.display()
+            );
+            let finished: Result<(), String> = run.run(
+                &mut game_counter,
+                threads,
+                &piece_symbol_map,
+                &position_name_map,
+                &mut |log| {
+                    println!(
+                        "Game {}: {} {{{}}} in {} plies",
+                        log.game_id,
+                        log.result.as_deref().unwrap_or("*"),
+                        log.reason.as_deref().unwrap_or(""),
+                        log.plies.len()
+                    );
+                },
+            );
+            if let Err(why) = finished {
+                println!("Self-play stopped: {}", why);
+            }
+            if run.checkpoint.completed < run.checkpoint.games {
+                println!(
+                    "Stopped after {} of {} games, carry on with selfplay --resume {}",
+                    run.checkpoint.completed,
+                    run.checkpoint.games,
+                    run.directory.display()
+                );
+            }
+        }
+        // tournament [name=]spec [name=]spec ..., each spec an engine like engine:depth=3,eval=mobility.
+        Some("tournament") => {
+            let entrants: Vec<Entrant> = args[2..].iter().map(|a| Entrant::parse(a)).collect();
+            // Every position in startingPositions.json unless told otherwise.
+            let openings: Vec<String> = match &openings {
+                Some(names) => names.split(',').map(String::from).collect(),
+                None => {
+                    let mut names: Vec<String> = position_name_map.keys().cloned().collect();
+                    names.sort();
+                    names
+                }
+            };
+            if let Some(unknown) = openings
+                .iter()
+                .find(|o| !position_name_map.contains_key(*o))
+            {
+                println!(
+                    "There's no position called {} in startingPositions.json",
+                    unknown
+                );
+                return;
+            }
+            let mut tournament: Tournament = match Tournament::new(entrants, openings) {
+                Ok(tournament) => tournament,
+                Err(why) => {
+                    println!("{}", why);
+                    println!("Usage: tournament [--gauntlet] [--rounds N] [--openings a,b] [--sprt elo0,elo1[,alpha,beta]] [--json FILE] name=spec name=spec ...");
+                    return;
+                }
+            };
+            tournament.gauntlet = gauntlet;
+            tournament.evaluator = evaluator_name;
+            tournament.control = time_control;
+            tournament.random_plies = random_plies;
+            match rounds.map(|r| r.parse()) {
+                Some(Ok(rounds)) => tournament.rounds = rounds,
+                Some(Err(_)) => {
+                    println!("--rounds takes a number");
+                    return;
+                }
+                None => {}
+            }
+            match sprt.map(|s| Sprt::parse(&s)) {
+                Some(Ok(test)) => tournament.sprt = Some(test),
+                Some(Err(why)) => {
+                    println!("--sprt: {}", why);
+                    return;
+                }
+                None => {}
+            }
+            if let Err(why) = catch_interrupts(tournament.stop.clone(), tournament.abandon.clone())
+            {
+                println!("Couldn't catch Ctrl-C: {}", why);
+            }
+            println!("{} games scheduled", tournament.schedule().len());
+            let results: TournamentResults = match tournament.run(
+                threads,
+                &piece_symbol_map,
+                &position_name_map,
+                &mut |game| {
+                    println!(
+                        "Game {} ({}): {} - {} {} {{{}}}",
+                        game.id, game.opening, game.white, game.black, game.result, game.reason
+                    );
+                },
+            ) {
+                Ok(results) => results,
+                Err(why) => {
+                    println!("The tournament stopped: {}", why);
+                    return;
+                }
+            };
+            print!("{}", results.cross_table());
+            match std::fs::write(&json_file, results.to_json()) {
+                Ok(()) => println!("Wrote {}", json_file),
+                Err(why) => println!("Couldn't write {}: {}", json_file, why),
+            }
+        }
+        // balance [piece name ...], by default every piece in pieces.json without a value.
+        Some("balance") => {
+            let mut balance: Balance = Balance::new(match &openings {
+                Some(names) => names.split(',').map(String::from).collect(),
+                None => vec![String::from("standard")],
+            });
+            balance.evaluator = evaluator_name;
+            balance.threads = threads;
+            balance.random_plies = random_plies;
+            if let Some(spec) = engine_spec {
+                balance.engine = spec;
+            }
+            match games.map(|g| g.parse()) {
+                Some(Ok(games)) => balance.games = games,
+                Some(Err(_)) => {
+                    println!("--games takes a number");
+                    return;
+                }
+                None => {}
+            }
+            let pieces: Vec<&PieceType> = if args.len() > 2 {
+                match args[2..]
+                    .iter()
+                    .map(|name| {
+                        piece_list
+                            .pieces
+                            .iter()
+                            .find(|p| &p.name == name)
+                            .ok_or(name)
+                    })
+                    .collect()
+                {
+                    Ok(pieces) => pieces,
+                    Err(name) => {
+                        println!("There's no piece called {} in pieces.json", name);
+                        return;
+                    }
+                }
+            } else {
+                piece_list
+                    .pieces
+                    .iter()
+                    .filter(|p| !p.royal && p.value.is_none())
+                    .collect()
+            };
+            if pieces.is_empty() {
+                println!("Every piece in pieces.json has a value, name the ones to measure again.");
+                return;
+            }
+            if let Err(why) = catch_interrupts(balance.stop.clone(), balance.abandon.clone()) {
+                println!("Couldn't catch Ctrl-C: {}", why);
+            }
+            for piece in pieces {
+                let replaced: Option<&PieceType> = match &replace {
+                    Some(name) => piece_list.pieces.iter().find(|p| &p.name == name),
+                    None => balance.replaceable(piece, &piece_list, &position_name_map),
+                };
+                let Some(replaced) = replaced else {
+                    println!(
+                        "Nothing to swap {} in for, pick a piece with --replace",
+                        piece.name
+                    );
+                    continue;
+                };
+                println!(
+                    "Measuring {} against {} with {} games a trial",
+                    piece.name, replaced.name, balance.games
+                );
+                let estimate: Estimate =
+                    match balance.estimate(piece, replaced, &piece_list, &position_name_map) {
+                        Ok(estimate) => estimate,
+                        Err(why) => {
+                            println!("Couldn't measure {}: {}", piece.name, why);
+                            continue;
+                        }
+                    };
+                print!("{}", estimate.report());
+                if let (true, Some(value)) = (write_values, estimate.value) {
+                    match write_piece_value(std::path::Path::new("pieces.json"), &piece.name, value)
+                    {
+                        Ok(()) => {
+                            println!("  Wrote value {} for {} to pieces.json", value, piece.name)
+                        }
+                        Err(why) => println!("  Couldn't write to pieces.json: {}", why),
+                    }
+                }
+                if balance.stop.load(std::sync::atomic::Ordering::SeqCst) {
+                    break;
+                }
+            }
+        }
+        Some("summarize") => {
+            let Some(path) = args.get(2) else {
+                println!("Usage: summarize <directory of .pgn and exported .csv files>");
+                return;
+            };
+            let mut game: Game = match setup_game(
+                game_counter,
+                "standard",
+                piece_symbol_map.clone(),
+                &position_name_map,
+            ) {
+                Ok(game) => game,
+                Err(why) => {
+                    println!("Couldn't set up standard: {}", why);
+                    return;
+                }
+            };
+            let evaluator: Arc<dyn Evaluator> = match evaluator_by_name(&evaluator_name, &mut game)
+            {
+                Ok(evaluator) => evaluator,
+                Err(why) => {
+                    println!("{}", why);
+                    return;
+                }
+            };
+            match summarize(
+                std::path::Path::new(path),
+                &piece_symbol_map,
+                &position_name_map,
+                evaluator.as_ref(),
+            ) {
+                Ok(summary) => print!("{}", summary.report()),
+                Err(why) => println!("Couldn't summarize {}: {}", path, why),
+            }
+        }
+        // build-book <book file> <directory or file> ..., from saved PGNs and self-play or exported logs.
+        Some("build-book") => {
+            if args.len() < 4 {
+                println!("Usage: build-book [--book-plies N] <book file> <directory or file> ...");
+                return;
+            }
+            let mut logs: Vec<GameLog> = Vec::new();
+            for path in &args[3..] {
+                match read_games(
+                    std::path::Path::new(path),
+                    &piece_symbol_map,
+                    &position_name_map,
+                ) {
+                    Ok(files) => {
+                        for (name, read) in files {
+                            match read {
+                                Ok(read) => logs.extend(read),
+                                Err(why) => println!("Skipped {}: {}", name, why),
+                            }
+                        }
+                    }
+                    Err(why) => println!("Skipped {}: {}", path, why),
+                }
+            }
+            let standard_fen: &str = position_name_map.get("standard").map_or("", |s| s.as_str());
+            let (book, problems) =
+                VariantBook::build(&logs, &piece_symbol_map, standard_fen, book_plies);
+            for problem in problems {
+                println!("Skipped {}", problem);
+            }
+            match book.save(std::path::Path::new(&args[2])) {
+                Ok(()) => println!(
+                    "Wrote {} positions from {} games to {}",
+                    book.positions.len(),
+                    book.games,
+                    args[2]
+                ),
+                Err(why) => println!("Couldn't write {}: {}", args[2], why),
+            }
+        }
+        // tablebase <ending> ..., like KQvK, along with any tables it needs. Saved in --tb, tablebases by default.
+        Some("tablebase") => {
+            if args.len() < 3 {
+                println!("Usage: tablebase [--tb DIRECTORY] <ending like KQvK> ...");
+                return;
+            }
+            let directory: String = tb_directory.unwrap_or_else(|| String::from("tablebases"));
+            let mut tablebases: Tablebases =
+                match Tablebases::open(std::path::Path::new(&directory), &piece_symbol_map) {
+                    Ok(tablebases) => tablebases,
+                    Err(why) => {
+                        println!("Couldn't open {}: {}", directory, why);
+                        return;
+                    }
+                };
+            for skipped in &tablebases.skipped {
+                println!("Ignoring {}", skipped);
+            }
+            let standard_fen: &str = position_name_map.get("standard").map_or("", |s| s.as_str());
+            for name in &args[2..] {
+                let endgame: Endgame = match Endgame::parse(name, &piece_symbol_map) {
+                    Ok(endgame) => endgame,
+                    Err(why) => {
+                        println!("{}", why);
+                        continue;
+                    }
+                };
+                let mut started: std::time::Instant = std::time::Instant::now();
+                let made: Result<(), String> =
+                    tablebases.generate(&endgame, &piece_symbol_map, standard_fen, &mut |table| {
+                        println!(
+                            "Made {} in {:.1}s",
+                            table.endgame.name(),
+                            started.elapsed().as_secs_f64()
+                        );
+                        started = std::time::Instant::now();
+                    });
+                if let Err(why) = made {
+                    println!("Couldn't make {}: {}", name, why);
+                    continue;
+                }
+                if let Some(table) = tablebases.get(&endgame) {
+                    print!("{}", table.report());
+                }
+            }
+        }
+        // solve --mate <N> [position name or FEN], finds every key that forces mate in N and checks the problem for cooks.
+        Some("solve") => {
+            let mate_in: u32 = match mate_in.map(|n| n.parse()) {
+                Some(Ok(moves)) if moves > 0 => moves,
+                _ => {
+                    println!("Usage: solve --mate <N> [position name or FEN]");
+                    return;
+                }
+            };
+            let position: String = if args.len() > 2 {
+                args[2..].join(" ")
+            } else {
+                String::from("standard")
+            };
+            let mut game: Game = match setup_game(
+                game_counter,
+                &position,
+                piece_symbol_map,
+                &position_name_map,
+            ) {
+                Ok(game) => game,
+                Err(why) => {
+                    println!("Couldn't set up {}: {}", position, why);
+                    return;
+                }
+            };
+            let started: std::time::Instant = std::time::Instant::now();
+            let solution: Solution = MateSolver::new().solve(&mut game, mate_in);
+            print!("{}", solution.report());
+            println!("Solved in {:.1}s", started.elapsed().as_secs_f64());
+        }
+        Some("pieces") => piece_list_console_diagnostics(&piece_list),
+        _ => {
+            println!("Hello, world!");
+            // Initialize a game.
+            let game: Game = Game::new(
+                game_counter,
+                String::from("standard"),
+                piece_symbol_map,
+                position_name_map,
+            );
+            // Fill a HashMap with the piece IDs and references to the pieces.
+            let piece_id_map: HashMap<u8, &Piece> = game.map_pieces_to_ids();
+            println!("Game {}", game.id);
+            println!("Piece ID Map");
+            println!("{}", Renderer::default().render_ids(&game));
+            println!("Current Board");
+            println!("{}", Renderer::default().render(&game));
+            println!(
+                "White pieces   {}",
+                game.generate_white_piece_bitmap(&piece_id_map)
+            );
+            println!(
+                "Black pieces   {}",
+                game.generate_black_piece_bitmap(&piece_id_map)
+            );
+            println!("Enemy attacks  {}", game.enemy_attack_bitmap());
+        }
+    }
+}
+
+// Start a game from either the name of a position in startingPositions.json or a FEN.
+// FENs are compared against the standard position to work out which pieces have moved.
+fn setup_game<'a>(
+    game_id: u64,
+    position: &str,
+    piece_symbol_map: HashMap<char, &'a PieceType>,
+    position_name_map: &HashMap<String, String>,
+) -> Result<Game<'a>, String> {
+    match position_name_map.get(position) {
+        Some(fen) => Game::from_fen(game_id, fen, fen, piece_symbol_map),
+        None => Game::from_fen(
+            game_id,
+            position,
+            position_name_map
+                .get("standard")
+                .map_or(position, |s| s.as_str()),
+            piece_symbol_map,
+        ),
+    }
+}
+
+// Remove "name value" from the arguments wherever it is and return the value.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index: usize = args.iter().position(|a| a == name)?;
+    if index + 1 >= args.len() {
+        args.remove(index);
+        return None;
+    }
+    let value: String = args.remove(index + 1);
+    args.remove(index);
+    Some(value)
+}
+
+// Remove a flag from the arguments wherever it is and say whether it was there.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let before: usize = args.len();
+    args.retain(|a| a != name);
+    args.len() != before
 }
 
 // Debugging Functions
 
-fn piece_list_console_diagnostics(piece_list: PieceList) {
-    for i in piece_list.pieces {
+fn piece_list_console_diagnostics(piece_list: &PieceList) {
+    for i in &piece_list.pieces {
         println!("{}", i.name.to_uppercase());
         println!("{}", i.white_id);
         println!("{}", i.black_id);
-        for j in i.moveset {
+        for j in &i.moveset {
             println!("Move ID {}", j.id);
             println!("Translation {} {}", j.translation.0, j.translation.1);
             println!("Moves? {}", j.moves);
             println!("Captures? {}", j.captures);
             println!("Requires Previous move? {}", j.piece_makes_previous_move);
             if j.piece_makes_previous_move {
-                println!("What is the previous move? {}", j.previous_move.unwrap());
+                println!(
+                    "What is the previous move? {}",
+                    j.previous_move.as_ref().unwrap()
+                );
             }
             println!("Conditional based on position? {}", j.requires_target_piece);
             if j.requires_target_piece {
@@ -95,7 +1006,7 @@ fn piece_list_console_diagnostics(piece_list: PieceList) {
                 );
                 println!(
                     "EnPassant target piece previous move: {}",
-                    j.enpassant_target_piece_previous_move.unwrap()
+                    j.enpassant_target_piece_previous_move.as_ref().unwrap()
                 );
             }
             println!("--");
@@ -106,4 +1017,4 @@ fn piece_list_console_diagnostics(piece_list: PieceList) {
     }
 }
 
-const BOARDSIZE: u8 = 8;
\ No newline at end of file
+const BOARDSIZE: u8 = 8;
@@ -0,0 +1,202 @@
+use crate::configuration::*;
+use crate::game::*;
+use crate::player::*;
+use crate::recorder::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How a match ended. Rules covers everything Game::outcome knows about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ending {
+    Rules(Outcome),
+    // The player who gave up, 'w' or 'b'.
+    Resigned(char),
+    // Called a draw after Match.max_plies.
+    MoveLimit,
+    // Stopped from outside before it was over, see Match.abandon.
+    Abandoned,
+}
+
+impl Ending {
+    pub fn result(&self) -> &'static str {
+        match self {
+            Ending::Rules(outcome) => outcome.result(),
+            Ending::Resigned('w') => "0-1",
+            Ending::Resigned(_) => "1-0",
+            Ending::MoveLimit => "1/2-1/2",
+            Ending::Abandoned => "*",
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Ending::Rules(outcome) => outcome.reason(),
+            Ending::Resigned('w') => "White resigns",
+            Ending::Resigned(_) => "Black resigns",
+            Ending::MoveLimit => "Move limit reached",
+            Ending::Abandoned => "Abandoned",
+        }
+    }
+}
+
+// Two players and the game between them. Any mix of humans and engines works.
+pub struct Match<'a> {
+    pub white: Box<dyn Player<'a> + 'a>,
+    pub black: Box<dyn Player<'a> + 'a>,
+    pub game: Game<'a>,
+    // Plies after which the game is called a draw, for engine games that would otherwise shuffle forever.
+    pub max_plies: Option<usize>,
+    // Every move goes through here, so the log has the whole game once play returns.
+    pub recorder: Recorder,
+    // Checked before every move. Once it's set the game stops there, for shutting down part way through.
+    pub abandon: Option<Arc<AtomicBool>>,
+}
+
+impl<'a> Match<'a> {
+    pub fn new(
+        white: Box<dyn Player<'a> + 'a>,
+        black: Box<dyn Player<'a> + 'a>,
+        game: Game<'a>,
+    ) -> Match<'a> {
+        let recorder: Recorder = Recorder::new(&white.name(), &black.name(), &game);
+        Match {
+            white,
+            black,
+            game,
+            max_plies: None,
+            recorder,
+            abandon: None,
+        }
+    }
+
+    // Start from a named position in startingPositions.json.
+    pub fn from_position(
+        white: Box<dyn Player<'a> + 'a>,
+        black: Box<dyn Player<'a> + 'a>,
+        game_id: u64,
+        position: &str,
+        position_name_map: &HashMap<String, String>,
+        piece_symbol_map: HashMap<char, &'a PieceType>,
+    ) -> Result<Match<'a>, String> {
+        let fen: &String = position_name_map
+            .get(position)
+            .ok_or_else(|| format!("no position called {}", position))?;
+        let game: Game<'a> = Game::from_fen(game_id, fen, fen, piece_symbol_map)?;
+        Ok(Match::new(white, black, game))
+    }
+
+    // Ask each side for moves in turn until the game ends. on_move hears about every move, in SAN, after it's played.
+    pub fn play(&mut self, on_move: &mut dyn FnMut(&Game<'a>, &str)) -> Ending {
+        let ending: Ending = self.play_moves(on_move);
+        self.recorder.finish(ending.result(), ending.reason());
+        ending
+    }
+
+    fn play_moves(&mut self, on_move: &mut dyn FnMut(&Game<'a>, &str)) -> Ending {
+        let mut plies: usize = 0;
+        loop {
+            if let Some(outcome) = self.game.outcome() {
+                return Ending::Rules(outcome);
+            }
+            if self.max_plies.is_some_and(|m| plies >= m) {
+                return Ending::MoveLimit;
+            }
+            if self
+                .abandon
+                .as_ref()
+                .is_some_and(|a| a.load(Ordering::SeqCst))
+            {
+                return Ending::Abandoned;
+            }
+            let mover: char = self.game.active_color;
+            let player: &mut Box<dyn Player<'a> + 'a> = if mover == 'w' {
+                &mut self.white
+            } else {
+                &mut self.black
+            };
+            let started: Instant = Instant::now();
+            let available_move: AvailableMove<'a> = match player.choose_move(&mut self.game) {
+                Some(available_move) => available_move,
+                None => return Ending::Resigned(mover),
+            };
+            let elapsed: Duration = started.elapsed();
+            // Thinking past the flag loses the game there and then, the move never gets played.
+            if !self.game.punch_clock(elapsed) {
+                continue;
+            }
+            let san: String = self.recorder.make_move(
+                &mut self.game,
+                available_move,
+                elapsed,
+                player.engine_state(),
+            );
+            plies += 1;
+            on_move(&self.game, &san);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::matches::*;
+    use crate::uci::*;
+    use std::io::Cursor;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn engine_beats_random_mover() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        // Two rooks mate well inside the search's horizon, wherever the random king goes.
+        let fen: &str = "4k3/8/8/8/8/8/8/R3K2R w - - 0 1";
+        let mut position_name_map: HashMap<String, String> = HashMap::new();
+        position_name_map.insert(String::from("rook ending"), String::from(fen));
+        let mut game: Game =
+            Game::from_fen(0, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let output: SharedOutput = Arc::new(Mutex::new(Vec::new()));
+        let white = player_from_spec("engine:depth=3", &mut game, "material", output.clone());
+        let black = player_from_spec("random:seed=5", &mut game, "material", output);
+        let mut played: Match = Match::from_position(
+            white.unwrap(),
+            black.unwrap(),
+            1,
+            "rook ending",
+            &position_name_map,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        played.max_plies = Some(200);
+        let mut moves: Vec<String> = Vec::new();
+        let ending: Ending = played.play(&mut |_, san| moves.push(String::from(san)));
+        // A mate, not the move limit or a draw by the rules.
+        assert_eq!((ending.result(), ending.reason()), ("1-0", "White mates"));
+        assert_eq!(moves.len(), played.game.list_of_moves.len());
+        assert!(played.white.name() == "engine:depth=3" && played.black.name() == "random:seed=5");
+    }
+
+    #[test]
+    fn human_moves_and_resigns() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let start: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let game: Game =
+            Game::from_fen(0, start, start, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let buffer: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+        let human: HumanPlayer =
+            HumanPlayer::new(Box::new(Cursor::new("e5\ne4\nresign\n")), buffer.clone());
+        let mut played: Match =
+            Match::new(Box::new(human), Box::new(RandomPlayer::new(Some(1))), game);
+        let ending: Ending = played.play(&mut |_, _| {});
+        assert_eq!(ending, Ending::Resigned('w'));
+        assert_eq!(ending.result(), "0-1");
+        assert_eq!(played.game.list_of_moves.len(), 2);
+        let output: String = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("e5 isn't a legal move here."));
+        // Black's reply came from the random mover, White saw the board again.
+        assert_eq!(output.matches("White to move.").count(), 2);
+    }
+}
@@ -0,0 +1,479 @@
+use crate::evaluation::*;
+use crate::game::*;
+use crate::random::*;
+use crate::search::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How moves are picked while playing a game out to the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playout {
+    // Any legal move, all equally likely.
+    Random,
+    // Captures before anything else, and the evaluator decides games that run too long.
+    Heuristic,
+}
+
+// Pick a playout by the name given on the command line or in an mcts spec.
+pub fn playout_by_name(name: &str) -> Result<Playout, String> {
+    match name {
+        "random" => Ok(Playout::Random),
+        "heuristic" => Ok(Playout::Heuristic),
+        _ => Err(format!(
+            "unknown playout {}, expected random or heuristic",
+            name
+        )),
+    }
+}
+
+// When to stop searching. Anything left as None doesn't limit the search.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MctsLimits {
+    pub iterations: Option<u64>,
+    pub movetime: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MctsResult<'a> {
+    pub best_move: Option<AvailableMove<'a>>,
+    // How often the best move won for the side to move, from 0 to 1.
+    pub win_rate: f64,
+    pub principal_variation: Vec<AvailableMove<'a>>,
+    // Iterations run by this search, and visits to the root counting those kept from earlier searches.
+    pub iterations: u64,
+    pub root_visits: u32,
+    pub elapsed: Duration,
+}
+
+struct Node<'a> {
+    // The move that leads here from the parent. None only at the root.
+    played: Option<AvailableMove<'a>>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // Legal moves without a child yet. None until the node is first reached.
+    untried: Option<Vec<AvailableMove<'a>>>,
+    visits: u32,
+    // Total reward for the player who played the move leading here.
+    reward: f64,
+    // Set once the game is known to be over here, to that same player's reward.
+    terminal: Option<f64>,
+}
+
+impl<'a> Node<'a> {
+    fn new(played: Option<AvailableMove<'a>>, parent: Option<usize>) -> Node<'a> {
+        Node {
+            played,
+            parent,
+            children: Vec::new(),
+            untried: None,
+            visits: 0,
+            reward: 0.0,
+            terminal: None,
+        }
+    }
+}
+
+// Monte Carlo tree search with UCT. Like Searcher it only sees the game through the move generator,
+// and it needs no evaluation at all with Random playouts, which suits variants nobody has tuned one for.
+pub struct Mcts<'a> {
+    // Higher explores more, lower sticks with what has won so far. sqrt(2) is the textbook value.
+    pub exploration: f64,
+    pub playout: Playout,
+    // Playouts that get this long are called a draw, or scored by material with Heuristic playouts.
+    pub max_playout_plies: usize,
+    // Set from another thread to make the search return as soon as it can. Cleared again by whoever set it.
+    pub stop: Arc<AtomicBool>,
+    // Only Heuristic playouts use it.
+    pub evaluator: Arc<dyn Evaluator>,
+    random: Random,
+    nodes: Vec<Node<'a>>,
+    root: usize,
+    // Which game and which moves the root stands for, so the tree can be picked up again on the next move.
+    root_game_id: u64,
+    root_history: Vec<MoveKey>,
+}
+
+impl<'a> Mcts<'a> {
+    // With a seed the same position and limits always give the same search.
+    pub fn new(seed: Option<u64>) -> Mcts<'a> {
+        Mcts {
+            exploration: std::f64::consts::SQRT_2,
+            playout: Playout::Random,
+            max_playout_plies: 200,
+            stop: Arc::new(AtomicBool::new(false)),
+            evaluator: Arc::new(Material),
+            random: match seed {
+                Some(seed) => Random::new(seed),
+                None => Random::from_time(),
+            },
+            nodes: Vec::new(),
+            root: 0,
+            root_game_id: 0,
+            root_history: Vec::new(),
+        }
+    }
+
+    pub fn root_visits(&self) -> u32 {
+        self.nodes.get(self.root).map_or(0, |n| n.visits)
+    }
+
+    pub fn search(&mut self, game: &mut Game<'a>, limits: MctsLimits) -> MctsResult<'a> {
+        let start: Instant = Instant::now();
+        match self.find_reusable_root(game) {
+            Some(node) => self.reroot(node),
+            None => {
+                self.nodes = vec![Node::new(None, None)];
+                self.root = 0;
+            }
+        }
+        self.root_game_id = game.id;
+        self.root_history = game.list_of_moves.iter().map(|r| r.played.key()).collect();
+        let mut iterations: u64 = 0;
+        loop {
+            if limits.iterations.is_some_and(|n| iterations >= n)
+                || limits.movetime.is_some_and(|t| start.elapsed() >= t)
+                || self.stop.load(Ordering::Relaxed)
+            {
+                break;
+            }
+            self.iterate(game);
+            iterations += 1;
+            // Nothing left to learn once the root is known to be over.
+            if self.nodes[self.root].terminal.is_some() {
+                break;
+            }
+        }
+        let mut principal_variation: Vec<AvailableMove<'a>> = Vec::new();
+        let mut node: usize = self.root;
+        while let Some(child) = self.most_visited_child(node) {
+            principal_variation.push(self.nodes[child].played.unwrap());
+            node = child;
+        }
+        let best: Option<usize> = self.most_visited_child(self.root);
+        MctsResult {
+            best_move: principal_variation.first().copied(),
+            win_rate: best.map_or(0.0, |b| self.nodes[b].reward / self.nodes[b].visits as f64),
+            principal_variation,
+            iterations,
+            root_visits: self.root_visits(),
+            elapsed: start.elapsed(),
+        }
+    }
+
+    // One round of selection, expansion, playout and backpropagation.
+    fn iterate(&mut self, game: &mut Game<'a>) {
+        let mut node: usize = self.root;
+        let mut depth: usize = 0;
+        loop {
+            if self.nodes[node].terminal.is_some() {
+                break;
+            }
+            if self.nodes[node].untried.is_none() {
+                if let Some(reward) = self.game_over(game) {
+                    self.nodes[node].terminal = Some(reward);
+                    break;
+                }
+                self.nodes[node].untried = Some(game.scan_available_moves());
+            }
+            let untried: &mut Vec<AvailableMove<'a>> = self.nodes[node].untried.as_mut().unwrap();
+            if !untried.is_empty() {
+                let index: usize = self.random.below(untried.len());
+                let available_move: AvailableMove<'a> = untried.swap_remove(index);
+                game.make_move(available_move);
+                depth += 1;
+                let child: usize = self.nodes.len();
+                self.nodes.push(Node::new(Some(available_move), Some(node)));
+                self.nodes[node].children.push(child);
+                node = child;
+                break;
+            }
+            if self.nodes[node].children.is_empty() {
+                // No legal moves at all: checkmate or stalemate.
+                let reward: f64 = if game.royal_in_check(game.active_color) {
+                    1.0
+                } else {
+                    0.5
+                };
+                self.nodes[node].terminal = Some(reward);
+                break;
+            }
+            node = self.select_child(node);
+            game.make_move(self.nodes[node].played.unwrap());
+            depth += 1;
+        }
+        let mut reward: f64 = match self.nodes[node].terminal {
+            Some(reward) => reward,
+            None => self.play_out(game),
+        };
+        for _ in 0..depth {
+            game.unmake_move();
+        }
+        let mut current: usize = node;
+        loop {
+            self.nodes[current].visits += 1;
+            self.nodes[current].reward += reward;
+            match self.nodes[current].parent {
+                Some(parent) => {
+                    reward = 1.0 - reward;
+                    current = parent;
+                }
+                None => break,
+            }
+        }
+    }
+
+    // The child with the best upper confidence bound.
+    fn select_child(&self, node: usize) -> usize {
+        let log_visits: f64 = (self.nodes[node].visits.max(1) as f64).ln();
+        let mut best: usize = self.nodes[node].children[0];
+        let mut best_bound: f64 = f64::NEG_INFINITY;
+        for &child in &self.nodes[node].children {
+            let visits: f64 = self.nodes[child].visits.max(1) as f64;
+            let bound: f64 =
+                self.nodes[child].reward / visits + self.exploration * (log_visits / visits).sqrt();
+            if bound > best_bound {
+                best_bound = bound;
+                best = child;
+            }
+        }
+        best
+    }
+
+    fn most_visited_child(&self, node: usize) -> Option<usize> {
+        self.nodes[node]
+            .children
+            .iter()
+            .copied()
+            .filter(|&c| self.nodes[c].visits > 0)
+            .max_by_key(|&c| self.nodes[c].visits)
+    }
+
+    // Draws by the fifty move rule or threefold repetition, from the point of view of the player who just moved.
+    fn game_over(&self, game: &Game) -> Option<f64> {
+        if game.halfmove_clock >= 100 || game.repetitions() >= 2 {
+            Some(0.5)
+        } else {
+            None
+        }
+    }
+
+    // Play the game out and return the reward for the player who made the last move before the playout started.
+    fn play_out(&mut self, game: &mut Game<'a>) -> f64 {
+        let perspective: char = opponent(game.active_color);
+        let mut plies: usize = 0;
+        let reward: f64 = loop {
+            if let Some(reward) = self.game_over(game) {
+                // game_over speaks for whoever just moved.
+                break if game.active_color == perspective {
+                    1.0 - reward
+                } else {
+                    reward
+                };
+            }
+            if plies >= self.max_playout_plies {
+                break match self.playout {
+                    Playout::Random => 0.5,
+                    Playout::Heuristic => {
+                        // Squash the material count into a win probability.
+                        let score: f64 = self.evaluator.evaluate(game) as f64;
+                        let for_side_to_move: f64 = 1.0 / (1.0 + (-score / 400.0).exp());
+                        if game.active_color == perspective {
+                            for_side_to_move
+                        } else {
+                            1.0 - for_side_to_move
+                        }
+                    }
+                };
+            }
+            if self.play_random_move(game) {
+                plies += 1;
+                continue;
+            }
+            break if !game.royal_in_check(game.active_color) {
+                0.5
+            } else if game.active_color == perspective {
+                0.0
+            } else {
+                1.0
+            };
+        };
+        for _ in 0..plies {
+            game.unmake_move();
+        }
+        reward
+    }
+
+    // Make a random legal move and return true, or return false if there isn't one.
+    // Pseudo-legal moves are tried in random order, which is far cheaper than generating every legal move first.
+    fn play_random_move(&mut self, game: &mut Game<'a>) -> bool {
+        let mover: char = game.active_color;
+        let mut moves: Vec<AvailableMove<'a>> = game.scan_pseudo_legal_moves();
+        let mut captures: Vec<AvailableMove<'a>> = Vec::new();
+        if self.playout == Playout::Heuristic {
+            let (taking, quiet): (Vec<AvailableMove<'a>>, Vec<AvailableMove<'a>>) =
+                moves.into_iter().partition(|m| m.is_capture());
+            captures = taking;
+            moves = quiet;
+        }
+        for candidates in [&mut captures, &mut moves] {
+            while !candidates.is_empty() {
+                let index: usize = self.random.below(candidates.len());
+                let available_move: AvailableMove<'a> = candidates.swap_remove(index);
+                game.make_move(available_move);
+                let left_in_check: bool = if mover == 'w' {
+                    game.white_check
+                } else {
+                    game.black_check
+                };
+                if !left_in_check {
+                    return true;
+                }
+                game.unmake_move();
+            }
+        }
+        false
+    }
+
+    // The node standing for the game as it is now, if the moves played since the last search are in the tree.
+    fn find_reusable_root(&self, game: &Game<'a>) -> Option<usize> {
+        if self.nodes.is_empty() || game.id != self.root_game_id {
+            return None;
+        }
+        let history: Vec<MoveKey> = game.list_of_moves.iter().map(|r| r.played.key()).collect();
+        if !history.starts_with(&self.root_history) {
+            return None;
+        }
+        let mut node: usize = self.root;
+        for key in &history[self.root_history.len()..] {
+            node = *self.nodes[node]
+                .children
+                .iter()
+                .find(|&&c| self.nodes[c].played.map(|m| m.key()) == Some(*key))?;
+        }
+        Some(node)
+    }
+
+    // Keep only the subtree under node, which becomes the new root.
+    fn reroot(&mut self, node: usize) {
+        let mut old: Vec<Option<Node<'a>>> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .map(Some)
+            .collect();
+        let mut root: Node<'a> = old[node].take().unwrap();
+        root.parent = None;
+        self.nodes.push(root);
+        let mut next: usize = 0;
+        while next < self.nodes.len() {
+            let children: Vec<usize> = std::mem::take(&mut self.nodes[next].children);
+            for child in children {
+                let mut moved: Node<'a> = old[child].take().unwrap();
+                moved.parent = Some(next);
+                let index: usize = self.nodes.len();
+                self.nodes[next].children.push(index);
+                self.nodes.push(moved);
+            }
+            next += 1;
+        }
+        self.root = 0;
+    }
+}
+
+// A finished search in the same shape as format_info, with the win rate standing in for a score.
+pub fn format_mcts_info(result: &MctsResult) -> String {
+    let principal_variation: Vec<String> = result
+        .principal_variation
+        .iter()
+        .map(|m| m.coordinate_notation())
+        .collect();
+    format!(
+        "info iterations {} visits {} winrate {:.3} time {} pv {}",
+        result.iterations,
+        result.root_visits,
+        result.win_rate,
+        result.elapsed.as_millis(),
+        principal_variation.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::mcts::*;
+    use std::path::Path;
+
+    fn iterations(n: u64) -> MctsLimits {
+        MctsLimits {
+            iterations: Some(n),
+            ..MctsLimits::default()
+        }
+    }
+
+    #[test]
+    fn finds_back_rank_mate() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let fen: &str = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        let mut game: Game =
+            Game::from_fen(0, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let mut mcts: Mcts = Mcts::new(Some(1));
+        mcts.max_playout_plies = 20;
+        let result: MctsResult = mcts.search(&mut game, iterations(300));
+        assert_eq!(
+            result.best_move.map(|m| m.coordinate_notation()),
+            Some(String::from("a1a8"))
+        );
+        assert!(result.win_rate > 0.9);
+    }
+
+    #[test]
+    fn same_seed_same_search() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let fen: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let mut game: Game =
+            Game::from_fen(0, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let mut runs: Vec<Vec<String>> = Vec::new();
+        for _ in 0..2 {
+            let mut mcts: Mcts = Mcts::new(Some(42));
+            mcts.playout = playout_by_name("heuristic").unwrap();
+            mcts.max_playout_plies = 10;
+            let result: MctsResult = mcts.search(&mut game, iterations(60));
+            runs.push(
+                result
+                    .principal_variation
+                    .iter()
+                    .map(|m| m.coordinate_notation())
+                    .collect(),
+            );
+        }
+        assert!(!runs[0].is_empty());
+        assert_eq!(runs[0], runs[1]);
+        assert!(playout_by_name("greedy").is_err());
+        assert_eq!(game.hash, game.compute_hash());
+    }
+
+    #[test]
+    fn tree_is_reused_after_moves() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let fen: &str = "4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1";
+        let mut game: Game =
+            Game::from_fen(0, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let mut mcts: Mcts = Mcts::new(Some(7));
+        mcts.max_playout_plies = 10;
+        let result: MctsResult = mcts.search(&mut game, iterations(400));
+        let reply_line: Vec<AvailableMove> = result.principal_variation;
+        assert!(reply_line.len() >= 2);
+        game.make_move(reply_line[0]);
+        game.make_move(reply_line[1]);
+        let result: MctsResult = mcts.search(&mut game, iterations(10));
+        assert!(result.root_visits > 10);
+        // A different game starts from scratch.
+        let mut other: Game =
+            Game::from_fen(1, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let result: MctsResult = mcts.search(&mut other, iterations(10));
+        assert_eq!(result.root_visits, 10);
+    }
+}
@@ -0,0 +1,521 @@
+use crate::clock::*;
+use crate::configuration::*;
+use crate::game::*;
+use crate::BOARDSIZE;
+use std::time::Duration;
+
+impl<'a> Game<'a> {
+    // Standard algebraic notation. Pieces go by the upper case of their symbol, promotable pieces have no letter like pawns,
+    // and castling-like moves are O-O towards the h-file and O-O-O towards the a-file.
+    pub fn san(&mut self, available_move: AvailableMove<'a>) -> String {
+        let (from, to) = (available_move.from, available_move.to);
+        let mut output: String = String::new();
+        if available_move.move_type.castles {
+            output.push_str(if to % BOARDSIZE > from % BOARDSIZE {
+                "O-O"
+            } else {
+                "O-O-O"
+            });
+        } else {
+            let piece: &Piece = self.piece(available_move.piece_id);
+            let (symbol, pawn_like, letter) = (
+                piece.symbol,
+                piece.piece_type.promotable,
+                piece.piece_type.white_id,
+            );
+            // Other pieces of the same kind that could go to the same square.
+            let rivals: Vec<u8> = self
+                .scan_available_moves()
+                .into_iter()
+                .filter(|m| {
+                    m.to == to
+                        && m.from != from
+                        && m.promotion == available_move.promotion
+                        && self.piece(m.piece_id).symbol == symbol
+                })
+                .map(|m| m.from)
+                .collect();
+            let from_name: String = square_name(from);
+            if pawn_like {
+                if available_move.is_capture() {
+                    output.push_str(&from_name[..1]);
+                }
+            } else {
+                output.push(letter);
+                if !rivals.is_empty() {
+                    if rivals.iter().all(|r| r % BOARDSIZE != from % BOARDSIZE) {
+                        output.push_str(&from_name[..1]);
+                    } else if rivals.iter().all(|r| r / BOARDSIZE != from / BOARDSIZE) {
+                        output.push_str(&from_name[1..]);
+                    } else {
+                        output.push_str(&from_name);
+                    }
+                }
+            }
+            if available_move.is_capture() {
+                output.push('x');
+            }
+            output.push_str(&square_name(to));
+            if let Some(promotion) = available_move.promotion {
+                output.push('=');
+                output.push(promotion.to_ascii_uppercase());
+            }
+        }
+        self.make_move(available_move);
+        if self.royal_in_check(self.active_color) {
+            output.push(if self.scan_available_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+        self.unmake_move();
+        output
+    }
+
+    // A move typed in by a player, in coordinate notation (e2e4, e7e8q) or SAN (e4, Nxf3, O-O, e8=Q).
+    // Anything that isn't a legal move comes back with the reason why.
+    pub fn parse_move(&mut self, text: &str) -> Result<AvailableMove<'a>, String> {
+        let text: &str = text.trim().trim_end_matches(['+', '#', '!', '?']);
+        if text.is_empty() {
+            return Err(String::from("Type a move."));
+        }
+        if let (Some(from), Some(to)) = (
+            text.get(0..2).and_then(parse_square),
+            text.get(2..4).and_then(parse_square),
+        ) {
+            if text.len() <= 5 {
+                return match self.find_move(text) {
+                    Some(available_move) => Ok(available_move),
+                    None => Err(self.explain_illegal(from, to)),
+                };
+            }
+        }
+        let wanted: String = simplify_san(text);
+        let mut matches: Vec<AvailableMove<'a>> = Vec::new();
+        for available_move in self.scan_available_moves() {
+            if simplify_san(&self.san(available_move)) == wanted {
+                matches.push(available_move);
+            }
+        }
+        match matches.len() {
+            1 => Ok(matches[0]),
+            0 => {
+                for available_move in self.scan_pseudo_legal_moves() {
+                    if simplify_san(&self.san(available_move)) == wanted {
+                        return Err(self.explain_illegal(available_move.from, available_move.to));
+                    }
+                }
+                if wanted == "OO" || wanted == "OOO" {
+                    let letter: char = if wanted == "OO" { 'K' } else { 'Q' };
+                    for status in self.castling_availability() {
+                        let player: char = self.piece(status.royal_id).player;
+                        if player == self.active_color
+                            && status.letter.eq_ignore_ascii_case(&letter)
+                        {
+                            return Err(self.explain_castling(status));
+                        }
+                    }
+                }
+                Err(format!(
+                    "{} isn't a legal move here. Type moves to see them.",
+                    text
+                ))
+            }
+            _ => {
+                let options: Vec<String> =
+                    matches.iter().map(|m| m.coordinate_notation()).collect();
+                Err(format!(
+                    "{} is ambiguous, it could be {}.",
+                    text,
+                    options.join(" or ")
+                ))
+            }
+        }
+    }
+
+    // What the side to move's royal piece is called, for saying it's in check.
+    pub fn royal_name(&self) -> String {
+        self.list_of_pieces_ingame
+            .iter()
+            .find(|p| !p.captured && p.player == self.active_color && p.piece_type.royal)
+            .map_or(String::from("royal piece"), |p| p.piece_type.name.clone())
+    }
+
+    // Why no legal move goes from one square to another.
+    pub fn explain_illegal(&mut self, from: u8, to: u8) -> String {
+        let id: u8 = self.position[from as usize];
+        if id == 0 {
+            return format!("There's no piece on {}.", square_name(from));
+        }
+        let piece: &Piece = self.piece(id);
+        let name: String = piece.piece_type.name.clone();
+        if piece.player != self.active_color {
+            return format!(
+                "The {} on {} is {}'s.",
+                name,
+                square_name(from),
+                player_name(piece.player)
+            );
+        }
+        if self
+            .scan_pseudo_legal_moves()
+            .iter()
+            .any(|m| m.from == from && m.to == to)
+        {
+            return format!("That would leave your {} in check.", self.royal_name());
+        }
+        if self.piece(id).piece_type.royal {
+            for status in self.castling_availability() {
+                let lands_on_to: bool = status.royal_id == id
+                    && move_vectors(status.move_type).iter().any(|&(dx, dy)| {
+                        self.offset_square(from, dx, dy, self.active_color) == Some(to)
+                    });
+                if lands_on_to {
+                    return self.explain_castling(status);
+                }
+            }
+        }
+        format!(
+            "The {} on {} can't move to {}.",
+            name,
+            square_name(from),
+            square_name(to)
+        )
+    }
+
+    // Why a castling-like move can't be played.
+    fn explain_castling(&self, status: CastlingStatus) -> String {
+        let name: &str = &self.piece(status.royal_id).piece_type.name;
+        if status.available {
+            format!(
+                "The {} can't castle right now. It can't castle out of, through or into check, or with pieces in the way.",
+                name
+            )
+        } else {
+            format!(
+                "The {} can't castle that way any more, it or the piece it castles with has moved.",
+                name
+            )
+        }
+    }
+}
+
+pub fn player_name(player: char) -> &'static str {
+    if player == 'w' {
+        "White"
+    } else {
+        "Black"
+    }
+}
+
+// SAN without the parts people often leave out or get wrong, so Nxf3, Nf3 and Nf3+ all compare equal.
+fn simplify_san(san: &str) -> String {
+    san.chars()
+        .filter(|c| !matches!(c, 'x' | '=' | '+' | '#' | '-' | '!' | '?'))
+        .map(|c| if c == '0' { 'O' } else { c })
+        .collect()
+}
+
+// A line of moves from the current position in SAN, played out on a copy.
+pub fn line_san<'a>(game: &Game<'a>, moves: &[AvailableMove<'a>]) -> Vec<String> {
+    let mut line: Game<'a> = game.clone();
+    let mut output: Vec<String> = Vec::new();
+    for &available_move in moves {
+        output.push(line.san(available_move));
+        line.make_move(available_move);
+    }
+    output
+}
+
+// The game as it was before any of its moves were played.
+pub fn starting_position<'a>(game: &Game<'a>) -> Game<'a> {
+    let mut output: Game<'a> = game.clone();
+    while output.list_of_moves.len() > output.setup_moves {
+        output.unmake_move();
+    }
+    output
+}
+
+// The game as PGN, with the tags in the order given and the moves in SAN.
+pub fn to_pgn(game: &Game, tags: &[(&str, String)], result: &str) -> String {
+    let mut replay: Game = starting_position(game);
+    let mut output: String = String::new();
+    for (name, value) in tags {
+        output.push_str(&format!("[{} \"{}\"]\n", name, value.replace('"', "'")));
+    }
+    output.push('\n');
+    let mut movetext: Vec<String> = Vec::new();
+    let times: &[Duration] = game.clocks.as_ref().map_or(&[], |c| c.times.as_slice());
+    for (index, record) in game.list_of_moves[game.setup_moves..].iter().enumerate() {
+        if replay.active_color == 'w' {
+            movetext.push(format!("{}.", replay.fullmove_number));
+        } else if movetext.is_empty() || !times.is_empty() {
+            // Black's moves get their number again after a comment.
+            movetext.push(format!("{}...", replay.fullmove_number));
+        }
+        movetext.push(replay.san(record.played));
+        if let Some(&time) = times.get(index) {
+            movetext.push(format!("{{[%clk {}]}}", format_clock(time)));
+        }
+        replay.make_move(record.played);
+    }
+    movetext.push(String::from(result));
+    // Keep lines under 80 characters, as the PGN standard asks.
+    let mut line: String = String::new();
+    for word in movetext {
+        if !line.is_empty() && line.len() + word.len() >= 80 {
+            output.push_str(&line);
+            output.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    output.push_str(&line);
+    output.push('\n');
+    output
+}
+
+// Tags, moves and result from a PGN with one game in it. Comments, variations and move numbers are skipped.
+pub fn parse_pgn(text: &str) -> (Vec<(String, String)>, Vec<String>, Option<String>) {
+    let mut tags: Vec<(String, String)> = Vec::new();
+    let mut movetext: String = String::new();
+    for line in text.lines() {
+        let line: &str = line.trim();
+        if let Some(tag) = line.strip_prefix('[') {
+            let tag: &str = tag.trim_end_matches(']');
+            if let Some((name, value)) = tag.split_once(' ') {
+                tags.push((
+                    String::from(name),
+                    String::from(value.trim().trim_matches('"')),
+                ));
+            }
+        } else if !line.starts_with('%') {
+            // Everything after a semicolon is a comment.
+            movetext.push_str(line.split(';').next().unwrap_or(""));
+            movetext.push(' ');
+        }
+    }
+    let mut moves: Vec<String> = Vec::new();
+    let mut result: Option<String> = None;
+    let mut depth: i32 = 0;
+    let mut token: String = String::new();
+    for c in movetext.chars().chain(std::iter::once(' ')) {
+        match c {
+            '{' | '(' => depth += 1,
+            '}' | ')' => depth -= 1,
+            _ if depth > 0 => {}
+            c if c.is_whitespace() => {
+                let word: &str = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                let word: &str = if word.is_empty() || token.starts_with('$') {
+                    ""
+                } else if matches!(token.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*") {
+                    result = Some(token.clone());
+                    ""
+                } else if token.chars().next().is_some_and(|c| c.is_ascii_digit())
+                    && token.contains('.')
+                {
+                    word
+                } else {
+                    &token
+                };
+                if !word.is_empty() {
+                    moves.push(String::from(word));
+                }
+                token.clear();
+            }
+            c => token.push(c),
+        }
+    }
+    (tags, moves, result)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::notation::*;
+    use std::path::Path;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn san_of_all(game: &mut Game) -> Vec<String> {
+        let mut output: Vec<String> = Vec::new();
+        for available_move in game.scan_available_moves() {
+            output.push(game.san(available_move));
+        }
+        output
+    }
+
+    #[test]
+    fn writes_san() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let kiwipete: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut game: Game = Game::from_fen(
+            0,
+            kiwipete,
+            kiwipete,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        let moves: Vec<String> = san_of_all(&mut game);
+        for expected in [
+            "O-O", "O-O-O", "dxe6", "Nxf7", "Qxf6", "Bxa6", "gxh3", "Rb1", "Kf1",
+        ] {
+            assert!(moves.contains(&String::from(expected)), "{}", expected);
+        }
+        let mut game: Game = Game::from_fen(
+            1,
+            "4k3/1P6/8/8/8/8/8/RN2K1NR w - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        let moves: Vec<String> = san_of_all(&mut game);
+        for expected in ["b8=Q+", "b8=R+", "b8=N", "Nd2", "Ne2", "Ra2", "Kf1"] {
+            assert!(moves.contains(&String::from(expected)), "{}", expected);
+        }
+        let mut game: Game = Game::from_fen(
+            2,
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        assert!(san_of_all(&mut game).contains(&String::from("Ra8#")));
+    }
+
+    #[test]
+    fn disambiguates() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game = Game::from_fen(
+            0,
+            "4k3/8/8/8/8/8/8/RN2KN1R w - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        let moves: Vec<String> = san_of_all(&mut game);
+        for expected in ["Nbd2", "Nfd2", "Nc3", "Ng3", "Rg1"] {
+            assert!(moves.contains(&String::from(expected)), "{}", expected);
+        }
+        let mut game: Game = Game::from_fen(
+            1,
+            "4k3/8/8/8/R7/8/8/R3K3 w - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        let moves: Vec<String> = san_of_all(&mut game);
+        assert!(moves.contains(&String::from("R4a2")));
+        assert!(moves.contains(&String::from("R1a2")));
+    }
+
+    #[test]
+    fn reads_moves_and_explains_illegal_ones() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        assert_eq!(game.parse_move("e4").unwrap().coordinate_notation(), "e2e4");
+        assert_eq!(
+            game.parse_move("Nf3").unwrap().coordinate_notation(),
+            "g1f3"
+        );
+        assert_eq!(
+            game.parse_move("b1c3").unwrap().coordinate_notation(),
+            "b1c3"
+        );
+        assert_eq!(
+            game.parse_move("e2e5").unwrap_err(),
+            "The pawn on e2 can't move to e5."
+        );
+        assert_eq!(
+            game.parse_move("e3e4").unwrap_err(),
+            "There's no piece on e3."
+        );
+        assert_eq!(
+            game.parse_move("e7e5").unwrap_err(),
+            "The pawn on e7 is Black's."
+        );
+        assert!(game
+            .parse_move("Qh5")
+            .unwrap_err()
+            .contains("isn't a legal move"));
+        let mut pinned: Game = Game::from_fen(
+            1,
+            "4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        assert_eq!(
+            pinned.parse_move("Bd3").unwrap_err(),
+            "That would leave your king in check."
+        );
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        assert_eq!(game.to_fen(), START);
+        let e4: AvailableMove = game.parse_move("e4").unwrap();
+        game.make_move(e4);
+        let fen: String = game.to_fen();
+        assert_eq!(
+            fen,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        let again: Game =
+            Game::from_fen(1, &fen, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        assert_eq!(again.to_fen(), fen);
+        assert_eq!(again.hash, game.hash);
+        let partial: &str = "r3k2r/8/8/8/8/8/8/R3K2R w Kq - 3 20";
+        let game: Game =
+            Game::from_fen(2, partial, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        assert_eq!(game.to_fen(), partial);
+    }
+
+    #[test]
+    fn pgn_round_trip() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        for san in [
+            "e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6", "Ng5", "d5", "exd5", "Nxd5", "Nxf7",
+        ] {
+            let available_move: AvailableMove = game.parse_move(san).unwrap();
+            game.make_move(available_move);
+        }
+        let pgn: String = to_pgn(&game, &[("Event", String::from("Test"))], "*");
+        assert!(pgn.starts_with(
+            "[Event \"Test\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. Ng5 d5 5. exd5 Nxd5 6. Nxf7 *\n"
+        ));
+        let (tags, moves, result) = parse_pgn(
+            "[Event \"Test\"]\n\n1. e4 {best by test} e5 2.Nf3 (2. f4 exf4) Nc6 $1 ; comment\n3. Bb5 1-0\n",
+        );
+        assert_eq!(tags, vec![(String::from("Event"), String::from("Test"))]);
+        assert_eq!(moves, vec!["e4", "e5", "Nf3", "Nc6", "Bb5"]);
+        assert_eq!(result, Some(String::from("1-0")));
+        // Timed games get the clock after every move.
+        let mut timed: Game =
+            Game::from_fen(1, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        timed.start_clocks(TimeControl::parse("5+2").unwrap());
+        for (san, seconds) in [("e4", 3), ("e5", 61)] {
+            timed.punch_clock(Duration::from_secs(seconds));
+            let available_move: AvailableMove = timed.parse_move(san).unwrap();
+            timed.make_move(available_move);
+        }
+        assert!(to_pgn(&timed, &[], "*")
+            .ends_with("1. e4 {[%clk 0:04:59]} 1... e5 {[%clk 0:04:01]} *\n"));
+    }
+}
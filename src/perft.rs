@@ -0,0 +1,148 @@
+use crate::game::*;
+
+// Count the leaf nodes of the move tree down to the given depth.
+// Comparing these against published numbers is the quickest way to catch mistakes in the move generator.
+pub fn perft(game: &mut Game, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves: Vec<AvailableMove> = game.scan_available_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut nodes: u64 = 0;
+    for available_move in moves {
+        game.make_move(available_move);
+        nodes += perft(game, depth - 1);
+        game.unmake_move();
+    }
+    nodes
+}
+
+// perft split up by the first move, so a wrong total can be narrowed down to the move responsible.
+pub fn divide(game: &mut Game, depth: u32) -> Vec<(String, u64)> {
+    let mut output: Vec<(String, u64)> = Vec::new();
+    for available_move in game.scan_available_moves() {
+        game.make_move(available_move);
+        output.push((
+            available_move.coordinate_notation(),
+            perft(game, depth.saturating_sub(1)),
+        ));
+        game.unmake_move();
+    }
+    output.sort();
+    output
+}
+
+// Same layout as Stockfish's "go perft", so the two can be diffed line by line.
+pub fn format_divide(results: &[(String, u64)]) -> String {
+    let mut output: String = String::new();
+    for (name, nodes) in results {
+        output.push_str(&format!("{}: {}\n", name, nodes));
+    }
+    output.push_str(&format!(
+        "\nNodes searched: {}\n",
+        results.iter().map(|(_, nodes)| nodes).sum::<u64>()
+    ));
+    output
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::perft::*;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    // These use the standard rules in testfiles rather than pieces.json, so experimenting with pieces.json won't break them.
+    fn perft_from_fen(fen: &str, depth: u32) -> u64 {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let piece_symbol_map: HashMap<char, &PieceType> = piece_list.map_piecetypes_to_symbols();
+        let position_name_map: HashMap<String, String> =
+            PositionListIntermediateRepresentation::from_file(Path::new(
+                "testfiles/standardStartingPositions.json",
+            ))
+            .map_positions_to_names();
+        let mut game: Game = Game::from_fen(
+            0,
+            fen,
+            position_name_map.get("standard").unwrap(),
+            piece_symbol_map,
+        )
+        .unwrap();
+        perft(&mut game, depth)
+    }
+
+    #[test]
+    fn perft_starting_position() {
+        let fen: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(perft_from_fen(fen, 1), 20);
+        assert_eq!(perft_from_fen(fen, 2), 400);
+        assert_eq!(perft_from_fen(fen, 3), 8902);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        let fen: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(perft_from_fen(fen, 1), 48);
+        assert_eq!(perft_from_fen(fen, 2), 2039);
+        assert_eq!(perft_from_fen(fen, 3), 97862);
+    }
+
+    #[test]
+    fn perft_position_3() {
+        let fen: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+        assert_eq!(perft_from_fen(fen, 1), 14);
+        assert_eq!(perft_from_fen(fen, 2), 191);
+        assert_eq!(perft_from_fen(fen, 3), 2812);
+        assert_eq!(perft_from_fen(fen, 4), 43238);
+    }
+
+    #[test]
+    fn perft_position_4() {
+        let fen: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        assert_eq!(perft_from_fen(fen, 1), 6);
+        assert_eq!(perft_from_fen(fen, 2), 264);
+        assert_eq!(perft_from_fen(fen, 3), 9467);
+    }
+
+    #[test]
+    fn perft_position_5() {
+        let fen: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+        assert_eq!(perft_from_fen(fen, 1), 44);
+        assert_eq!(perft_from_fen(fen, 2), 1486);
+        assert_eq!(perft_from_fen(fen, 3), 62379);
+    }
+
+    #[test]
+    fn perft_position_6() {
+        let fen: &str = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+        assert_eq!(perft_from_fen(fen, 1), 46);
+        assert_eq!(perft_from_fen(fen, 2), 2079);
+        assert_eq!(perft_from_fen(fen, 3), 89890);
+    }
+
+    #[test]
+    fn perft_enpassant_from_fen() {
+        // Black has just played d7d5, so exd6 is available.
+        let fen: &str = "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2";
+        assert_eq!(perft_from_fen(fen, 1), 31);
+    }
+
+    #[test]
+    fn divide_matches_perft_format() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let fen: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let mut game: Game =
+            Game::from_fen(0, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let results: Vec<(String, u64)> = divide(&mut game, 2);
+        assert_eq!(results.len(), 20);
+        assert!(results.contains(&(String::from("e2e4"), 20)));
+        let output: String = format_divide(&results);
+        assert!(output.starts_with("a2a3: 20\n"));
+        assert!(output.ends_with("\nNodes searched: 400\n"));
+    }
+}
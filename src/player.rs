@@ -0,0 +1,365 @@
+use crate::book::*;
+use crate::evaluation::*;
+use crate::game::*;
+use crate::hints::*;
+use crate::mcts::*;
+use crate::notation::*;
+use crate::random::*;
+use crate::recorder::*;
+use crate::render::*;
+use crate::search::*;
+use crate::tablebase::*;
+use crate::transposition::*;
+use crate::uci::*;
+use std::io::{BufRead, IsTerminal};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Depth and iterations for engines whose spec doesn't give any limits.
+const DEFAULT_DEPTH: u32 = 4;
+const DEFAULT_ITERATIONS: u64 = 2000;
+
+// Anything that can pick a move for the side to move: a person, a search, or a dice roll.
+// None means the player resigns.
+pub trait Player<'a> {
+    fn name(&self) -> String;
+
+    fn choose_move(&mut self, game: &mut Game<'a>) -> Option<AvailableMove<'a>>;
+
+    // What the search behind the last choose_move found, for players that search.
+    fn engine_state(&self) -> Option<EngineState> {
+        None
+    }
+}
+
+// A person at the console. The board is shown from their side and moves are read like in the REPL.
+pub struct HumanPlayer {
+    input: Box<dyn BufRead>,
+    output: SharedOutput,
+    pub renderer: Renderer,
+}
+
+impl HumanPlayer {
+    pub fn new(input: Box<dyn BufRead>, output: SharedOutput) -> HumanPlayer {
+        HumanPlayer {
+            input,
+            output,
+            renderer: Renderer::default(),
+        }
+    }
+}
+
+impl<'a> Player<'a> for HumanPlayer {
+    fn name(&self) -> String {
+        String::from("human")
+    }
+
+    fn choose_move(&mut self, game: &mut Game<'a>) -> Option<AvailableMove<'a>> {
+        let renderer: Renderer = Renderer {
+            flipped: game.active_color == 'b',
+            ..self.renderer
+        };
+        send(&self.output, &renderer.render(game));
+        send(&self.output, &renderer.status(game));
+        loop {
+            let mut line: String = String::new();
+            // Running out of input counts as resigning, so scripted games always end.
+            match self.input.read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => {}
+            }
+            match line.trim() {
+                "" => {}
+                "resign" => return None,
+                "fen" => send(&self.output, &game.to_fen()),
+                "threats" => send(&self.output, &show_threats(game, &renderer)),
+                "moves" => {
+                    let mut moves: Vec<String> = Vec::new();
+                    for available_move in game.scan_available_moves() {
+                        moves.push(game.san(available_move));
+                    }
+                    moves.sort();
+                    send(&self.output, &moves.join(" "));
+                }
+                text if text.starts_with("hint ") => {
+                    send(&self.output, &show_hint(game, &renderer, &text[5..]))
+                }
+                text if text.starts_with("why ") => send(&self.output, &why(game, &text[4..])),
+                text => match game.parse_move(text) {
+                    Ok(available_move) => return Some(available_move),
+                    Err(why) => send(&self.output, &why),
+                },
+            }
+        }
+    }
+}
+
+// The alpha-beta Searcher with fixed limits for every move.
+pub struct AlphaBetaPlayer {
+    name: String,
+    pub searcher: Searcher,
+    pub limits: SearchLimits,
+    last_search: Option<EngineState>,
+}
+
+impl<'a> Player<'a> for AlphaBetaPlayer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    // On a clock the time left caps the search, and stands in for the default depth if the spec gave no limits.
+    fn choose_move(&mut self, game: &mut Game<'a>) -> Option<AvailableMove<'a>> {
+        let mut limits: SearchLimits = self.limits;
+        match game.time_budget() {
+            Some(budget) => {
+                limits.movetime = Some(limits.movetime.map_or(budget, |m| m.min(budget)))
+            }
+            None if limits.depth.is_none()
+                && limits.movetime.is_none()
+                && limits.nodes.is_none() =>
+            {
+                limits.depth = Some(DEFAULT_DEPTH)
+            }
+            None => {}
+        }
+        let result: SearchResult<'a> = self.searcher.search(game, limits, &mut |_| {});
+        self.last_search = Some(EngineState {
+            depth: result.depth,
+            score: Some(result.score),
+            win_rate: None,
+            principal_variation: line_san(game, &result.principal_variation),
+            nodes: result.nodes,
+            tt_hit_rate: Some(result.table.hit_rate()),
+        });
+        result.best_move
+    }
+
+    fn engine_state(&self) -> Option<EngineState> {
+        self.last_search.clone()
+    }
+}
+
+pub struct MctsPlayer<'a> {
+    name: String,
+    pub mcts: Mcts<'a>,
+    pub limits: MctsLimits,
+    last_search: Option<EngineState>,
+}
+
+impl<'a> Player<'a> for MctsPlayer<'a> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn choose_move(&mut self, game: &mut Game<'a>) -> Option<AvailableMove<'a>> {
+        let mut limits: MctsLimits = self.limits;
+        match game.time_budget() {
+            Some(budget) => {
+                limits.movetime = Some(limits.movetime.map_or(budget, |m| m.min(budget)))
+            }
+            None if limits.iterations.is_none() && limits.movetime.is_none() => {
+                limits.iterations = Some(DEFAULT_ITERATIONS)
+            }
+            None => {}
+        }
+        let result: MctsResult<'a> = self.mcts.search(game, limits);
+        self.last_search = Some(EngineState {
+            depth: result.principal_variation.len() as u32,
+            score: None,
+            win_rate: Some(result.win_rate),
+            principal_variation: line_san(game, &result.principal_variation),
+            nodes: result.iterations,
+            tt_hit_rate: None,
+        });
+        result.best_move
+    }
+
+    fn engine_state(&self) -> Option<EngineState> {
+        self.last_search.clone()
+    }
+}
+
+// Picks any legal move. Mostly good for checking that everything else beats it.
+pub struct RandomPlayer {
+    name: String,
+    random: Random,
+}
+
+impl RandomPlayer {
+    pub fn new(seed: Option<u64>) -> RandomPlayer {
+        RandomPlayer {
+            name: String::from("random"),
+            random: seed.map_or_else(Random::from_time, Random::new),
+        }
+    }
+}
+
+impl<'a> Player<'a> for RandomPlayer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn choose_move(&mut self, game: &mut Game<'a>) -> Option<AvailableMove<'a>> {
+        let moves: Vec<AvailableMove<'a>> = game.scan_available_moves();
+        if moves.is_empty() {
+            return None;
+        }
+        Some(moves[self.random.below(moves.len())])
+    }
+}
+
+// Plays from an opening book while the position is in it, and lets the engine it wraps take over after that.
+pub struct BookPlayer<'a> {
+    pub book: OpeningBook,
+    random: Random,
+    engine: Box<dyn Player<'a> + 'a>,
+    // Book moves weren't searched, so there's no engine state to report for them.
+    from_book: bool,
+}
+
+impl<'a> Player<'a> for BookPlayer<'a> {
+    fn name(&self) -> String {
+        self.engine.name()
+    }
+
+    fn choose_move(&mut self, game: &mut Game<'a>) -> Option<AvailableMove<'a>> {
+        if let Some(book_move) = self.book.choose(game, &mut self.random) {
+            self.from_book = true;
+            return Some(book_move);
+        }
+        self.from_book = false;
+        self.engine.choose_move(game)
+    }
+
+    fn engine_state(&self) -> Option<EngineState> {
+        if self.from_book {
+            None
+        } else {
+            self.engine.engine_state()
+        }
+    }
+}
+
+// Make a player from a command line spec:
+//   human
+//   random[:seed=N]
+//   engine[:depth=N,movetime=MS,nodes=N,eval=NAME,hash=MB,book=FILE,seed=N,tb=DIRECTORY]
+//   mcts[:iterations=N,movetime=MS,seed=N,eval=NAME,playout=random|heuristic,book=FILE]
+// Engines use default_evaluator unless the spec names one. Humans play on stdin and the given output.
+// book=FILE plays from a Polyglot .bin or a book made by build-book first, picking between book moves with the seed.
+// tb=DIRECTORY has the engine look up endings made with the tablebase command.
+// MCTS playouts are random unless playout=heuristic, and only heuristic playouts ask the evaluator.
+pub fn player_from_spec<'a>(
+    spec: &str,
+    game: &mut Game,
+    default_evaluator: &str,
+    output: SharedOutput,
+) -> Result<Box<dyn Player<'a> + 'a>, String> {
+    let (kind, settings) = spec.split_once(':').unwrap_or((spec, ""));
+    let mut options: Vec<(&str, &str)> = Vec::new();
+    for setting in settings.split(',').filter(|s| !s.is_empty()) {
+        match setting.split_once('=') {
+            Some(option) => options.push(option),
+            None => return Err(format!("expected key=value in {}, got {}", spec, setting)),
+        }
+    }
+    let number = |key: &str| -> Result<Option<u64>, String> {
+        match options.iter().find(|(k, _)| *k == key) {
+            Some((_, value)) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("{} takes a number, got {}", key, value)),
+            None => Ok(None),
+        }
+    };
+    let known = |allowed: &[&str]| -> Result<(), String> {
+        match options.iter().find(|(k, _)| !allowed.contains(k)) {
+            Some((key, _)) => Err(format!("{} doesn't take {}", kind, key)),
+            None => Ok(()),
+        }
+    };
+    let evaluator_name: &str = options
+        .iter()
+        .find(|(k, _)| *k == "eval")
+        .map_or(default_evaluator, |(_, v)| v);
+    let seed: Option<u64> = number("seed")?;
+    let engine: Box<dyn Player<'a> + 'a> = match kind {
+        "human" => {
+            known(&[])?;
+            let mut player: HumanPlayer =
+                HumanPlayer::new(Box::new(std::io::BufReader::new(std::io::stdin())), output);
+            if std::io::stdout().is_terminal() {
+                player.renderer = Renderer::terminal();
+            }
+            return Ok(Box::new(player));
+        }
+        "random" => {
+            known(&["seed"])?;
+            let mut player: RandomPlayer = RandomPlayer::new(number("seed")?);
+            player.name = String::from(spec);
+            return Ok(Box::new(player));
+        }
+        "engine" => {
+            known(&[
+                "depth", "movetime", "nodes", "eval", "hash", "book", "seed", "tb",
+            ])?;
+            let limits: SearchLimits = SearchLimits {
+                depth: number("depth")?.map(|d| d as u32),
+                movetime: number("movetime")?.map(Duration::from_millis),
+                nodes: number("nodes")?,
+            };
+            let mut searcher: Searcher = Searcher::new();
+            searcher.evaluator = evaluator_by_name(evaluator_name, game)?;
+            if let Some((_, directory)) = options.iter().find(|(k, _)| *k == "tb") {
+                searcher.tablebases = Some(Arc::new(Tablebases::open(
+                    Path::new(directory),
+                    &game.piece_symbol_map,
+                )?));
+            }
+            if let Some(megabytes) = number("hash")? {
+                searcher.table =
+                    TranspositionTable::new(megabytes as usize, Replacement::DepthPreferred);
+            }
+            Box::new(AlphaBetaPlayer {
+                name: String::from(spec),
+                searcher,
+                limits,
+                last_search: None,
+            })
+        }
+        "mcts" => {
+            known(&["iterations", "movetime", "seed", "eval", "playout", "book"])?;
+            let limits: MctsLimits = MctsLimits {
+                iterations: number("iterations")?,
+                movetime: number("movetime")?.map(Duration::from_millis),
+            };
+            let mut mcts: Mcts = Mcts::new(seed);
+            if let Some((_, name)) = options.iter().find(|(k, _)| *k == "playout") {
+                mcts.playout = playout_by_name(name)?;
+            }
+            mcts.evaluator = evaluator_by_name(evaluator_name, game)?;
+            Box::new(MctsPlayer {
+                name: String::from(spec),
+                mcts,
+                limits,
+                last_search: None,
+            })
+        }
+        _ => {
+            return Err(format!(
+                "unknown player {}, expected human, engine, mcts or random",
+                kind
+            ))
+        }
+    };
+    match options.iter().find(|(k, _)| *k == "book") {
+        Some((_, file)) => Ok(Box::new(BookPlayer {
+            book: OpeningBook::open(Path::new(file), game)?,
+            random: seed.map_or_else(Random::from_time, Random::new),
+            engine,
+            from_book: false,
+        })),
+        None => Ok(engine),
+    }
+}
@@ -0,0 +1,37 @@
+use crate::zobrist::splitmix64;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Small seedable random number generator. Anything random in the engine goes through this,
+// so a game played from a given seed can be played again move for move.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random { state: seed }
+    }
+
+    // Seeded from the clock, for when nobody needs to repeat the run.
+    pub fn from_time() -> Random {
+        let nanos: u128 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        Random::new(nanos as u64)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(1);
+        splitmix64(self.state)
+    }
+
+    // A whole number from 0 up to but not including n.
+    pub fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            return 0;
+        }
+        (self.next_u64() % n as u64) as usize
+    }
+}
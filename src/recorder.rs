@@ -0,0 +1,161 @@
+use crate::game::*;
+use std::time::Duration;
+
+// What an engine thought when it chose a move.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineState {
+    // Alpha-beta's deepest finished iteration, or how far MCTS's best line goes.
+    pub depth: u32,
+    // Centipawns for the mover from alpha-beta, or how often the move won from MCTS.
+    pub score: Option<i32>,
+    pub win_rate: Option<f64>,
+    // In SAN from the position the move was chosen in.
+    pub principal_variation: Vec<String>,
+    // Nodes searched, or MCTS iterations.
+    pub nodes: u64,
+    // Only alpha-beta has a transposition table.
+    pub tt_hit_rate: Option<f64>,
+}
+
+// Everything about one ply, taken just before the move was made.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlyRecord {
+    // Counting from 1.
+    pub ply: usize,
+    pub fen: String,
+    pub mover: char,
+    pub san: String,
+    pub coordinate: String,
+    pub moveid: String,
+    pub piece_type: String,
+    // Names of the piece type taken and the one promoted to, if any.
+    pub captured: Option<String>,
+    pub promotion: Option<String>,
+    pub legal_moves: usize,
+    // How long the player took to choose.
+    pub time: Duration,
+    // None for players that don't search, like people.
+    pub engine: Option<EngineState>,
+}
+
+// One game's worth of records, for the exporters to turn into files.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GameLog {
+    pub game_id: u64,
+    pub white: String,
+    pub black: String,
+    pub start_fen: String,
+    pub plies: Vec<PlyRecord>,
+    // Like "1-0" and "White mates", once the game is over.
+    pub result: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    pub log: GameLog,
+}
+
+impl Recorder {
+    pub fn new(white: &str, black: &str, game: &Game) -> Recorder {
+        Recorder {
+            log: GameLog {
+                game_id: game.id,
+                white: String::from(white),
+                black: String::from(black),
+                start_fen: game.to_fen(),
+                ..GameLog::default()
+            },
+        }
+    }
+
+    // Record the move, then make it. Returns the move in SAN.
+    pub fn make_move<'a>(
+        &mut self,
+        game: &mut Game<'a>,
+        available_move: AvailableMove<'a>,
+        time: Duration,
+        engine: Option<EngineState>,
+    ) -> String {
+        let san: String = game.san(available_move);
+        self.log.plies.push(PlyRecord {
+            ply: self.log.plies.len() + 1,
+            fen: game.to_fen(),
+            mover: game.active_color,
+            san: san.clone(),
+            coordinate: available_move.coordinate_notation(),
+            moveid: available_move.move_type.id.clone(),
+            piece_type: game.piece(available_move.piece_id).piece_type.name.clone(),
+            captured: available_move
+                .captured_piece_id
+                .map(|id| game.piece(id).piece_type.name.clone()),
+            promotion: available_move
+                .promotion
+                .and_then(|symbol| game.piece_symbol_map.get(&symbol))
+                .map(|p| p.name.clone()),
+            legal_moves: game.scan_available_moves().len(),
+            time,
+            engine,
+        });
+        game.make_move(available_move);
+        san
+    }
+
+    // Take the last move back, and the result with it, for games where people can undo.
+    pub fn unmake_move(&mut self, game: &mut Game) {
+        game.unmake_move();
+        self.log.plies.pop();
+        self.log.result = None;
+        self.log.reason = None;
+    }
+
+    pub fn finish(&mut self, result: &str, reason: &str) {
+        self.log.result = Some(String::from(result));
+        self.log.reason = Some(String::from(reason));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::matches::*;
+    use crate::player::*;
+    use crate::recorder::*;
+    use crate::uci::*;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn records_every_ply() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let output: SharedOutput = Arc::new(Mutex::new(Vec::new()));
+        let white = player_from_spec("engine:depth=2", &mut game, "material", output.clone());
+        let black = player_from_spec("random:seed=3", &mut game, "material", output);
+        let mut played: Match = Match::new(white.unwrap(), black.unwrap(), game);
+        played.max_plies = Some(4);
+        played.play(&mut |_, _| {});
+        let log: &GameLog = &played.recorder.log;
+        assert_eq!(log.white, "engine:depth=2");
+        assert_eq!(log.start_fen, START);
+        assert_eq!(log.plies.len(), 4);
+        assert_eq!(log.result.as_deref(), Some("1/2-1/2"));
+        assert_eq!(log.reason.as_deref(), Some("Move limit reached"));
+        let first: &PlyRecord = &log.plies[0];
+        assert_eq!((first.ply, first.mover, first.legal_moves), (1, 'w', 20));
+        assert_eq!(first.fen, START);
+        assert!(first.moveid.ends_with('!'));
+        let engine: &EngineState = first.engine.as_ref().unwrap();
+        assert_eq!(engine.depth, 2);
+        assert!(engine.nodes > 0 && engine.tt_hit_rate.is_some());
+        assert_eq!(engine.principal_variation[0], first.san);
+        assert_eq!(log.plies[1].engine, None);
+        assert_eq!(log.plies[1].mover, 'b');
+        assert!(log.plies[1].fen.contains(" b KQkq "));
+    }
+}
@@ -0,0 +1,388 @@
+use crate::clock::*;
+use crate::configuration::*;
+use crate::evaluation::*;
+use crate::game::*;
+use crate::BOARDSIZE;
+
+// 256-colour backgrounds for the squares, and for the two squares of the last move.
+pub const LIGHT_SQUARE: u8 = 180;
+pub const DARK_SQUARE: u8 = 137;
+pub const LIGHT_HIGHLIGHT: u8 = 187;
+pub const DARK_HIGHLIGHT: u8 = 143;
+// Backgrounds for the hint and threat overlays.
+pub const SELECTED: u8 = 75;
+pub const QUIET: u8 = 114;
+pub const CAPTURE: u8 = 167;
+pub const ATTACKED: u8 = 216;
+pub const WHITE_PIECE: u8 = 231;
+pub const BLACK_PIECE: u8 = 16;
+const RESET: &str = "\x1b[0m";
+
+// Something to point out on a square. Plain boards mark empty squares with a character in place of the dot,
+// and occupied squares with a character in front of the piece.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mark {
+    LastMove,
+    // The piece a hint is for.
+    Selected,
+    Quiet,
+    Capture,
+    Attacked,
+}
+
+impl Mark {
+    fn plain(&self) -> char {
+        match self {
+            Mark::LastMove => ' ',
+            Mark::Selected => '>',
+            Mark::Quiet => '*',
+            Mark::Capture => 'x',
+            Mark::Attacked => '!',
+        }
+    }
+}
+
+// Draws the board as text. Everything is off by default, which gives plain ASCII that's easy to compare in tests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Renderer {
+    // Use the glyphs from pieces.json where there are any.
+    pub unicode: bool,
+    // ANSI colours for light and dark squares and the pieces on them.
+    pub colour: bool,
+    // Black at the bottom.
+    pub flipped: bool,
+    // Mark the from and to squares of the last move. Only shows with colour on.
+    pub highlight_last_move: bool,
+}
+
+impl Renderer {
+    // Everything on, for a terminal.
+    pub fn terminal() -> Renderer {
+        Renderer {
+            unicode: true,
+            colour: true,
+            flipped: false,
+            highlight_last_move: true,
+        }
+    }
+
+    pub fn render(&self, game: &Game) -> String {
+        self.render_marked(game, &[])
+    }
+
+    // The board with some squares marked, for hints and threats. Marks win over the last move highlight.
+    pub fn render_marked(&self, game: &Game, marks: &[(u8, Mark)]) -> String {
+        let last_move: Option<(u8, u8)> = if self.highlight_last_move {
+            game.list_of_moves
+                .last()
+                .map(|record| (record.played.from, record.played.to))
+        } else {
+            None
+        };
+        self.draw(|square| {
+            let id: u8 = game.position[square as usize];
+            let mark: Option<Mark> = match marks.iter().find(|(s, _)| *s == square) {
+                Some((_, mark)) => Some(*mark),
+                None if last_move.is_some_and(|(from, to)| square == from || square == to) => {
+                    Some(Mark::LastMove)
+                }
+                None => None,
+            };
+            if id == 0 {
+                let empty: char = match mark {
+                    _ if self.colour => ' ',
+                    Some(mark) if mark != Mark::LastMove => mark.plain(),
+                    _ => '.',
+                };
+                return (empty.to_string(), None, mark);
+            }
+            let piece: &Piece = game.piece(id);
+            (self.glyph(piece).to_string(), Some(piece.player), mark)
+        })
+    }
+
+    // What goes under the board: captures, material, castling, the clocks in timed games, then whose move it is and whether they're in check.
+    pub fn status(&self, game: &mut Game) -> String {
+        let mut output: String = String::new();
+        for (player, name) in [('w', "White"), ('b', "Black")] {
+            let captured: Vec<String> = game
+                .captured_by(player)
+                .iter()
+                .map(|p| self.glyph(p).to_string())
+                .collect();
+            output.push_str(&format!(
+                "Captured by {}: {}\n",
+                name,
+                if captured.is_empty() {
+                    String::from("none")
+                } else {
+                    captured.join(" ")
+                }
+            ));
+        }
+        let balance: i32 = material_balance(game);
+        output.push_str(&match balance {
+            0 => String::from("Material: even\n"),
+            b if b > 0 => format!("Material: White +{}\n", b),
+            b => format!("Material: Black +{}\n", -b),
+        });
+        // Rights the side to move has kept but can't use this move go in brackets.
+        let mut castling: Vec<String> = Vec::new();
+        for (player, name) in [('w', "White"), ('b', "Black")] {
+            let mut sides: Vec<String> = Vec::new();
+            for status in game.castling_availability() {
+                if game.piece(status.royal_id).player != player || !status.available {
+                    continue;
+                }
+                let side: &str = if status.letter.eq_ignore_ascii_case(&'K') {
+                    "O-O"
+                } else {
+                    "O-O-O"
+                };
+                sides.push(if status.playable || player != game.active_color {
+                    String::from(side)
+                } else {
+                    format!("({})", side)
+                });
+            }
+            sides.dedup();
+            castling.push(format!(
+                "{} {}",
+                name,
+                if sides.is_empty() {
+                    String::from("none")
+                } else {
+                    sides.join(" ")
+                }
+            ));
+        }
+        output.push_str(&format!("Castling: {}\n", castling.join(", ")));
+        if let Some(clocks) = &game.clocks {
+            output.push_str(&format!(
+                "Clocks: White {}, Black {}\n",
+                format_clock(clocks.remaining('w')),
+                format_clock(clocks.remaining('b'))
+            ));
+        }
+        let mover: &str = if game.active_color == 'w' {
+            "White"
+        } else {
+            "Black"
+        };
+        output.push_str(&format!("Move {}, ", game.fullmove_number));
+        output.push_str(&match game.outcome() {
+            Some(Outcome::Checkmate(_)) => format!("{} is checkmated.", mover),
+            Some(outcome) => format!("{}.", outcome.reason()),
+            None if game.royal_in_check(game.active_color) => {
+                format!("{} to move, in check.", mover)
+            }
+            None => format!("{} to move.", mover),
+        });
+        output
+    }
+
+    pub fn glyph(&self, piece: &Piece) -> char {
+        match piece.piece_type.glyphs {
+            Some((white, black)) if self.unicode => {
+                if piece.player == 'w' {
+                    white
+                } else {
+                    black
+                }
+            }
+            _ => piece.symbol,
+        }
+    }
+
+    // The piece ids instead of the pieces, for debugging the id bookkeeping.
+    pub fn render_ids(&self, game: &Game) -> String {
+        self.draw(|square| {
+            let id: u8 = game.position[square as usize];
+            let player: Option<char> = if id == 0 {
+                None
+            } else {
+                Some(game.piece(id).player)
+            };
+            let text: String = if id == 0 {
+                String::from(" .")
+            } else {
+                format!("{:>2}", id)
+            };
+            (text, player, None)
+        })
+    }
+
+    // Lay out one cell per square, given what goes in it, whose piece it is and whether to highlight it.
+    fn draw(&self, cell: impl Fn(u8) -> (String, Option<char>, Option<Mark>)) -> String {
+        let mut order: Vec<u8> = (0..BOARDSIZE).collect();
+        if self.flipped {
+            order.reverse();
+        }
+        let mut output: String = String::new();
+        let mut width: usize = 1;
+        for &rank in &order {
+            output.push_str(&format!("{:>2} ", BOARDSIZE - rank));
+            for &file in &order {
+                let square: u8 = rank * BOARDSIZE + file;
+                let (text, player, mark) = cell(square);
+                width = width.max(text.chars().count());
+                if self.colour {
+                    let light: bool = (rank + file) % 2 == 0;
+                    let background: u8 = match (mark, light) {
+                        (None, true) => LIGHT_SQUARE,
+                        (None, false) => DARK_SQUARE,
+                        (Some(Mark::LastMove), true) => LIGHT_HIGHLIGHT,
+                        (Some(Mark::LastMove), false) => DARK_HIGHLIGHT,
+                        (Some(Mark::Selected), _) => SELECTED,
+                        (Some(Mark::Quiet), _) => QUIET,
+                        (Some(Mark::Capture), _) => CAPTURE,
+                        (Some(Mark::Attacked), _) => ATTACKED,
+                    };
+                    let foreground: u8 = if player == Some('w') {
+                        WHITE_PIECE
+                    } else {
+                        BLACK_PIECE
+                    };
+                    output.push_str(&format!(
+                        "\x1b[48;5;{}m\x1b[38;5;{}m {} {}",
+                        background, foreground, text, RESET
+                    ));
+                } else {
+                    // Marks on occupied squares go where the space would be.
+                    output.push(match mark {
+                        Some(mark) if player.is_some() => mark.plain(),
+                        _ => ' ',
+                    });
+                    output.push_str(&text);
+                }
+            }
+            output.push('\n');
+        }
+        output.push_str("   ");
+        for &file in &order {
+            let label: char = (b'a' + file) as char;
+            if self.colour {
+                output.push_str(&format!(" {:>width$} ", label));
+            } else {
+                output.push_str(&format!(" {:>width$}", label));
+            }
+        }
+        output
+    }
+}
+
+// White's material minus Black's, by the values the material evaluator uses.
+pub fn material_balance(game: &Game) -> i32 {
+    let mut output: i32 = 0;
+    for piece in game.list_of_pieces_ingame.iter().filter(|p| !p.captured) {
+        let value: i32 = Material.piece_value(piece.piece_type);
+        output += if piece.player == 'w' { value } else { -value };
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::render::*;
+    use std::path::Path;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn plain_and_flipped() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let e4: AvailableMove = game.find_move("e2e4").unwrap();
+        game.make_move(e4);
+        let plain: Renderer = Renderer::default();
+        assert_eq!(
+            plain.render(&game),
+            " 8  r n b q k b n r
+ 7  p p p p p p p p
+ 6  . . . . . . . .
+ 5  . . . . . . . .
+ 4  . . . . P . . .
+ 3  . . . . . . . .
+ 2  P P P P . P P P
+ 1  R N B Q K B N R
+    a b c d e f g h"
+        );
+        let flipped: Renderer = Renderer {
+            flipped: true,
+            unicode: true,
+            ..Renderer::default()
+        };
+        assert_eq!(
+            flipped.render(&game),
+            " 1  ♖ ♘ ♗ ♔ ♕ ♗ ♘ ♖
+ 2  ♙ ♙ ♙ . ♙ ♙ ♙ ♙
+ 3  . . . . . . . .
+ 4  . . . ♙ . . . .
+ 5  . . . . . . . .
+ 6  . . . . . . . .
+ 7  ♟ ♟ ♟ ♟ ♟ ♟ ♟ ♟
+ 8  ♜ ♞ ♝ ♚ ♛ ♝ ♞ ♜
+    h g f e d c b a"
+        );
+        assert!(plain
+            .render_ids(&game)
+            .starts_with(" 8   1  2  3  4  5  6  7  8\n 7   9 10 11 12 13 14 15 16\n 6   .  ."));
+    }
+
+    #[test]
+    fn colours_and_last_move() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game = Game::from_fen(
+            0,
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        let king: AvailableMove = game.find_move("e1d2").unwrap();
+        game.make_move(king);
+        let output: String = Renderer::terminal().render(&game);
+        let lines: Vec<&str> = output.lines().collect();
+        // a8 is light, b8 dark.
+        assert!(lines[0].starts_with(" 8 \x1b[48;5;180m\x1b[38;5;16m   \x1b[0m\x1b[48;5;137m"));
+        // d2 and e1 are both dark squares.
+        assert!(lines[6].contains("\x1b[48;5;143m\x1b[38;5;231m ♔ \x1b[0m"));
+        assert!(lines[7].contains("\x1b[48;5;143m\x1b[38;5;16m   \x1b[0m"));
+        assert_eq!(output.matches("48;5;143").count(), 2);
+        assert_eq!(lines[8], "    a  b  c  d  e  f  g  h ");
+    }
+
+    #[test]
+    fn status_panel() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        for coordinate in ["e2e4", "d7d5", "e4d5", "d8d5", "g1f3", "d5e5"] {
+            let available_move: AvailableMove = game.find_move(coordinate).unwrap();
+            game.make_move(available_move);
+        }
+        assert_eq!(
+            Renderer::default().status(&mut game),
+            "Captured by White: p
+Captured by Black: P
+Material: even
+Castling: White (O-O) (O-O-O), Black O-O O-O-O
+Move 4, White to move, in check."
+        );
+        let unicode: Renderer = Renderer {
+            unicode: true,
+            ..Renderer::default()
+        };
+        let takes: AvailableMove = game.find_move("f3e5").unwrap();
+        game.make_move(takes);
+        let status: String = unicode.status(&mut game);
+        assert!(status
+            .starts_with("Captured by White: ♟ ♛\nCaptured by Black: ♙\nMaterial: White +900\n"));
+    }
+}
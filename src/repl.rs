@@ -0,0 +1,454 @@
+use crate::clock::*;
+use crate::configuration::*;
+use crate::export::*;
+use crate::game::*;
+use crate::hints::*;
+use crate::notation::*;
+use crate::recorder::*;
+use crate::render::*;
+use crate::uci::*;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+
+// Two people sharing a terminal. Moves go in as SAN or coordinates and anything that isn't legal is sent back with the reason.
+pub struct Repl {
+    output: SharedOutput,
+    position_name_map: HashMap<String, String>,
+    piece_list: &'static PieceList,
+    variant: String,
+    game_counter: u64,
+    game: Game<'static>,
+    // Every move since the position was set up, with how long it took, for export.
+    pub recorder: Recorder,
+    pub renderer: Renderer,
+    // Result and reason once the game is over, like "1-0 {Black resigns}".
+    finished: Option<String>,
+    draw_offered: bool,
+    // Every game gets these clocks, if set. They run from when the board is shown to when a move is typed.
+    time_control: Option<TimeControl>,
+    turn_started: Instant,
+}
+
+impl Repl {
+    pub fn new(
+        pieces_path: &str,
+        positions_path: &str,
+        output: SharedOutput,
+    ) -> Result<Repl, String> {
+        let piece_list: &'static PieceList = load_ruleset(pieces_path)?;
+        let position_name_map: HashMap<String, String> = load_positions(positions_path)?;
+        let variant: String = default_variant(&position_name_map)?;
+        let game: Game<'static> = Game::from_fen(
+            0,
+            &position_name_map[&variant],
+            &position_name_map[&variant],
+            piece_list.map_piecetypes_to_symbols(),
+        )?;
+        Ok(Repl {
+            output,
+            position_name_map,
+            piece_list,
+            variant,
+            game_counter: 0,
+            recorder: Recorder::new("human", "human", &game),
+            game,
+            renderer: Renderer::default(),
+            finished: None,
+            draw_offered: false,
+            time_control: None,
+            turn_started: Instant::now(),
+        })
+    }
+
+    // Start from a named position or a FEN rather than the default one.
+    pub fn set_position(&mut self, position: &str) -> Result<(), String> {
+        let game: Game<'static> = self.setup(position)?;
+        if self.position_name_map.contains_key(position) {
+            self.variant = String::from(position);
+        }
+        self.recorder = Recorder::new("human", "human", &game);
+        self.game = game;
+        self.finished = None;
+        self.draw_offered = false;
+        self.start_clocks();
+        Ok(())
+    }
+
+    // Puts the current game and every new one on the clock.
+    pub fn set_time_control(&mut self, control: TimeControl) {
+        self.time_control = Some(control);
+        self.start_clocks();
+    }
+
+    fn start_clocks(&mut self) {
+        if let Some(control) = &self.time_control {
+            self.game.start_clocks(control.clone());
+        }
+        self.turn_started = Instant::now();
+    }
+
+    pub fn run(&mut self, input: impl BufRead) {
+        self.show();
+        for line in input.lines() {
+            match line {
+                Ok(line) if self.handle(&line) => {}
+                _ => return,
+            }
+        }
+    }
+
+    // Returns false once asked to quit.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let argument: String = words[words.len().min(1)..].join(" ");
+        match words.first().copied() {
+            None => return true,
+            Some("quit" | "exit") => return false,
+            Some("help") => self.help(),
+            Some("moves") => {
+                let mut moves: Vec<String> = Vec::new();
+                for available_move in self.game.scan_available_moves() {
+                    moves.push(self.game.san(available_move));
+                }
+                moves.sort();
+                self.send(&moves.join(" "));
+            }
+            Some("undo") => {
+                if self.game.list_of_moves.len() > self.game.setup_moves {
+                    self.recorder.unmake_move(&mut self.game);
+                    self.game.undo_clock();
+                    self.turn_started = Instant::now();
+                    self.finished = None;
+                    self.draw_offered = false;
+                    self.show();
+                } else {
+                    self.send("Nothing to undo.");
+                }
+            }
+            Some("fen") => self.send(&self.game.to_fen()),
+            Some("flip") => {
+                self.renderer.flipped = !self.renderer.flipped;
+                self.show();
+            }
+            Some("hint") if !argument.is_empty() => {
+                let hint: String = show_hint(&mut self.game, &self.renderer, &argument);
+                self.send(&hint)
+            }
+            Some("threats") => self.send(&show_threats(&self.game, &self.renderer)),
+            Some("why") if !argument.is_empty() => {
+                let explanation: String = why(&mut self.game, &argument);
+                self.send(&explanation)
+            }
+            Some("hint") => self.send("Give a square, like hint e2."),
+            Some("why") => self.send("Give a move, like why Nf3."),
+            Some("resign") if self.finished.is_none() => {
+                let loser: char = self.game.active_color;
+                let result: &str = if loser == 'w' { "0-1" } else { "1-0" };
+                self.finish(format!("{} {{{} resigns}}", result, player_name(loser)));
+            }
+            Some("draw") if self.finished.is_none() => {
+                // Both players share the keyboard, so the offer is answered before the side to move plays on.
+                let mover: char = self.game.active_color;
+                if self.draw_offered {
+                    self.finish(String::from("1/2-1/2 {Draw agreed}"));
+                } else {
+                    self.draw_offered = true;
+                    self.send(&format!(
+                        "{} offers a draw. {} can type draw to accept, or make a move to decline.",
+                        player_name(mover),
+                        player_name(opponent(mover))
+                    ));
+                }
+            }
+            Some("export") if !argument.is_empty() => {
+                match export_logs(
+                    std::slice::from_ref(&self.recorder.log),
+                    self.piece_list,
+                    &argument,
+                ) {
+                    Ok(files) => self.send(&format!("Wrote {}.", files.join(", "))),
+                    Err(why) => self.send(&format!("Couldn't export to {}: {}", argument, why)),
+                }
+            }
+            Some("save") if !argument.is_empty() => match std::fs::write(&argument, self.pgn()) {
+                Ok(()) => self.send(&format!("Saved to {}.", argument)),
+                Err(why) => self.send(&format!("Couldn't save to {}: {}", argument, why)),
+            },
+            Some("load") if !argument.is_empty() => match self.load(&argument) {
+                Ok(()) => self.show(),
+                Err(why) => self.send(&format!("Couldn't load {}: {}", argument, why)),
+            },
+            Some("new") => {
+                let position: String = if argument.is_empty() {
+                    self.variant.clone()
+                } else {
+                    argument
+                };
+                match self.set_position(&position) {
+                    Ok(()) => self.show(),
+                    Err(why) => self.send(&format!("Couldn't set up {}: {}", position, why)),
+                }
+            }
+            Some("save" | "load" | "export") => self.send("Give a file name."),
+            Some(_) if self.finished.is_some() => {
+                self.send("The game is over. Type new, load, undo, export or quit.")
+            }
+            Some(_) => match self.game.parse_move(line) {
+                Ok(available_move) => {
+                    // A move typed after the flag fell doesn't count, outcome reports the flag instead.
+                    let elapsed: Duration = self.turn_started.elapsed();
+                    if self.game.punch_clock(elapsed) {
+                        self.recorder
+                            .make_move(&mut self.game, available_move, elapsed, None);
+                    }
+                    self.turn_started = Instant::now();
+                    // Moving instead of answering turns a draw offer down.
+                    self.draw_offered = false;
+                    match self.game.outcome() {
+                        Some(outcome) => {
+                            self.finish(format!("{} {{{}}}", outcome.result(), outcome.reason()))
+                        }
+                        None => self.show(),
+                    }
+                }
+                Err(why) => self.send(&why),
+            },
+        }
+        true
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.output, line);
+    }
+
+    fn help(&self) {
+        self.send("Type a move as SAN (e4, Nf3, exd5, O-O, e8=Q) or coordinates (e2e4, e7e8q).");
+        self.send("moves     list the legal moves");
+        self.send("undo      take back the last move");
+        self.send("fen       show the position as FEN");
+        self.send("flip      turn the board around");
+        self.send("hint S    show where the piece on S can go");
+        self.send("threats   show the squares the opponent attacks");
+        self.send("why M     explain why the move M can't be played");
+        self.send("resign    give up");
+        self.send("draw      offer a draw, or accept one");
+        self.send("save F    write the game to F as PGN");
+        self.send("load F    read a game from the PGN file F");
+        self.send("export P  write the game's log to P.csv, P-games.csv and P.mat");
+        self.send("new [P]   start again, from position P if given");
+        self.send("quit      leave");
+    }
+
+    // The board, then the status panel ending with whose turn it is.
+    fn show(&mut self) {
+        self.send(&self.renderer.render(&self.game));
+        let status: String = self.renderer.status(&mut self.game);
+        self.send(&status);
+    }
+
+    fn finish(&mut self, result: String) {
+        self.send(&self.renderer.render(&self.game));
+        self.send(&format!("Game over: {}", result));
+        let (score, reason) = result.split_once(' ').unwrap_or((&result, ""));
+        self.recorder.finish(score, reason.trim_matches(['{', '}']));
+        self.finished = Some(result);
+    }
+
+    fn setup(&mut self, position: &str) -> Result<Game<'static>, String> {
+        self.game_counter += 1;
+        let fen: &str = self
+            .position_name_map
+            .get(position)
+            .map_or(position, |f| f.as_str());
+        let standard: &str = self
+            .position_name_map
+            .get(&self.variant)
+            .map_or(fen, |f| f.as_str());
+        Game::from_fen(
+            self.game_counter,
+            fen,
+            standard,
+            self.piece_list.map_piecetypes_to_symbols(),
+        )
+    }
+
+    fn pgn(&self) -> String {
+        let result: &str = self
+            .finished
+            .as_deref()
+            .and_then(|f| f.split(' ').next())
+            .unwrap_or("*");
+        let start: String = starting_position(&self.game).to_fen();
+        let mut tags: Vec<(&str, String)> = vec![
+            ("Event", String::from("Casual game")),
+            ("Site", String::from("?")),
+            ("Date", String::from("????.??.??")),
+            ("Round", String::from("-")),
+            ("White", String::from("White")),
+            ("Black", String::from("Black")),
+            ("Result", String::from(result)),
+            ("Variant", self.variant.clone()),
+        ];
+        if let Some(clocks) = &self.game.clocks {
+            tags.push(("TimeControl", clocks.control.to_pgn()));
+        }
+        if self.position_name_map.get(&self.variant) != Some(&start) {
+            tags.push(("SetUp", String::from("1")));
+            tags.push(("FEN", start));
+        }
+        to_pgn(&self.game, &tags, result)
+    }
+
+    // Replaces the current game only if every move in the file can be played.
+    fn load(&mut self, path: &str) -> Result<(), String> {
+        let text: String = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let (tags, moves, result) = parse_pgn(&text);
+        let tag = |name: &str| tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+        let previous_variant: String = self.variant.clone();
+        if let Some(variant) = tag("Variant").filter(|v| self.position_name_map.contains_key(v)) {
+            self.variant = variant;
+        }
+        let start: String = tag("FEN").unwrap_or_else(|| self.variant.clone());
+        let mut game: Game<'static> = match self.setup(&start) {
+            Ok(game) => game,
+            Err(why) => {
+                self.variant = previous_variant;
+                return Err(why);
+            }
+        };
+        // Timed games carry on from the last [%clk] of each side.
+        let control: Option<TimeControl> =
+            tag("TimeControl").and_then(|t| TimeControl::from_pgn(&t).ok());
+        if let Some(control) = &control {
+            game.clocks = Some(Clocks::resume(
+                control.clone(),
+                &pgn_clock_times(&text),
+                game.active_color,
+            ));
+        }
+        let mut recorder: Recorder = Recorder::new("human", "human", &game);
+        for (number, text) in moves.iter().enumerate() {
+            match game.parse_move(text) {
+                Ok(available_move) => {
+                    recorder.make_move(&mut game, available_move, Duration::ZERO, None);
+                }
+                Err(why) => {
+                    self.variant = previous_variant;
+                    return Err(format!("move {} ({}): {}", number + 1, text, why));
+                }
+            }
+        }
+        if let Some(result) = result.as_deref().filter(|r| *r != "*") {
+            recorder.finish(result, &tag("Termination").unwrap_or_default());
+        }
+        self.game = game;
+        self.recorder = recorder;
+        self.draw_offered = false;
+        self.time_control = control;
+        self.turn_started = Instant::now();
+        self.finished = match result.as_deref() {
+            Some(result) if result != "*" => Some(String::from(result)),
+            _ => None,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::repl::*;
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    fn run_script(script: &str) -> String {
+        let buffer: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut repl: Repl = Repl::new(
+            "testfiles/standardPieces.json",
+            "testfiles/standardStartingPositions.json",
+            buffer.clone(),
+        )
+        .unwrap();
+        repl.run(Cursor::new(script));
+        let output: Vec<u8> = buffer.lock().unwrap().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn plays_a_game_to_mate() {
+        let output: String = run_script("f3\ne5\ng4\nQh4\nNf3\n");
+        assert!(output.starts_with(" 8  r n b q k b n r\n"));
+        assert!(output.contains("Black to move.\n"));
+        assert!(output.ends_with(
+            "Game over: 0-1 {Black mates}\nThe game is over. Type new, load, undo, export or quit.\n"
+        ));
+    }
+
+    #[test]
+    fn rejects_illegal_moves_and_handles_commands() {
+        let output: String = run_script("e5\ne2e5\nd4\nfen\nflip\ndraw\ndraw\nundo\nresign\n");
+        assert!(output.contains("e5 isn't a legal move here."));
+        assert!(output.contains("The pawn on e2 can't move to e5."));
+        assert!(output.contains("rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 1\n"));
+        assert!(output.contains(" 1  R N B K Q B N R\n"));
+        assert!(output.contains("Game over: 1/2-1/2 {Draw agreed}"));
+        assert!(output.ends_with("Game over: 0-1 {White resigns}\n"));
+    }
+
+    #[test]
+    fn saves_and_loads_pgn() {
+        let path: std::path::PathBuf =
+            std::env::temp_dir().join(format!("piecetestbed-repl-{}.pgn", std::process::id()));
+        let path: &str = path.to_str().unwrap();
+        let output: String = run_script(&format!(
+            "e4\nc5\nNf3\nsave {}\nnew\nload {}\nfen\n",
+            path, path
+        ));
+        let pgn: String = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(pgn.contains("[Result \"*\"]"));
+        assert!(pgn.ends_with("\n1. e4 c5 2. Nf3 *\n"));
+        assert!(
+            output.ends_with("rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2\n")
+        );
+    }
+
+    #[test]
+    fn exports_the_game() {
+        let prefix: std::path::PathBuf =
+            std::env::temp_dir().join(format!("piecetestbed-export-{}", std::process::id()));
+        let prefix: &str = prefix.to_str().unwrap();
+        let output: String = run_script(&format!("f3\ne5\nd4\nundo\ng4\nQh4\nexport {}\n", prefix));
+        let plies: String = std::fs::read_to_string(format!("{}.csv", prefix)).unwrap();
+        let games: String = std::fs::read_to_string(format!("{}-games.csv", prefix)).unwrap();
+        for extension in [".csv", "-games.csv", ".mat"] {
+            std::fs::remove_file(format!("{}{}", prefix, extension)).unwrap();
+        }
+        assert!(output.contains(&format!("Wrote {}.csv", prefix)));
+        // The undone move is gone, and the game ends in mate.
+        let rows: Vec<Vec<String>> = parse_csv(&plies);
+        let san: usize = rows[0].iter().position(|c| c == "san").unwrap();
+        let sans: Vec<&str> = rows[1..].iter().map(|r| r[san].as_str()).collect();
+        assert_eq!(sans, vec!["f3", "e5", "g4", "Qh4#"]);
+        assert!(games.contains("0-1"));
+    }
+
+    #[test]
+    fn keeps_time() {
+        let path: std::path::PathBuf =
+            std::env::temp_dir().join(format!("piecetestbed-clock-{}.pgn", std::process::id()));
+        let path: &str = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "[Variant \"standard\"]\n[TimeControl \"300+2\"]\n\n1. e4 {[%clk 0:04:58]} 1... e5 {[%clk 0:04:30]} *\n",
+        )
+        .unwrap();
+        let output: String = run_script(&format!("load {}\nsave {}\n", path, path));
+        let pgn: String = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(output.contains("Clocks: White 0:04:58, Black 0:04:30\n"));
+        assert!(pgn.contains("[TimeControl \"300+2\"]"));
+        assert!(pgn.ends_with("\n1. e4 {[%clk 0:04:58]} 1... e5 {[%clk 0:04:30]} *\n"));
+    }
+}
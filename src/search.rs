@@ -0,0 +1,495 @@
+use crate::evaluation::*;
+use crate::game::*;
+use crate::tablebase::*;
+use crate::transposition::*;
+use crate::BOARDSIZE;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const MATE: i32 = 100_000;
+pub const INFINITY: i32 = 1_000_000;
+pub const DRAW: i32 = 0;
+pub const MAX_PLY: usize = 128;
+
+// Key of a move as used for killers, history and the principal variation. See AvailableMove::key.
+pub type MoveKey = (u8, u8, Option<char>);
+
+// When to stop searching. Anything left as None doesn't limit the search.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub nodes: Option<u64>,
+}
+
+// The outcome of the deepest finished iteration.
+#[derive(Debug, Clone)]
+pub struct SearchResult<'a> {
+    pub best_move: Option<AvailableMove<'a>>,
+    pub score: i32,
+    pub depth: u32,
+    pub principal_variation: Vec<AvailableMove<'a>>,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    pub table: TableStats,
+}
+
+// Negamax alpha-beta with iterative deepening. It only knows about the game through scan_available_moves,
+// so it plays whatever variant pieces.json describes.
+pub struct Searcher {
+    // Set from another thread to make the search return as soon as it can.
+    // Whoever sets it clears it again before the next search, so a stop sent early isn't lost.
+    pub stop: Arc<AtomicBool>,
+    pub evaluator: Arc<dyn Evaluator>,
+    pub table: TranspositionTable,
+    // Endings with few enough pieces are looked up rather than searched.
+    pub tablebases: Option<Arc<Tablebases>>,
+    limits: SearchLimits,
+    start: Instant,
+    nodes: u64,
+    stopped: bool,
+    killers: Vec<[Option<MoveKey>; 2]>,
+    history: Vec<i32>,
+    previous_principal_variation: Vec<MoveKey>,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Searcher::new()
+    }
+}
+
+impl Searcher {
+    pub fn new() -> Searcher {
+        let squares: usize = BOARDSIZE as usize * BOARDSIZE as usize;
+        Searcher {
+            stop: Arc::new(AtomicBool::new(false)),
+            evaluator: Arc::new(Material),
+            table: TranspositionTable::new(DEFAULT_HASH_MB, Replacement::DepthPreferred),
+            tablebases: None,
+            limits: SearchLimits::default(),
+            start: Instant::now(),
+            nodes: 0,
+            stopped: false,
+            killers: vec![[None, None]; MAX_PLY],
+            history: vec![0; squares * squares],
+            previous_principal_variation: Vec::new(),
+        }
+    }
+
+    // Search deeper and deeper until a limit is hit. report is called after every finished iteration.
+    pub fn search<'a>(
+        &mut self,
+        game: &mut Game<'a>,
+        limits: SearchLimits,
+        report: &mut dyn FnMut(&SearchResult<'a>),
+    ) -> SearchResult<'a> {
+        self.limits = limits;
+        self.start = Instant::now();
+        self.nodes = 0;
+        self.stopped = false;
+        self.killers = vec![[None, None]; MAX_PLY];
+        self.history.iter_mut().for_each(|h| *h = 0);
+        self.previous_principal_variation.clear();
+        self.table.new_search();
+        let root_moves: Vec<AvailableMove<'a>> = game.scan_available_moves();
+        let mut best: SearchResult<'a> = SearchResult {
+            best_move: root_moves.first().copied(),
+            score: if root_moves.is_empty() {
+                self.no_moves_score(game, 0)
+            } else {
+                DRAW
+            },
+            depth: 0,
+            principal_variation: Vec::new(),
+            nodes: 0,
+            elapsed: Duration::ZERO,
+            table: TableStats::default(),
+        };
+        let max_depth: u32 = limits
+            .depth
+            .unwrap_or(MAX_PLY as u32 - 1)
+            .min(MAX_PLY as u32 - 1);
+        let mut depth: u32 = 1;
+        while !root_moves.is_empty() && depth <= max_depth {
+            let mut principal_variation: Vec<AvailableMove<'a>> = Vec::new();
+            let score: i32 = self.negamax(
+                game,
+                depth,
+                -INFINITY,
+                INFINITY,
+                0,
+                &mut principal_variation,
+            );
+            // A search cut short part of the way through an iteration can't be trusted.
+            if self.stopped || principal_variation.is_empty() {
+                break;
+            }
+            self.previous_principal_variation =
+                principal_variation.iter().map(|m| m.key()).collect();
+            best = SearchResult {
+                best_move: principal_variation.first().copied(),
+                score,
+                depth,
+                principal_variation,
+                nodes: self.nodes,
+                elapsed: self.start.elapsed(),
+                table: self.table.stats(),
+            };
+            report(&best);
+            // Another iteration takes longer than all the previous ones put together, so don't start one we can't finish.
+            if let Some(movetime) = limits.movetime {
+                if self.start.elapsed() * 2 > movetime {
+                    break;
+                }
+            }
+            depth += 1;
+        }
+        best.nodes = self.nodes;
+        best.elapsed = self.start.elapsed();
+        best.table = self.table.stats();
+        best
+    }
+
+    fn negamax<'a>(
+        &mut self,
+        game: &mut Game<'a>,
+        depth: u32,
+        mut alpha: i32,
+        beta: i32,
+        ply: usize,
+        principal_variation: &mut Vec<AvailableMove<'a>>,
+    ) -> i32 {
+        principal_variation.clear();
+        if self.out_of_budget() {
+            return DRAW;
+        }
+        self.nodes += 1;
+        if ply > 0 && (game.halfmove_clock >= 100 || game.repetitions() > 0) {
+            return DRAW;
+        }
+        if ply > 0 {
+            if let Some(result) = self.tablebases.as_ref().and_then(|t| t.probe(game)) {
+                return tablebase_score(result, ply);
+            }
+        }
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(game, alpha, beta, ply);
+        }
+        // A deep enough result for this position can be used as is, and its best move is worth trying first either way.
+        let mut table_move: Option<MoveKey> = None;
+        if let Some(probe) = self.table.probe(game.hash, ply) {
+            let usable: bool = match probe.bound {
+                Bound::Exact => true,
+                Bound::Lower => probe.score >= beta,
+                Bound::Upper => probe.score <= alpha,
+            };
+            if ply > 0 && probe.depth >= depth && usable {
+                return probe.score;
+            }
+            table_move = probe.best_move;
+        }
+        let mut moves: Vec<AvailableMove<'a>> = game.scan_available_moves();
+        if moves.is_empty() {
+            return self.no_moves_score(game, ply);
+        }
+        self.order_moves(game, &mut moves, ply, table_move);
+        let original_alpha: i32 = alpha;
+        let mut child_variation: Vec<AvailableMove<'a>> = Vec::new();
+        let mut best: i32 = -INFINITY;
+        let mut best_move: Option<MoveKey> = None;
+        for available_move in moves {
+            game.make_move(available_move);
+            let score: i32 = -self.negamax(
+                game,
+                depth - 1,
+                -beta,
+                -alpha,
+                ply + 1,
+                &mut child_variation,
+            );
+            game.unmake_move();
+            if self.stopped {
+                return DRAW;
+            }
+            if score > best {
+                best = score;
+                best_move = Some(available_move.key());
+            }
+            if score > alpha {
+                alpha = score;
+                principal_variation.clear();
+                principal_variation.push(available_move);
+                principal_variation.extend(child_variation.iter().copied());
+            }
+            if alpha >= beta {
+                if !available_move.is_capture() {
+                    self.remember_cutoff(available_move.key(), depth, ply);
+                }
+                break;
+            }
+        }
+        let bound: Bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.table
+            .store(game.hash, depth, best, bound, best_move, ply);
+        best
+    }
+
+    // Keep searching captures until the position is quiet, so the evaluation isn't taken in the middle of an exchange.
+    fn quiescence<'a>(
+        &mut self,
+        game: &mut Game<'a>,
+        mut alpha: i32,
+        beta: i32,
+        ply: usize,
+    ) -> i32 {
+        if self.out_of_budget() {
+            return DRAW;
+        }
+        self.nodes += 1;
+        // In check there's no standing pat: every evasion gets searched, and having none is mate.
+        let in_check: bool = game.royal_in_check(game.active_color);
+        if !in_check || ply >= MAX_PLY - 1 {
+            let stand_pat: i32 = self.evaluator.evaluate(game);
+            if stand_pat >= beta || ply >= MAX_PLY - 1 {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+        }
+        let moves: Vec<AvailableMove<'a>> = game.scan_available_moves();
+        if in_check && moves.is_empty() {
+            return self.no_moves_score(game, ply);
+        }
+        let mut captures: Vec<AvailableMove<'a>> = moves
+            .into_iter()
+            .filter(|m| in_check || m.is_capture())
+            .collect();
+        self.order_moves(game, &mut captures, ply, None);
+        for available_move in captures {
+            game.make_move(available_move);
+            let score: i32 = -self.quiescence(game, -beta, -alpha, ply + 1);
+            game.unmake_move();
+            if self.stopped {
+                return DRAW;
+            }
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    // Checkmate if a royal piece is attacked, otherwise stalemate. Closer mates score higher.
+    fn no_moves_score(&self, game: &Game, ply: usize) -> i32 {
+        if game.royal_in_check(game.active_color) {
+            -MATE + ply as i32
+        } else {
+            DRAW
+        }
+    }
+
+    fn out_of_budget(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+        if let Some(nodes) = self.limits.nodes {
+            if self.nodes >= nodes {
+                self.stopped = true;
+            }
+        }
+        // Checking the clock on every node would slow everything down.
+        if self.nodes.is_multiple_of(1024) {
+            let out_of_time: bool = match self.limits.movetime {
+                Some(movetime) => self.start.elapsed() >= movetime,
+                None => false,
+            };
+            if out_of_time || self.stop.load(Ordering::Relaxed) {
+                self.stopped = true;
+            }
+        }
+        self.stopped
+    }
+
+    // Quiet moves that cause a cutoff are worth trying early elsewhere: as killers at the same ply, and through the history table.
+    fn remember_cutoff(&mut self, key: MoveKey, depth: u32, ply: usize) {
+        let killers: &mut [Option<MoveKey>; 2] = &mut self.killers[ply];
+        if killers[0] != Some(key) {
+            killers[1] = killers[0];
+            killers[0] = Some(key);
+        }
+        let squares: usize = BOARDSIZE as usize * BOARDSIZE as usize;
+        self.history[key.0 as usize * squares + key.1 as usize] += (depth * depth) as i32;
+    }
+
+    // Principal variation first, then the transposition table's move, then captures by most valuable victim / least valuable attacker,
+    // then killers, then history.
+    fn order_moves(
+        &self,
+        game: &Game,
+        moves: &mut [AvailableMove],
+        ply: usize,
+        table_move: Option<MoveKey>,
+    ) {
+        let on_principal_variation: bool = ply < self.previous_principal_variation.len()
+            && game.list_of_moves.len() >= ply + game.setup_moves
+            && game.list_of_moves[game.list_of_moves.len() - ply..]
+                .iter()
+                .map(|record| record.played.key())
+                .eq(self.previous_principal_variation[..ply].iter().copied());
+        let squares: usize = BOARDSIZE as usize * BOARDSIZE as usize;
+        moves.sort_by_cached_key(|m| {
+            let key: MoveKey = m.key();
+            let score: i32 =
+                if on_principal_variation && self.previous_principal_variation[ply] == key {
+                    4_000_000
+                } else if table_move == Some(key) {
+                    3_000_000
+                } else if let Some(captured_id) = m.captured_piece_id {
+                    2_000_000
+                        + 10 * self
+                            .evaluator
+                            .piece_value(game.piece(captured_id).piece_type)
+                        - self
+                            .evaluator
+                            .piece_value(game.piece(m.piece_id).piece_type)
+                } else if m.promotion.is_some() {
+                    1_500_000
+                } else if self.killers[ply][0] == Some(key) {
+                    1_000_002
+                } else if self.killers[ply][1] == Some(key) {
+                    1_000_001
+                } else {
+                    self.history[key.0 as usize * squares + key.1 as usize].min(1_000_000)
+                };
+            -score
+        });
+    }
+}
+
+// How long to think given the clock: an even share of the time left over the moves still to play
+// (30 if the time control doesn't say), plus most of the increment. Never more than half of what's left.
+pub fn time_for_move(
+    remaining: Duration,
+    increment: Duration,
+    moves_to_go: Option<u32>,
+) -> Duration {
+    let share: Duration = remaining / moves_to_go.unwrap_or(30).max(1) + increment * 3 / 4;
+    share.min(remaining / 2)
+}
+
+// Mate scores like no_moves_score gives, counted from the root.
+fn tablebase_score(result: TablebaseResult, ply: usize) -> i32 {
+    match result {
+        TablebaseResult::Win(plies) => MATE - ply as i32 - plies as i32,
+        TablebaseResult::Loss(plies) => -MATE + ply as i32 + plies as i32,
+        TablebaseResult::Draw => DRAW,
+    }
+}
+
+// A finished iteration as a UCI info line, which doubles as a readable summary on the console.
+pub fn format_info(result: &SearchResult) -> String {
+    let score: String = if result.score > MATE - MAX_PLY as i32 {
+        format!("mate {}", (MATE - result.score + 1) / 2)
+    } else if result.score < -MATE + MAX_PLY as i32 {
+        format!("mate -{}", (MATE + result.score) / 2)
+    } else {
+        format!("cp {}", result.score)
+    };
+    let principal_variation: Vec<String> = result
+        .principal_variation
+        .iter()
+        .map(|m| m.coordinate_notation())
+        .collect();
+    format!(
+        "info depth {} score {} nodes {} time {} hashfull {} pv {}",
+        result.depth,
+        score,
+        result.nodes,
+        result.elapsed.as_millis(),
+        result.table.hashfull,
+        principal_variation.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::search::*;
+    use std::path::Path;
+
+    fn search_fen(fen: &str, limits: SearchLimits) -> (Option<String>, i32, u64) {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game = Game::from_fen(
+            0,
+            fen,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        let result: SearchResult = Searcher::new().search(&mut game, limits, &mut |_| {});
+        (
+            result.best_move.map(|m| m.coordinate_notation()),
+            result.score,
+            result.nodes,
+        )
+    }
+
+    fn depth(depth: u32) -> SearchLimits {
+        SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        }
+    }
+
+    #[test]
+    fn finds_back_rank_mate() {
+        let (best_move, score, _) = search_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", depth(2));
+        assert_eq!(best_move, Some(String::from("a1a8")));
+        assert_eq!(score, MATE - 1);
+    }
+
+    #[test]
+    fn sees_mate_on_the_last_ply() {
+        let (best_move, score, _) = search_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", depth(1));
+        assert_eq!(best_move, Some(String::from("a1a8")));
+        assert_eq!(score, MATE - 1);
+    }
+
+    #[test]
+    fn takes_a_hanging_queen() {
+        let (best_move, score, _) = search_fen("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1", depth(2));
+        assert_eq!(best_move, Some(String::from("d1d5")));
+        assert!(score > 0);
+    }
+
+    #[test]
+    fn stalemate_is_a_draw() {
+        let (best_move, score, _) = search_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", depth(3));
+        assert_eq!(best_move, None);
+        assert_eq!(score, DRAW);
+    }
+
+    #[test]
+    fn node_limit_is_respected() {
+        let limits: SearchLimits = SearchLimits {
+            nodes: Some(500),
+            ..SearchLimits::default()
+        };
+        let (best_move, _, nodes) = search_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            limits,
+        );
+        assert!(best_move.is_some());
+        assert!(nodes <= 500);
+    }
+}
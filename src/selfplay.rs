@@ -0,0 +1,600 @@
+use crate::clock::*;
+use crate::configuration::*;
+use crate::export::*;
+use crate::game::*;
+use crate::matches::*;
+use crate::player::*;
+use crate::random::*;
+use crate::recorder::*;
+use crate::uci::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+
+// What goes in a self-play directory. The CSVs are the same as --export writes, so summarize reads them as they are.
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+pub const PLIES_FILE: &str = "selfplay.csv";
+pub const GAMES_FILE: &str = "selfplay-games.csv";
+// Random moves at the start of every game unless --random-plies says otherwise. Enough for deterministic engines
+// to play something different each game without wrecking the opening.
+pub const RANDOM_PLIES: usize = 4;
+
+// The settings of a run and how far it got, rewritten after every game.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub games: u64,
+    pub white: String,
+    pub black: String,
+    // A name from startingPositions.json or a FEN.
+    pub position: String,
+    pub evaluator: String,
+    // In PGN form, like 180+2.
+    pub time_control: Option<String>,
+    // See GameSetup. Runs checkpointed before there were any have none.
+    #[serde(default)]
+    pub random_plies: usize,
+    // Games finished and logged, which is also the ID of the next one.
+    pub completed: u64,
+    // Lengths of the logs after the last finished game. Anything past them was cut off half written.
+    pub plies_bytes: u64,
+    pub games_bytes: u64,
+}
+
+pub struct SelfPlay {
+    pub directory: PathBuf,
+    pub checkpoint: Checkpoint,
+    // Set by catch_interrupts: stop ends the run once the games being played are over, abandon ends it now.
+    pub stop: Arc<AtomicBool>,
+    pub abandon: Arc<AtomicBool>,
+}
+
+impl SelfPlay {
+    // A fresh run. The directory is made if it isn't there, but mustn't hold another run.
+    pub fn start(directory: &Path, checkpoint: Checkpoint) -> Result<SelfPlay, String> {
+        if directory.join(CHECKPOINT_FILE).exists() {
+            return Err(format!(
+                "{} already has a run in it, carry on with selfplay --resume {}",
+                directory.display(),
+                directory.display()
+            ));
+        }
+        if checkpoint.white == "human" || checkpoint.black == "human" {
+            return Err(String::from(
+                "self-play needs two computer players, pick them with --white and --black",
+            ));
+        }
+        std::fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+        let run: SelfPlay = SelfPlay::new(directory, checkpoint);
+        run.save()?;
+        Ok(run)
+    }
+
+    // Carry on from the checkpoint in the directory.
+    pub fn resume(directory: &Path) -> Result<SelfPlay, String> {
+        let text: String = std::fs::read_to_string(directory.join(CHECKPOINT_FILE))
+            .map_err(|e| format!("no run in {}: {}", directory.display(), e))?;
+        let checkpoint: Checkpoint = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        Ok(SelfPlay::new(directory, checkpoint))
+    }
+
+    fn new(directory: &Path, checkpoint: Checkpoint) -> SelfPlay {
+        SelfPlay {
+            directory: directory.to_path_buf(),
+            checkpoint,
+            stop: Arc::new(AtomicBool::new(false)),
+            abandon: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Ctrl-C can only be caught once per process, so only main calls this.
+    pub fn handle_interrupts(&self) -> Result<(), String> {
+        catch_interrupts(self.stop.clone(), self.abandon.clone())
+    }
+
+    // Play until the run is done or stopped, on as many threads as asked for. Finished games are logged
+    // in ID order, and game_counter is the ID of the next one to be logged.
+    // Only whole games are logged. An abandoned one, and any finished after it, are played again when the run is resumed.
+    pub fn run<'a>(
+        &mut self,
+        game_counter: &mut u64,
+        threads: usize,
+        piece_symbol_map: &HashMap<char, &'a PieceType>,
+        position_name_map: &HashMap<String, String>,
+        on_game: &mut dyn FnMut(&GameLog),
+    ) -> Result<(), String> {
+        self.trim_logs()?;
+        let control: Option<TimeControl> = match &self.checkpoint.time_control {
+            Some(control) => Some(TimeControl::from_pgn(control)?),
+            None => None,
+        };
+        *game_counter = self.checkpoint.completed;
+        let setup: GameSetup = GameSetup {
+            white: self.checkpoint.white.clone(),
+            black: self.checkpoint.black.clone(),
+            position: self.checkpoint.position.clone(),
+            evaluator: self.checkpoint.evaluator.clone(),
+            control,
+            max_plies: None,
+            random_plies: self.checkpoint.random_plies,
+            opening_seed: None,
+        };
+        let (stop, abandon) = (self.stop.clone(), self.abandon.clone());
+        in_parallel(
+            *game_counter..self.checkpoint.games,
+            threads,
+            &stop,
+            &|game_id| {
+                play_game(
+                    game_id,
+                    &setup,
+                    &abandon,
+                    piece_symbol_map,
+                    position_name_map,
+                )
+            },
+            &mut |_, log: GameLog| {
+                if let Err(why) = self.log_game(&log) {
+                    // Nothing more can be logged, so there's no point finishing the others.
+                    self.abandon.store(true, Ordering::SeqCst);
+                    return Err(why);
+                }
+                *game_counter += 1;
+                on_game(&log);
+                Ok(())
+            },
+        )
+    }
+
+    // Adds a finished game to the logs, then moves the checkpoint past it.
+    fn log_game(&mut self, log: &GameLog) -> Result<(), String> {
+        self.checkpoint.plies_bytes = append_rows(
+            &self.directory.join(PLIES_FILE),
+            &plies_csv(std::slice::from_ref(log)),
+        )
+        .map_err(|e| e.to_string())?;
+        self.checkpoint.games_bytes = append_rows(
+            &self.directory.join(GAMES_FILE),
+            &games_csv(std::slice::from_ref(log)),
+        )
+        .map_err(|e| e.to_string())?;
+        self.checkpoint.completed = log.game_id + 1;
+        self.save()
+    }
+
+    // Written to one side and renamed over the old one, so a checkpoint is never half there.
+    fn save(&self) -> Result<(), String> {
+        let path: PathBuf = self.directory.join(CHECKPOINT_FILE);
+        let temporary: PathBuf = path.with_extension("json.tmp");
+        let text: String =
+            serde_json::to_string_pretty(&self.checkpoint).map_err(|e| e.to_string())?;
+        std::fs::write(&temporary, text).map_err(|e| e.to_string())?;
+        std::fs::rename(&temporary, &path).map_err(|e| e.to_string())
+    }
+
+    // Cut the logs back to the end of the last finished game, in case the last run died while writing.
+    fn trim_logs(&self) -> Result<(), String> {
+        for (name, length) in [
+            (PLIES_FILE, self.checkpoint.plies_bytes),
+            (GAMES_FILE, self.checkpoint.games_bytes),
+        ] {
+            let path: PathBuf = self.directory.join(name);
+            if !path.exists() && length == 0 {
+                continue;
+            }
+            let file = OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            if file.metadata().map_err(|e| e.to_string())?.len() < length {
+                return Err(format!(
+                    "{} is shorter than the checkpoint says",
+                    path.display()
+                ));
+            }
+            file.set_len(length).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+// Adds a CSV's rows to the end of a file, with the header only when the file is new, and makes sure
+// they're on disk. Returns the file's new length.
+fn append_rows(path: &Path, csv: &str) -> std::io::Result<u64> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let length: u64 = file.metadata()?.len();
+    let rows: &str = if length == 0 {
+        csv
+    } else {
+        csv.split_once('\n').map_or("", |(_, rows)| rows)
+    };
+    file.write_all(rows.as_bytes())?;
+    file.sync_data()?;
+    Ok(length + rows.len() as u64)
+}
+
+// The first Ctrl-C sets stop, so no more games are started, and the second sets abandon, so the ones
+// being played end there and then.
+pub fn catch_interrupts(stop: Arc<AtomicBool>, abandon: Arc<AtomicBool>) -> Result<(), String> {
+    ctrlc::set_handler(move || {
+        if stop.swap(true, Ordering::SeqCst) {
+            abandon.store(true, Ordering::SeqCst);
+            eprintln!("\nAbandoning the games being played.");
+        } else {
+            eprintln!("\nStopping after the games being played, Ctrl-C again to stop now.");
+        }
+    })
+    .map_err(|e| e.to_string())
+}
+
+// Everything about a game between two computer players apart from the rules and its ID.
+#[derive(Debug, Clone)]
+pub struct GameSetup {
+    pub white: String,
+    pub black: String,
+    // A name from startingPositions.json or a FEN.
+    pub position: String,
+    // For players that don't pick their own.
+    pub evaluator: String,
+    pub control: Option<TimeControl>,
+    // Plies after which the game is called a draw.
+    pub max_plies: Option<usize>,
+    // Moves played at random, seeded by the game ID, before the players take over. Deterministic
+    // engines would otherwise play the same game every time.
+    pub random_plies: usize,
+    // Seeds the random moves instead of the game ID, so that games can share them.
+    pub opening_seed: Option<u64>,
+}
+
+// Plays one game, or returns None if it was abandoned.
+pub fn play_game<'a>(
+    game_id: u64,
+    setup: &GameSetup,
+    abandon: &Arc<AtomicBool>,
+    piece_symbol_map: &HashMap<char, &'a PieceType>,
+    position_name_map: &HashMap<String, String>,
+) -> Result<Option<GameLog>, String> {
+    let mut game: Game<'a> = crate::setup_game(
+        game_id,
+        &setup.position,
+        piece_symbol_map.clone(),
+        position_name_map,
+    )?;
+    let mut random: Random = Random::new(setup.opening_seed.unwrap_or(game_id));
+    for _ in 0..setup.random_plies {
+        let moves: Vec<AvailableMove<'a>> = game.scan_available_moves();
+        if moves.is_empty() {
+            break;
+        }
+        game.make_move(moves[random.below(moves.len())]);
+    }
+    // Nothing needs to hear from computer players.
+    let output: SharedOutput = Arc::new(Mutex::new(std::io::sink()));
+    let white: Box<dyn Player<'a> + 'a> = player_from_spec(
+        &reseed(&setup.white, game_id),
+        &mut game,
+        &setup.evaluator,
+        output.clone(),
+    )?;
+    let black: Box<dyn Player<'a> + 'a> = player_from_spec(
+        &reseed(&setup.black, game_id),
+        &mut game,
+        &setup.evaluator,
+        output,
+    )?;
+    let mut played: Match<'a> = Match::new(white, black, game);
+    played.abandon = Some(abandon.clone());
+    played.max_plies = setup.max_plies;
+    if let Some(control) = &setup.control {
+        played.game.start_clocks(control.clone());
+    }
+    if played.play(&mut |_, _| {}) == Ending::Abandoned {
+        return Ok(None);
+    }
+    Ok(Some(played.recorder.log))
+}
+
+// Runs play for every ID on a pool of threads, each thread taking the next ID as it comes free, and hands
+// what comes back to collect in ID order whichever finishes first. The PieceTypes are only ever read, so
+// the threads can all borrow them. Threads stop taking IDs once stop is set, and a thread whose game was
+// abandoned or went wrong stops too.
+pub fn in_parallel<T: Send>(
+    ids: Range<u64>,
+    threads: usize,
+    stop: &AtomicBool,
+    play: &(dyn Fn(u64) -> Result<Option<T>, String> + Sync),
+    collect: &mut dyn FnMut(u64, T) -> Result<(), String>,
+) -> Result<(), String> {
+    let next: AtomicU64 = AtomicU64::new(ids.start);
+    let (sender, receiver) = mpsc::channel::<(u64, Result<T, String>)>();
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            let sender: Sender<(u64, Result<T, String>)> = sender.clone();
+            let (next, end) = (&next, ids.end);
+            scope.spawn(move || {
+                while !stop.load(Ordering::SeqCst) {
+                    let id: u64 = next.fetch_add(1, Ordering::SeqCst);
+                    if id >= end {
+                        break;
+                    }
+                    match play(id) {
+                        Ok(Some(played)) => {
+                            let _ = sender.send((id, Ok(played)));
+                        }
+                        Ok(None) => break,
+                        Err(why) => {
+                            let _ = sender.send((id, Err(why)));
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+        drop(sender);
+        // Results wait here until the ones before them are in.
+        let mut finished: HashMap<u64, T> = HashMap::new();
+        let mut expected: u64 = ids.start;
+        let mut failed: Option<String> = None;
+        for (id, played) in receiver {
+            match played {
+                Ok(played) => {
+                    finished.insert(id, played);
+                }
+                Err(why) => {
+                    stop.store(true, Ordering::SeqCst);
+                    failed.get_or_insert(format!("game {}: {}", id, why));
+                }
+            }
+            while let Some(played) = finished.remove(&expected) {
+                if let Err(why) = collect(expected, played) {
+                    // The scope waits for the others to see this.
+                    stop.store(true, Ordering::SeqCst);
+                    return Err(why);
+                }
+                expected += 1;
+            }
+        }
+        failed.map_or(Ok(()), Err)
+    })
+}
+
+// Every game gets its own seed, worked out from the game ID, so it plays out the same whichever thread
+// it lands on and can be played again on its own. A seed in the spec moves the whole run along, and
+// players that don't take a seed are left alone.
+fn reseed(spec: &str, game_id: u64) -> String {
+    let (kind, settings) = spec.split_once(':').unwrap_or((spec, ""));
+    // Engines only need a seed to pick between book moves.
+    if !matches!(kind, "random" | "mcts") && !settings.contains("book=") {
+        return String::from(spec);
+    }
+    let mut settings: Vec<String> = settings
+        .split(',')
+        .filter(|setting| !setting.is_empty())
+        .map(String::from)
+        .collect();
+    match settings.iter_mut().find(|s| s.starts_with("seed=")) {
+        Some(setting) => {
+            if let Ok(seed) = setting["seed=".len()..].parse::<u64>() {
+                *setting = format!("seed={}", seed.wrapping_add(game_id));
+            }
+        }
+        None => settings.push(format!("seed={}", game_id)),
+    }
+    format!("{}:{}", kind, settings.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::selfplay::*;
+
+    fn checkpoint(games: u64) -> Checkpoint {
+        Checkpoint {
+            games,
+            white: String::from("engine:depth=1"),
+            black: String::from("random:seed=1"),
+            position: String::from("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"),
+            evaluator: String::from("material"),
+            time_control: None,
+            random_plies: 0,
+            completed: 0,
+            plies_bytes: 0,
+            games_bytes: 0,
+        }
+    }
+
+    #[test]
+    fn stops_and_resumes() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let piece_symbol_map: HashMap<char, &PieceType> = piece_list.map_piecetypes_to_symbols();
+        let position_name_map: HashMap<String, String> =
+            PositionListIntermediateRepresentation::from_file(Path::new(
+                "testfiles/standardStartingPositions.json",
+            ))
+            .map_positions_to_names();
+        let directory: PathBuf = std::env::temp_dir().join("piecetestbed-selfplay");
+        let _ = std::fs::remove_dir_all(&directory);
+        let mut game_counter: u64 = 0;
+        let mut run: SelfPlay = SelfPlay::start(&directory, checkpoint(1)).unwrap();
+        assert!(SelfPlay::start(&directory, checkpoint(1)).is_err());
+        let mut played: Vec<u64> = Vec::new();
+        run.run(
+            &mut game_counter,
+            1,
+            &piece_symbol_map,
+            &position_name_map,
+            &mut |log| played.push(log.game_id),
+        )
+        .unwrap();
+        assert_eq!((played.as_slice(), game_counter), (&[0][..], 1));
+        // A game that was being written when the process died.
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(directory.join(PLIES_FILE))
+            .unwrap()
+            .write_all(b"1,1,w,half a row")
+            .unwrap();
+        // Abandoning logs nothing.
+        let mut run: SelfPlay = SelfPlay::resume(&directory).unwrap();
+        assert_eq!(run.checkpoint.completed, 1);
+        run.checkpoint.games = 2;
+        run.abandon.store(true, Ordering::SeqCst);
+        run.run(
+            &mut game_counter,
+            1,
+            &piece_symbol_map,
+            &position_name_map,
+            &mut |_| {},
+        )
+        .unwrap();
+        assert_eq!(game_counter, 1);
+        // Stopped before it starts, as after Ctrl-C.
+        let mut run: SelfPlay = SelfPlay::resume(&directory).unwrap();
+        assert_eq!(run.checkpoint.completed, 1);
+        run.checkpoint.games = 2;
+        run.stop.store(true, Ordering::SeqCst);
+        run.run(
+            &mut game_counter,
+            1,
+            &piece_symbol_map,
+            &position_name_map,
+            &mut |_| {},
+        )
+        .unwrap();
+        run.stop.store(false, Ordering::SeqCst);
+        run.run(
+            &mut game_counter,
+            1,
+            &piece_symbol_map,
+            &position_name_map,
+            &mut |log| played.push(log.game_id),
+        )
+        .unwrap();
+        assert_eq!((played.as_slice(), game_counter), (&[0, 1][..], 2));
+        let plies: String = std::fs::read_to_string(directory.join(PLIES_FILE)).unwrap();
+        let games: String = std::fs::read_to_string(directory.join(GAMES_FILE)).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        let logs: Vec<GameLog> = logs_from_csv(&plies, Some(&games)).unwrap();
+        assert_eq!(logs.len(), 2);
+        assert!(logs.iter().all(|log| log.result.is_some()));
+        assert_eq!((logs[0].game_id, logs[1].game_id), (0, 1));
+        assert_eq!(
+            (logs[0].black.as_str(), logs[1].black.as_str()),
+            ("random:seed=1", "random:seed=2")
+        );
+        assert_eq!(games.lines().count(), 3);
+    }
+
+    #[test]
+    fn same_games_on_any_number_of_threads() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let piece_symbol_map: HashMap<char, &PieceType> = piece_list.map_piecetypes_to_symbols();
+        let position_name_map: HashMap<String, String> =
+            PositionListIntermediateRepresentation::from_file(Path::new(
+                "testfiles/standardStartingPositions.json",
+            ))
+            .map_positions_to_names();
+        let mut runs: Vec<Vec<GameLog>> = Vec::new();
+        for threads in [1, 3] {
+            let directory: PathBuf =
+                std::env::temp_dir().join(format!("piecetestbed-selfplay-{}", threads));
+            let _ = std::fs::remove_dir_all(&directory);
+            let mut settings: Checkpoint = checkpoint(5);
+            settings.black = String::from("random");
+            let mut run: SelfPlay = SelfPlay::start(&directory, settings).unwrap();
+            let mut order: Vec<u64> = Vec::new();
+            let mut game_counter: u64 = 0;
+            run.run(
+                &mut game_counter,
+                threads,
+                &piece_symbol_map,
+                &position_name_map,
+                &mut |log| order.push(log.game_id),
+            )
+            .unwrap();
+            assert_eq!(order, vec![0, 1, 2, 3, 4]);
+            let plies: String = std::fs::read_to_string(directory.join(PLIES_FILE)).unwrap();
+            let games: String = std::fs::read_to_string(directory.join(GAMES_FILE)).unwrap();
+            std::fs::remove_dir_all(&directory).unwrap();
+            runs.push(logs_from_csv(&plies, Some(&games)).unwrap());
+        }
+        // Times differ from run to run, the moves and results don't.
+        let moves = |logs: &Vec<GameLog>| -> Vec<(u64, Option<String>, Vec<String>)> {
+            logs.iter()
+                .map(|log| {
+                    let sans: Vec<String> = log.plies.iter().map(|p| p.san.clone()).collect();
+                    (log.game_id, log.result.clone(), sans)
+                })
+                .collect()
+        };
+        assert_eq!(moves(&runs[0]), moves(&runs[1]));
+        assert_ne!(runs[0][0].plies, runs[0][1].plies);
+    }
+
+    #[test]
+    fn random_plies_vary_engine_games() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let piece_symbol_map: HashMap<char, &PieceType> = piece_list.map_piecetypes_to_symbols();
+        let position_name_map: HashMap<String, String> =
+            PositionListIntermediateRepresentation::from_file(Path::new(
+                "testfiles/standardStartingPositions.json",
+            ))
+            .map_positions_to_names();
+        let mut setup: GameSetup = GameSetup {
+            white: String::from("engine:depth=1"),
+            black: String::from("engine:depth=1"),
+            position: String::from("standard"),
+            evaluator: String::from("material"),
+            control: None,
+            max_plies: Some(8),
+            random_plies: 0,
+            opening_seed: None,
+        };
+        let abandon: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+        let sans = |setup: &GameSetup, game_id: u64| -> Vec<String> {
+            play_game(
+                game_id,
+                setup,
+                &abandon,
+                &piece_symbol_map,
+                &position_name_map,
+            )
+            .unwrap()
+            .unwrap()
+            .plies
+            .iter()
+            .map(|p| p.san.clone())
+            .collect()
+        };
+        assert_eq!(sans(&setup, 0), sans(&setup, 1));
+        setup.random_plies = RANDOM_PLIES;
+        assert_ne!(sans(&setup, 0), sans(&setup, 1));
+        // Games sharing a seed share their random moves.
+        setup.opening_seed = Some(7);
+        assert_eq!(sans(&setup, 0), sans(&setup, 1));
+    }
+
+    #[test]
+    fn reseeds_players() {
+        assert_eq!(reseed("random:seed=5", 3), "random:seed=8");
+        assert_eq!(
+            reseed("mcts:iterations=50,seed=1", 1),
+            "mcts:iterations=50,seed=2"
+        );
+        assert_eq!(reseed("engine:depth=2", 9), "engine:depth=2");
+        assert_eq!(
+            reseed("engine:depth=2,book=openings.bin", 9),
+            "engine:depth=2,book=openings.bin,seed=9"
+        );
+        assert_eq!(reseed("random", 9), "random:seed=9");
+        assert_eq!(reseed("mcts:iterations=50", 2), "mcts:iterations=50,seed=2");
+    }
+}
@@ -0,0 +1,245 @@
+use crate::game::*;
+use std::collections::HashMap;
+
+// One defence to a key and the attacker's moves that still mate in time after it. More than one is a dual.
+#[derive(Debug, Clone)]
+pub struct Variation {
+    pub defence: String,
+    pub continuations: Vec<String>,
+}
+
+// A first move that forces mate, in SAN and coordinates, and in how many moves at the quickest.
+#[derive(Debug, Clone)]
+pub struct Key {
+    pub san: String,
+    pub coordinate: String,
+    pub mate_in: u32,
+    pub variations: Vec<Variation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub fen: String,
+    pub mate_in: u32,
+    pub keys: Vec<Key>,
+    pub nodes: u64,
+}
+
+impl Solution {
+    // One key, and no mate quicker than the stipulation.
+    pub fn sound(&self) -> bool {
+        self.keys.len() == 1 && self.keys[0].mate_in == self.mate_in
+    }
+
+    pub fn report(&self) -> String {
+        let mut output: String = format!("{}\nMate in {}: ", self.fen, self.mate_in);
+        if self.keys.is_empty() {
+            output.push_str("no solution.\n");
+            return output;
+        }
+        if self.sound() {
+            output.push_str("sound, one key.\n");
+        } else if self.keys.len() > 1 {
+            output.push_str(&format!("cooked, {} keys.\n", self.keys.len()));
+        } else {
+            output.push_str("the key mates sooner than that.\n");
+        }
+        for key in &self.keys {
+            output.push_str(&format!(
+                "1. {}! ({}) mates in {}",
+                key.san, key.coordinate, key.mate_in
+            ));
+            if key.mate_in < self.mate_in {
+                output.push_str(" (short)");
+            }
+            output.push('\n');
+            for variation in &key.variations {
+                output.push_str(&format!(
+                    "    1... {} 2. {}{}\n",
+                    variation.defence,
+                    variation.continuations.join(" or "),
+                    if variation.continuations.len() > 1 {
+                        " (dual)"
+                    } else {
+                        ""
+                    }
+                ));
+            }
+        }
+        output.push_str(&format!("{} positions searched\n", self.nodes));
+        output
+    }
+}
+
+// Exhaustive AND/OR search for forced mates. The side to move at the start is the attacker: one of its moves has to work,
+// and every defence has to fail. Repetitions and the fifty move rule don't come into it.
+#[derive(Debug, Default)]
+pub struct MateSolver {
+    pub nodes: u64,
+    // Whether the attacker, to move, mates within so many moves. Transpositions are common in mating nets.
+    known: HashMap<(u64, u32), bool>,
+}
+
+impl MateSolver {
+    pub fn new() -> MateSolver {
+        MateSolver::default()
+    }
+
+    // Every key that mates within mate_in moves, with the defences to it, ready for checking a problem.
+    pub fn solve<'a>(&mut self, game: &mut Game<'a>, mate_in: u32) -> Solution {
+        let mut keys: Vec<Key> = Vec::new();
+        for key_move in game.scan_available_moves() {
+            let san: String = game.san(key_move);
+            game.make_move(key_move);
+            let quickest: Option<u32> =
+                (1..=mate_in).find(|moves| self.defence_fails(game, moves - 1));
+            let variations: Vec<Variation> = match quickest {
+                Some(moves) if moves > 1 => self.variations(game, moves - 1),
+                _ => Vec::new(),
+            };
+            game.unmake_move();
+            if let Some(moves) = quickest {
+                keys.push(Key {
+                    san,
+                    coordinate: key_move.coordinate_notation(),
+                    mate_in: moves,
+                    variations,
+                });
+            }
+        }
+        keys.sort_by(|a, b| a.mate_in.cmp(&b.mate_in).then(a.san.cmp(&b.san)));
+        Solution {
+            fen: game.to_fen(),
+            mate_in,
+            keys,
+            nodes: self.nodes,
+        }
+    }
+
+    // Whether the side to move can mate within `moves` of its own moves whatever the other side does.
+    pub fn forces_mate<'a>(&mut self, game: &mut Game<'a>, moves: u32) -> bool {
+        if moves == 0 {
+            return false;
+        }
+        if let Some(known) = self.known.get(&(game.hash, moves)) {
+            return *known;
+        }
+        self.nodes += 1;
+        let mut mates: bool = false;
+        for available_move in game.scan_available_moves() {
+            game.make_move(available_move);
+            mates = self.defence_fails(game, moves - 1);
+            game.unmake_move();
+            if mates {
+                break;
+            }
+        }
+        self.known.insert((game.hash, moves), mates);
+        mates
+    }
+
+    // After an attacking move: whether the defender is mated now, or every defence gets mated within `moves` more.
+    fn defence_fails<'a>(&mut self, game: &mut Game<'a>, moves: u32) -> bool {
+        self.nodes += 1;
+        let in_check: bool = game.royal_in_check(game.active_color);
+        // Only a check can be mate, so there's no need to look at the defences otherwise.
+        if moves == 0 && !in_check {
+            return false;
+        }
+        let defences: Vec<AvailableMove<'a>> = game.scan_available_moves();
+        if defences.is_empty() {
+            return in_check;
+        }
+        for defence in defences {
+            game.make_move(defence);
+            let mated: bool = self.forces_mate(game, moves);
+            game.unmake_move();
+            if !mated {
+                return false;
+            }
+        }
+        true
+    }
+
+    // The defences to a key, each with the attacker's replies that keep the mate to the same length.
+    fn variations<'a>(&mut self, game: &mut Game<'a>, moves: u32) -> Vec<Variation> {
+        let mut output: Vec<Variation> = Vec::new();
+        for defence in game.scan_available_moves() {
+            let defence_san: String = game.san(defence);
+            game.make_move(defence);
+            let mut continuations: Vec<String> = Vec::new();
+            for reply in game.scan_available_moves() {
+                let reply_san: String = game.san(reply);
+                game.make_move(reply);
+                if self.defence_fails(game, moves - 1) {
+                    continuations.push(reply_san);
+                }
+                game.unmake_move();
+            }
+            game.unmake_move();
+            continuations.sort();
+            output.push(Variation {
+                defence: defence_san,
+                continuations,
+            });
+        }
+        output.sort_by(|a, b| a.defence.cmp(&b.defence));
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::solver::*;
+    use std::path::Path;
+
+    fn solve(fen: &str, mate_in: u32) -> Solution {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut game: Game = Game::from_fen(
+            0,
+            fen,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        MateSolver::new().solve(&mut game, mate_in)
+    }
+
+    #[test]
+    fn finds_keys_and_cooks() {
+        let sound: Solution = solve("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1);
+        assert!(sound.sound());
+        assert_eq!(sound.keys[0].san, "Ra8#");
+        assert!(sound.report().contains("sound, one key"));
+        let cooked: Solution = solve("6k1/5ppp/8/8/8/8/8/RR4K1 w - - 0 1", 1);
+        assert_eq!(cooked.keys.len(), 2);
+        assert!(cooked.report().contains("cooked, 2 keys"));
+        // Asked for a mate in 2, the mate in 1 is a short solution.
+        let short: Solution = solve("k7/8/1K6/8/8/8/8/7R w - - 0 1", 2);
+        assert!(!short.sound());
+        assert_eq!(
+            (short.keys[0].san.as_str(), short.keys[0].mate_in),
+            ("Rh8#", 1)
+        );
+        assert!(solve("k7/8/8/8/8/8/8/K7 w - - 0 1", 2).keys.is_empty());
+    }
+
+    #[test]
+    fn lists_variations() {
+        let solution: Solution = solve("k7/8/2K5/8/8/8/8/7R w - - 0 1", 2);
+        assert!(solution.keys.iter().all(|key| key.mate_in == 2));
+        let king: &Key = solution
+            .keys
+            .iter()
+            .find(|key| key.coordinate == "c6b6")
+            .unwrap();
+        // Black's king has nowhere but b8 to go, and the rook mates from h8 or on the a-file.
+        assert_eq!(king.variations.len(), 1);
+        assert_eq!(king.variations[0].defence, "Kb8");
+        assert_eq!(king.variations[0].continuations, vec!["Rh8#"]);
+        assert!(solution.report().contains("1... Kb8 2. Rh8#"));
+    }
+}
@@ -0,0 +1,355 @@
+use crate::configuration::*;
+use crate::evaluation::*;
+use crate::export::*;
+use crate::game::*;
+use crate::notation::*;
+use crate::recorder::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// How one PieceType fared, summed over every game.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PieceTypeStats {
+    pub moves: usize,
+    // Captures it made, and times it was taken.
+    pub captures: usize,
+    pub captured: usize,
+    // Times a piece promoted to it.
+    pub promotions: usize,
+}
+
+// How often one MOVEID was played and how much the evaluation changed because of it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MoveTypeStats {
+    pub played: usize,
+    // Centipawns for the mover, summed.
+    pub swing: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    pub files: usize,
+    pub games: usize,
+    pub white_wins: usize,
+    pub black_wins: usize,
+    pub draws: usize,
+    pub unfinished: usize,
+    pub plies: usize,
+    pub piece_types: HashMap<String, PieceTypeStats>,
+    // Keyed by the name of the piece type as well, since different pieces can share a MOVEID.
+    pub move_types: HashMap<(String, String), MoveTypeStats>,
+    pub evaluator: String,
+    // Files and games that couldn't be read, with why.
+    pub problems: Vec<String>,
+}
+
+impl Summary {
+    // Replays the game from its start, so the evaluation swing of every move can be worked out.
+    pub fn add<'a>(
+        &mut self,
+        log: &GameLog,
+        piece_symbol_map: &HashMap<char, &'a PieceType>,
+        standard_fen: &str,
+        evaluator: &dyn Evaluator,
+    ) -> Result<(), String> {
+        let mut game: Game<'a> = Game::from_fen(
+            log.game_id,
+            &log.start_fen,
+            standard_fen,
+            piece_symbol_map.clone(),
+        )?;
+        let mut swings: Vec<i64> = Vec::new();
+        for ply in &log.plies {
+            let available_move: AvailableMove = game
+                .find_move(&ply.coordinate)
+                .ok_or_else(|| format!("ply {} ({}) can't be played", ply.ply, ply.coordinate))?;
+            let before: i32 = evaluator.evaluate(&game);
+            game.make_move(available_move);
+            // The evaluator scores for the side to move, which is now the opponent.
+            swings.push(-(evaluator.evaluate(&game) as i64) - before as i64);
+        }
+        // Only count the game once all of it has been played through.
+        self.evaluator = String::from(evaluator.name());
+        self.games += 1;
+        match log.result.as_deref() {
+            Some("1-0") => self.white_wins += 1,
+            Some("0-1") => self.black_wins += 1,
+            Some("1/2-1/2") => self.draws += 1,
+            _ => self.unfinished += 1,
+        }
+        self.plies += log.plies.len();
+        for (ply, swing) in log.plies.iter().zip(swings) {
+            let stats: &mut PieceTypeStats =
+                self.piece_types.entry(ply.piece_type.clone()).or_default();
+            stats.moves += 1;
+            if let Some(captured) = &ply.captured {
+                stats.captures += 1;
+                self.piece_types
+                    .entry(captured.clone())
+                    .or_default()
+                    .captured += 1;
+            }
+            if let Some(promotion) = &ply.promotion {
+                self.piece_types
+                    .entry(promotion.clone())
+                    .or_default()
+                    .promotions += 1;
+            }
+            let stats: &mut MoveTypeStats = self
+                .move_types
+                .entry((ply.piece_type.clone(), ply.moveid.clone()))
+                .or_default();
+            stats.played += 1;
+            stats.swing += swing;
+        }
+        Ok(())
+    }
+
+    pub fn report(&self) -> String {
+        let mut output: String = format!("Games: {} from {} files\n", self.games, self.files);
+        if self.games == 0 {
+            return output;
+        }
+        let games: f64 = self.games as f64;
+        let percent = |count: usize| count as f64 * 100.0 / games;
+        output.push_str(&format!(
+            "Results: White wins {:.1}%, draws {:.1}%, Black wins {:.1}%, unfinished {:.1}%\n",
+            percent(self.white_wins),
+            percent(self.draws),
+            percent(self.black_wins),
+            percent(self.unfinished)
+        ));
+        output.push_str(&format!(
+            "Average length: {:.1} plies\n\n",
+            self.plies as f64 / games
+        ));
+        output.push_str("Per game      moves  captures  captured  promotions\n");
+        let mut names: Vec<&String> = self.piece_types.keys().collect();
+        names.sort();
+        for name in names {
+            let stats: &PieceTypeStats = &self.piece_types[name];
+            output.push_str(&format!(
+                "{:<12}{:>7.2}{:>10.2}{:>10.2}{:>12.2}\n",
+                name,
+                stats.moves as f64 / games,
+                stats.captures as f64 / games,
+                stats.captured as f64 / games,
+                stats.promotions as f64 / games
+            ));
+        }
+        output.push_str(&format!(
+            "\nMove types, most played first. Swing is the average change in the {} evaluation for the mover.\n",
+            self.evaluator
+        ));
+        output.push_str("Piece       played   share    swing  MOVEID\n");
+        let mut move_types: Vec<(&(String, String), &MoveTypeStats)> =
+            self.move_types.iter().collect();
+        move_types.sort_by(|a, b| b.1.played.cmp(&a.1.played).then(a.0.cmp(b.0)));
+        for ((piece_type, moveid), stats) in move_types {
+            output.push_str(&format!(
+                "{:<12}{:>6}{:>7.1}%{:>9.1}  {}\n",
+                piece_type,
+                stats.played,
+                stats.played as f64 * 100.0 / self.plies as f64,
+                stats.swing as f64 / stats.played as f64,
+                moveid
+            ));
+        }
+        for problem in &self.problems {
+            output.push_str(&format!("Skipped {}\n", problem));
+        }
+        output
+    }
+}
+
+// Every game in a directory, or in a single file: PGNs saved with the REPL's save command,
+// and the per-ply CSVs written by --export along with their -games.csv for the results.
+pub fn summarize(
+    path: &Path,
+    piece_symbol_map: &HashMap<char, &PieceType>,
+    position_name_map: &HashMap<String, String>,
+    evaluator: &dyn Evaluator,
+) -> Result<Summary, String> {
+    let standard_fen: &str = position_name_map.get("standard").map_or("", |s| s.as_str());
+    let mut summary: Summary = Summary::default();
+    for (name, logs) in read_games(path, piece_symbol_map, position_name_map)? {
+        summary.files += 1;
+        match logs {
+            Ok(logs) => {
+                for (number, log) in logs.iter().enumerate() {
+                    if let Err(why) = summary.add(log, piece_symbol_map, standard_fen, evaluator) {
+                        summary
+                            .problems
+                            .push(format!("game {} of {}: {}", number + 1, name, why));
+                    }
+                }
+            }
+            Err(why) => summary.problems.push(format!("{}: {}", name, why)),
+        }
+    }
+    Ok(summary)
+}
+
+// A file's name and the games in it, or why they couldn't be read.
+pub type GameFile = (String, Result<Vec<GameLog>, String>);
+
+// The games in each .pgn and exported .csv file under path, by file name. A file that can't be read doesn't stop the rest.
+pub fn read_games(
+    path: &Path,
+    piece_symbol_map: &HashMap<char, &PieceType>,
+    position_name_map: &HashMap<String, String>,
+) -> Result<Vec<GameFile>, String> {
+    let mut files: Vec<PathBuf> = if path.is_dir() {
+        std::fs::read_dir(path)
+            .map_err(|e| e.to_string())?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+    files.sort();
+    let mut output: Vec<GameFile> = Vec::new();
+    for file in files {
+        let name: String = file.display().to_string();
+        let logs: Result<Vec<GameLog>, String> = if name.ends_with(".pgn") {
+            std::fs::read_to_string(&file)
+                .map_err(|e| e.to_string())
+                .and_then(|text| {
+                    split_pgn(&text)
+                        .iter()
+                        .map(|pgn| log_from_pgn(pgn, piece_symbol_map, position_name_map))
+                        .collect()
+                })
+        } else if name.ends_with(".csv") && !name.ends_with("-games.csv") {
+            let games: Option<String> =
+                std::fs::read_to_string(format!("{}-games.csv", &name[..name.len() - 4])).ok();
+            std::fs::read_to_string(&file)
+                .map_err(|e| e.to_string())
+                .and_then(|text| logs_from_csv(&text, games.as_deref()))
+        } else {
+            continue;
+        };
+        output.push((name, logs));
+    }
+    if output.is_empty() {
+        return Err(format!("no .pgn or .csv files in {}", path.display()));
+    }
+    Ok(output)
+}
+
+// A PGN file can hold many games, each starting with its tags.
+fn split_pgn(text: &str) -> Vec<String> {
+    let mut games: Vec<String> = Vec::new();
+    let mut in_tags: bool = false;
+    for line in text.lines() {
+        let tag: bool = line.trim_start().starts_with('[');
+        if tag && !in_tags || games.is_empty() {
+            games.push(String::new());
+        }
+        in_tags = tag;
+        let game: &mut String = games.last_mut().unwrap();
+        game.push_str(line);
+        game.push('\n');
+    }
+    games.retain(|game| !game.trim().is_empty());
+    games
+}
+
+// Plays through a PGN to get the same records a match would have made, without the times or engine states.
+fn log_from_pgn<'a>(
+    text: &str,
+    piece_symbol_map: &HashMap<char, &'a PieceType>,
+    position_name_map: &HashMap<String, String>,
+) -> Result<GameLog, String> {
+    let (tags, moves, result) = parse_pgn(text);
+    let tag = |name: &str| tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+    let standard_fen: &str = position_name_map.get("standard").map_or("", |s| s.as_str());
+    let start: String = tag("FEN")
+        .or_else(|| tag("Variant").and_then(|v| position_name_map.get(&v).cloned()))
+        .unwrap_or_else(|| String::from(standard_fen));
+    let mut game: Game<'a> = Game::from_fen(0, &start, standard_fen, piece_symbol_map.clone())?;
+    let mut recorder: Recorder = Recorder::new(
+        &tag("White").unwrap_or_default(),
+        &tag("Black").unwrap_or_default(),
+        &game,
+    );
+    for (number, text) in moves.iter().enumerate() {
+        let available_move: AvailableMove = game
+            .parse_move(text)
+            .map_err(|why| format!("move {} ({}): {}", number + 1, text, why))?;
+        recorder.make_move(&mut game, available_move, Duration::ZERO, None);
+    }
+    if let Some(result) = result.or_else(|| tag("Result")) {
+        recorder.finish(&result, &tag("Termination").unwrap_or_default());
+    }
+    Ok(recorder.log)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::summary::*;
+
+    const PGN: &str =
+        "[Event \"One\"]\n[White \"a\"]\n[Black \"b\"]\n\n1. e4 d5 2. exd5 Qxd5 1-0\n\n\
+        [Event \"Two\"]\n\n1. f4 e5 2. g4 Qh4# 0-1\n";
+
+    #[test]
+    fn summarizes_games() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let position_name_map: HashMap<String, String> =
+            PositionListIntermediateRepresentation::from_file(Path::new(
+                "testfiles/standardStartingPositions.json",
+            ))
+            .map_positions_to_names();
+        let directory: PathBuf = std::env::temp_dir().join("piecetestbed-summary");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("games.pgn"), PGN).unwrap();
+        std::fs::write(directory.join("notes.txt"), "not a game").unwrap();
+        let summary: Summary = summarize(
+            &directory,
+            &piece_list.map_piecetypes_to_symbols(),
+            &position_name_map,
+            &Material,
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!((summary.files, summary.games, summary.plies), (1, 2, 8));
+        assert_eq!(
+            (summary.white_wins, summary.black_wins, summary.draws),
+            (1, 1, 0)
+        );
+        assert!(summary.problems.is_empty());
+        let pawn: &PieceTypeStats = &summary.piece_types["pawn"];
+        assert_eq!((pawn.moves, pawn.captures, pawn.captured), (6, 1, 2));
+        assert_eq!(summary.piece_types["queen"].captures, 1);
+        // exd5 and Qxd5 each win a pawn for the mover.
+        let captures: Vec<&MoveTypeStats> = summary
+            .move_types
+            .values()
+            .filter(|stats| stats.swing != 0)
+            .collect();
+        assert_eq!(captures.len(), 2);
+        assert!(captures.iter().all(|stats| stats.swing == 100));
+        let report: String = summary.report();
+        assert!(report.contains("White wins 50.0%, draws 0.0%, Black wins 50.0%"));
+        assert!(report.contains("Average length: 4.0 plies"));
+    }
+
+    #[test]
+    fn reads_exported_logs() {
+        let csv: &str = "game,ply,mover,fen,coordinate,piece_type,captured\n\
+            3,1,w,\"a, b\",e2e4,pawn,\n3,2,b,x,d7d5,pawn,\n4,1,w,y,e2e4,pawn,knight\n";
+        let games: &str = "game,result,reason\n3,1-0,Black resigns\n";
+        let logs: Vec<GameLog> = logs_from_csv(csv, Some(games)).unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].start_fen, "a, b");
+        assert_eq!(logs[0].plies.len(), 2);
+        assert_eq!(logs[0].result.as_deref(), Some("1-0"));
+        assert_eq!(logs[1].result, None);
+        assert_eq!(logs[1].plies[0].captured.as_deref(), Some("knight"));
+        assert!(logs_from_csv("game,ply\n", None).is_err());
+    }
+}
@@ -0,0 +1,832 @@
+use crate::book::ruleset_tag;
+use crate::configuration::*;
+use crate::game::*;
+use crate::BOARDSIZE;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Every placement of up to this many pieces, for both sides to move, is 2 * 64^4 positions: 32 MB a table.
+pub const MAX_TABLEBASE_PIECES: usize = 4;
+const FILE_HEADER: &str = "piecetestbed tablebase 1";
+// One byte per position: 0 is a draw, 255 can't happen, anything else is the number of plies to mate plus one.
+// Odd plies mean the side to move mates, even plies that it gets mated.
+const DRAWN: u8 = 0;
+const INVALID: u8 = 255;
+const UNKNOWN: u8 = 254;
+const LONGEST_MATE: usize = 252;
+
+// What a table says about the side to move, with the plies until mate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TablebaseResult {
+    Win(u32),
+    Loss(u32),
+    Draw,
+}
+
+// The pieces in an ending, by the symbols they have on the board. Royal pieces come first on each side,
+// then the rest in symbol order, and that's the order their squares go into a table's index.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Endgame {
+    pub white: Vec<char>,
+    pub black: Vec<char>,
+}
+
+impl Endgame {
+    // Like KQvK or KAvK. Black's pieces can be written with either symbol.
+    pub fn parse(
+        name: &str,
+        piece_symbol_map: &HashMap<char, &PieceType>,
+    ) -> Result<Endgame, String> {
+        let (white, black) = name.split_once('v').ok_or_else(|| {
+            format!(
+                "{} should look like KQvK, White's pieces then Black's",
+                name
+            )
+        })?;
+        let mut endgame: Endgame = Endgame {
+            white: Vec::new(),
+            black: Vec::new(),
+        };
+        for symbol in white.chars() {
+            match piece_symbol_map.get(&symbol) {
+                Some(piece_type) if piece_type.white_id == symbol => endgame.white.push(symbol),
+                _ => {
+                    return Err(format!(
+                        "no white piece in pieces.json uses the symbol {}",
+                        symbol
+                    ))
+                }
+            }
+        }
+        for symbol in black.chars() {
+            match piece_symbol_map.get(&symbol) {
+                Some(piece_type) => endgame.black.push(piece_type.black_id),
+                None => {
+                    return Err(format!(
+                        "no piece in pieces.json uses the symbol {}",
+                        symbol
+                    ))
+                }
+            }
+        }
+        if endgame.pieces() > MAX_TABLEBASE_PIECES {
+            return Err(format!(
+                "{} has {} pieces, tables only go up to {}",
+                name,
+                endgame.pieces(),
+                MAX_TABLEBASE_PIECES
+            ));
+        }
+        endgame.sort(piece_symbol_map);
+        Ok(endgame)
+    }
+
+    // The pieces still on the board in a game, and their squares in table order.
+    fn from_game(game: &Game) -> (Endgame, Vec<u8>) {
+        let mut white: Vec<(bool, char, u8)> = Vec::new();
+        let mut black: Vec<(bool, char, u8)> = Vec::new();
+        for piece in game.list_of_pieces_ingame.iter().filter(|p| !p.captured) {
+            let side: &mut Vec<(bool, char, u8)> = if piece.player == 'w' {
+                &mut white
+            } else {
+                &mut black
+            };
+            side.push((!piece.piece_type.royal, piece.symbol, piece.position));
+        }
+        white.sort();
+        black.sort();
+        let squares: Vec<u8> = white.iter().chain(black.iter()).map(|p| p.2).collect();
+        let endgame: Endgame = Endgame {
+            white: white.iter().map(|p| p.1).collect(),
+            black: black.iter().map(|p| p.1).collect(),
+        };
+        (endgame, squares)
+    }
+
+    fn sort(&mut self, piece_symbol_map: &HashMap<char, &PieceType>) {
+        let royal_first = |symbol: &char| (!piece_symbol_map[symbol].royal, *symbol);
+        self.white.sort_by_key(royal_first);
+        self.black.sort_by_key(royal_first);
+    }
+
+    pub fn name(&self) -> String {
+        format!(
+            "{}v{}",
+            self.white.iter().collect::<String>(),
+            self.black.iter().collect::<String>()
+        )
+    }
+
+    pub fn pieces(&self) -> usize {
+        self.white.len() + self.black.len()
+    }
+
+    fn symbols(&self) -> Vec<char> {
+        self.white
+            .iter()
+            .chain(self.black.iter())
+            .copied()
+            .collect()
+    }
+
+    // The endings one capture or promotion away, whose tables this one needs first.
+    // Royal pieces are never captured, so endings without one aren't needed.
+    fn exits(&self, piece_symbol_map: &HashMap<char, &PieceType>) -> Vec<Endgame> {
+        let mut output: Vec<Endgame> = Vec::new();
+        let sides: usize = self.white.len();
+        for (slot, symbol) in self.symbols().into_iter().enumerate() {
+            let piece_type: &PieceType = piece_symbol_map[&symbol];
+            let mut options: Vec<Option<char>> = Vec::new();
+            if !piece_type.royal {
+                options.push(None);
+            }
+            if piece_type.promotable {
+                for promoted in piece_type.promotes_to.chars() {
+                    if let Some(promoted_type) = piece_symbol_map.get(&promoted) {
+                        options.push(Some(if slot < sides {
+                            promoted_type.white_id
+                        } else {
+                            promoted_type.black_id
+                        }));
+                    }
+                }
+            }
+            for replacement in options {
+                let mut exit: Endgame = self.clone();
+                let side: &mut Vec<char> = if slot < sides {
+                    &mut exit.white
+                } else {
+                    &mut exit.black
+                };
+                let at: usize = if slot < sides { slot } else { slot - sides };
+                match replacement {
+                    Some(promoted) => side[at] = promoted,
+                    None => {
+                        side.remove(at);
+                    }
+                }
+                exit.sort(piece_symbol_map);
+                if exit != *self && !output.contains(&exit) {
+                    output.push(exit);
+                }
+            }
+        }
+        output
+    }
+
+    // Squares in base 64, one digit per piece in table order, with the side to move on top.
+    fn index(&self, squares: &[u8], active_color: char) -> usize {
+        let mut output: usize = usize::from(active_color == 'b');
+        for square in squares {
+            output = (output << 6) | *square as usize;
+        }
+        output
+    }
+
+    fn decode(&self, index: usize) -> (Vec<u8>, char) {
+        let pieces: usize = self.pieces();
+        let squares: Vec<u8> = (0..pieces)
+            .map(|slot| ((index >> (6 * (pieces - 1 - slot))) & 63) as u8)
+            .collect();
+        let active_color: char = if (index >> (6 * pieces)) & 1 == 0 {
+            'w'
+        } else {
+            'b'
+        };
+        (squares, active_color)
+    }
+
+    fn size(&self) -> usize {
+        2 << (6 * self.pieces())
+    }
+
+    fn fen(&self, squares: &[u8], active_color: char) -> String {
+        let mut board: Vec<Option<char>> = vec![None; 64];
+        for (symbol, square) in self.symbols().into_iter().zip(squares) {
+            board[*square as usize] = Some(symbol);
+        }
+        let mut ranks: Vec<String> = Vec::new();
+        for rank in board.chunks(8) {
+            let mut text: String = String::new();
+            let mut empty: u32 = 0;
+            for square in rank {
+                match square {
+                    Some(symbol) => {
+                        if empty > 0 {
+                            text.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        text.push(*symbol);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                text.push_str(&empty.to_string());
+            }
+            ranks.push(text);
+        }
+        format!("{} {} - - 0 1", ranks.join("/"), active_color)
+    }
+}
+
+// The result of every position in one ending.
+#[derive(Debug, Clone)]
+pub struct Tablebase {
+    pub endgame: Endgame,
+    values: Vec<u8>,
+}
+
+impl Tablebase {
+    fn result(&self, index: usize) -> Option<TablebaseResult> {
+        match self.values[index] {
+            INVALID | UNKNOWN => None,
+            DRAWN => Some(TablebaseResult::Draw),
+            value if value % 2 == 0 => Some(TablebaseResult::Win(value as u32 - 1)),
+            value => Some(TablebaseResult::Loss(value as u32 - 1)),
+        }
+    }
+
+    // How each side to move fares, and the longest mate with a position it happens from.
+    pub fn report(&self) -> String {
+        let mut output: String = format!("{}\n", self.endgame.name());
+        let half: usize = self.values.len() / 2;
+        for (active_color, name) in [('w', "White"), ('b', "Black")] {
+            let offset: usize = if active_color == 'w' { 0 } else { half };
+            let mut counts: [usize; 3] = [0; 3];
+            let mut longest: Option<(u32, usize)> = None;
+            for index in offset..offset + half {
+                match self.result(index) {
+                    Some(TablebaseResult::Win(plies)) => {
+                        counts[0] += 1;
+                        if longest.is_none_or(|(most, _)| plies > most) {
+                            longest = Some((plies, index));
+                        }
+                    }
+                    Some(TablebaseResult::Draw) => counts[1] += 1,
+                    Some(TablebaseResult::Loss(_)) => counts[2] += 1,
+                    None => {}
+                }
+            }
+            let legal: usize = counts.iter().sum::<usize>().max(1);
+            output.push_str(&format!(
+                "  {} to move: {:.1}% won, {:.1}% drawn, {:.1}% lost of {} positions",
+                name,
+                counts[0] as f64 * 100.0 / legal as f64,
+                counts[1] as f64 * 100.0 / legal as f64,
+                counts[2] as f64 * 100.0 / legal as f64,
+                legal
+            ));
+            if let Some((plies, index)) = longest {
+                let (squares, active_color) = self.endgame.decode(index);
+                output.push_str(&format!(
+                    ", longest mate in {} from {}",
+                    plies.div_ceil(2),
+                    self.endgame.fen(&squares, active_color)
+                ));
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    // The header names the ending and the rules it was made under, then runs of equal bytes as count and value.
+    fn save(&self, path: &Path, ruleset: &str) -> Result<(), String> {
+        let mut bytes: Vec<u8> = format!(
+            "{} {} {} {}\n",
+            FILE_HEADER,
+            ruleset,
+            self.endgame.white.iter().collect::<String>(),
+            self.endgame.black.iter().collect::<String>()
+        )
+        .into_bytes();
+        let mut values = self.values.iter().peekable();
+        while let Some(value) = values.next() {
+            let mut run: u8 = 1;
+            while run < u8::MAX && values.peek() == Some(&value) {
+                values.next();
+                run += 1;
+            }
+            bytes.push(run);
+            bytes.push(*value);
+        }
+        std::fs::write(path, bytes).map_err(|e| e.to_string())
+    }
+
+    // The table and the rules it was made under.
+    fn load(path: &Path) -> Result<(Tablebase, String), String> {
+        let bytes: Vec<u8> = std::fs::read(path).map_err(|e| e.to_string())?;
+        let end: usize = bytes
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or("not a tablebase")?;
+        let header: String = String::from_utf8_lossy(&bytes[..end]).into_owned();
+        let fields: Vec<&str> = header
+            .strip_prefix(FILE_HEADER)
+            .ok_or("not a tablebase")?
+            .split_whitespace()
+            .collect();
+        let [ruleset, white, black] = fields[..] else {
+            return Err(String::from("not a tablebase"));
+        };
+        let endgame: Endgame = Endgame {
+            white: white.chars().collect(),
+            black: black.chars().collect(),
+        };
+        let mut values: Vec<u8> = Vec::with_capacity(endgame.size());
+        for run in bytes[end + 1..].chunks(2) {
+            if let [count, value] = run {
+                values.extend(std::iter::repeat_n(*value, *count as usize));
+            }
+        }
+        if endgame.pieces() > MAX_TABLEBASE_PIECES || values.len() != endgame.size() {
+            return Err(String::from("the table is cut short"));
+        }
+        Ok((Tablebase { endgame, values }, String::from(ruleset)))
+    }
+}
+
+// Every table in a directory made under the current pieces.json. Tables for other rules are left alone.
+pub struct Tablebases {
+    pub directory: PathBuf,
+    ruleset: String,
+    tables: HashMap<Endgame, Tablebase>,
+    // Tables that are there but for other rules or unreadable, with why.
+    pub skipped: Vec<String>,
+}
+
+impl Tablebases {
+    pub fn open(
+        directory: &Path,
+        piece_symbol_map: &HashMap<char, &PieceType>,
+    ) -> Result<Tablebases, String> {
+        let mut tablebases: Tablebases = Tablebases {
+            directory: directory.to_path_buf(),
+            ruleset: ruleset_tag(piece_symbol_map),
+            tables: HashMap::new(),
+            skipped: Vec::new(),
+        };
+        if !directory.exists() {
+            return Ok(tablebases);
+        }
+        let mut files: Vec<PathBuf> = std::fs::read_dir(directory)
+            .map_err(|e| format!("{}: {}", directory.display(), e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|e| e == "tb"))
+            .collect();
+        files.sort();
+        for file in files {
+            match Tablebase::load(&file) {
+                Ok((table, ruleset)) if ruleset == tablebases.ruleset => {
+                    tablebases.tables.insert(table.endgame.clone(), table);
+                }
+                Ok(_) => tablebases
+                    .skipped
+                    .push(format!("{}: made for other rules", file.display())),
+                Err(why) => tablebases
+                    .skipped
+                    .push(format!("{}: {}", file.display(), why)),
+            }
+        }
+        Ok(tablebases)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn get(&self, endgame: &Endgame) -> Option<&Tablebase> {
+        self.tables.get(endgame)
+    }
+
+    // Make the table for an ending, and before it any it can turn into, unless they're there already.
+    // Each one is saved as it's finished, and reported once it's been saved.
+    pub fn generate<'a>(
+        &mut self,
+        endgame: &Endgame,
+        piece_symbol_map: &HashMap<char, &'a PieceType>,
+        standard_fen: &str,
+        on_table: &mut dyn FnMut(&Tablebase),
+    ) -> Result<(), String> {
+        self.generate_after(
+            endgame,
+            piece_symbol_map,
+            standard_fen,
+            on_table,
+            &mut Vec::new(),
+        )
+    }
+
+    fn generate_after<'a>(
+        &mut self,
+        endgame: &Endgame,
+        piece_symbol_map: &HashMap<char, &'a PieceType>,
+        standard_fen: &str,
+        on_table: &mut dyn FnMut(&Tablebase),
+        waiting: &mut Vec<Endgame>,
+    ) -> Result<(), String> {
+        if self.tables.contains_key(endgame) {
+            return Ok(());
+        }
+        if waiting.contains(endgame) {
+            return Err(format!("{} can promote back into itself", endgame.name()));
+        }
+        waiting.push(endgame.clone());
+        for exit in endgame.exits(piece_symbol_map) {
+            self.generate_after(&exit, piece_symbol_map, standard_fen, on_table, waiting)?;
+        }
+        waiting.pop();
+        let table: Tablebase = self.build(endgame, piece_symbol_map, standard_fen)?;
+        std::fs::create_dir_all(&self.directory).map_err(|e| e.to_string())?;
+        let path: PathBuf = self.directory.join(format!("{}.tb", endgame.name()));
+        table
+            .save(&path, &self.ruleset)
+            .map_err(|why| format!("{}: {}", path.display(), why))?;
+        on_table(&table);
+        self.tables.insert(endgame.clone(), table);
+        Ok(())
+    }
+
+    // Retrograde analysis. One pass of the move generator over every placement finds the legal positions,
+    // the quiet moves between them, and the results of captures and promotions from the tables already made.
+    // Mates are then worked back from one ply at a time, so every result comes out with its distance to mate.
+    // Positions are taken as set up from a FEN with no castling or en passant, and pieces count as unmoved on
+    // squares where the standard position has the same piece.
+    fn build<'a>(
+        &self,
+        endgame: &Endgame,
+        piece_symbol_map: &HashMap<char, &'a PieceType>,
+        standard_fen: &str,
+    ) -> Result<Tablebase, String> {
+        let pieces: usize = endgame.pieces();
+        let size: usize = endgame.size();
+        let standard: Vec<Option<char>> =
+            parse_fen_board(standard_fen.split_whitespace().next().unwrap_or(""))?;
+        // Pieces set out on the first squares in table order, so piece ids line up with slots.
+        let first_squares: Vec<u8> = (0..pieces as u8).collect();
+        let mut game: Game<'a> = Game::from_fen(
+            0,
+            &endgame.fen(&first_squares, 'w'),
+            standard_fen,
+            piece_symbol_map.clone(),
+        )?;
+        // Promotable pieces never stand on the far rank, they'd have promoted getting there.
+        let far_rows: Vec<Option<u8>> = endgame
+            .symbols()
+            .into_iter()
+            .enumerate()
+            .map(|(slot, symbol)| {
+                let piece_type: &PieceType = piece_symbol_map[&symbol];
+                let promotes: bool = piece_type.promotable
+                    && piece_type
+                        .promotes_to
+                        .chars()
+                        .any(|p| piece_symbol_map.contains_key(&p));
+                match (promotes, slot < endgame.white.len()) {
+                    (false, _) => None,
+                    (true, true) => Some(0),
+                    (true, false) => Some(BOARDSIZE - 1),
+                }
+            })
+            .collect();
+        let mut values: Vec<u8> = vec![INVALID; size];
+        let mut in_check: Vec<bool> = vec![false; size];
+        // Whether any capture or promotion is legal, whether one of them doesn't lose, and when the slowest loses.
+        let mut exits: Vec<bool> = vec![false; size];
+        let mut escapes: Vec<bool> = vec![false; size];
+        let mut exit_losses: Vec<u8> = vec![0; size];
+        let mut buckets: Vec<Vec<u32>> = vec![Vec::new(); LONGEST_MATE + 1];
+        // Quiet moves as the slot that moved and where to, 2 and 6 bits.
+        let mut forward: Vec<u8> = Vec::new();
+        let mut forward_starts: Vec<u32> = Vec::with_capacity(size + 1);
+        for index in 0..size {
+            forward_starts.push(forward.len() as u32);
+            let (squares, active_color) = endgame.decode(index);
+            if (1..pieces).any(|slot| squares[..slot].contains(&squares[slot]))
+                || (0..pieces).any(|slot| far_rows[slot] == Some(squares[slot] / BOARDSIZE))
+            {
+                continue;
+            }
+            place(&mut game, &squares, active_color, &standard);
+            if game.royal_in_check(opponent(active_color)) {
+                continue;
+            }
+            values[index] = UNKNOWN;
+            in_check[index] = game.royal_in_check(active_color);
+            let mut hashed: bool = false;
+            for available_move in game.scan_pseudo_legal_moves() {
+                if available_move.captured_piece_id.is_none()
+                    && available_move.promotion.is_none()
+                    && available_move.castle_target.is_none()
+                {
+                    forward.push(((available_move.piece_id - 1) << 6) | available_move.to);
+                    continue;
+                }
+                // make_move checks the hash in debug builds.
+                if !hashed {
+                    game.hash = game.compute_hash();
+                    hashed = true;
+                }
+                game.make_move(available_move);
+                let result: Option<Result<TablebaseResult, String>> =
+                    if game.royal_in_check(active_color) {
+                        None
+                    } else {
+                        Some(self.exit_result(endgame, &game))
+                    };
+                game.unmake_move();
+                let Some(result) = result else {
+                    continue;
+                };
+                exits[index] = true;
+                // The result is for the opponent, who moves next.
+                match result? {
+                    TablebaseResult::Draw => escapes[index] = true,
+                    TablebaseResult::Loss(plies) if plies as usize >= LONGEST_MATE => {
+                        return Err(too_long(endgame))
+                    }
+                    TablebaseResult::Loss(plies) => {
+                        escapes[index] = true;
+                        buckets[plies as usize + 1].push(index as u32);
+                    }
+                    TablebaseResult::Win(plies) => {
+                        exit_losses[index] = exit_losses[index].max(plies as u8 + 1)
+                    }
+                }
+            }
+        }
+        forward_starts.push(forward.len() as u32);
+        // Turn the quiet moves round, counting the legal ones from each position on the way.
+        let mut remaining: Vec<u16> = vec![0; size];
+        let mut backward_starts: Vec<u32> = vec![0; size + 1];
+        for index in 0..size {
+            for &step in
+                &forward[forward_starts[index] as usize..forward_starts[index + 1] as usize]
+            {
+                let next: usize = moved(index, step, pieces);
+                if values[next] != INVALID {
+                    remaining[index] += 1;
+                    backward_starts[next + 1] += 1;
+                }
+            }
+        }
+        for index in 0..size {
+            backward_starts[index + 1] += backward_starts[index];
+        }
+        // Where a move came from, the same way round as forward.
+        let mut backward: Vec<u8> = vec![0; backward_starts[size] as usize];
+        let mut filled: Vec<u32> = backward_starts[..size].to_vec();
+        for index in 0..size {
+            for &step in
+                &forward[forward_starts[index] as usize..forward_starts[index + 1] as usize]
+            {
+                let next: usize = moved(index, step, pieces);
+                if values[next] != INVALID {
+                    let slot: usize = (step >> 6) as usize;
+                    let from: u8 = ((index >> (6 * (pieces - 1 - slot))) & 63) as u8;
+                    backward[filled[next] as usize] = ((slot as u8) << 6) | from;
+                    filled[next] += 1;
+                }
+            }
+        }
+        drop(forward);
+        for index in 0..size {
+            if values[index] != UNKNOWN || remaining[index] > 0 || escapes[index] {
+                continue;
+            }
+            if exits[index] {
+                buckets[exit_losses[index] as usize].push(index as u32);
+            } else if in_check[index] {
+                buckets[0].push(index as u32);
+            }
+            // Otherwise stalemate, which stays a draw.
+        }
+        for plies in 0..=LONGEST_MATE {
+            for index in std::mem::take(&mut buckets[plies]) {
+                let index: usize = index as usize;
+                if values[index] != UNKNOWN {
+                    continue;
+                }
+                values[index] = plies as u8 + 1;
+                for &step in
+                    &backward[backward_starts[index] as usize..backward_starts[index + 1] as usize]
+                {
+                    let previous: usize = moved(index, step, pieces);
+                    if values[previous] != UNKNOWN {
+                        continue;
+                    }
+                    // A position that loses makes every move into it a win. One that wins only loses
+                    // for the side that moved into it once all its other moves win too.
+                    let next: usize = if plies % 2 == 0 {
+                        plies + 1
+                    } else {
+                        remaining[previous] -= 1;
+                        if remaining[previous] > 0 || escapes[previous] {
+                            continue;
+                        }
+                        (plies + 1).max(exit_losses[previous] as usize)
+                    };
+                    if next > LONGEST_MATE {
+                        return Err(too_long(endgame));
+                    }
+                    buckets[next].push(previous as u32);
+                }
+            }
+        }
+        for value in values.iter_mut().filter(|v| **v == UNKNOWN) {
+            *value = DRAWN;
+        }
+        Ok(Tablebase {
+            endgame: endgame.clone(),
+            values,
+        })
+    }
+
+    // A capture or promotion leads into another ending's table.
+    fn exit_result(&self, endgame: &Endgame, game: &Game) -> Result<TablebaseResult, String> {
+        let (exit, squares) = Endgame::from_game(game);
+        if exit == *endgame {
+            return Err(format!(
+                "{} has a move that changes more than one piece without a capture or promotion",
+                endgame.name()
+            ));
+        }
+        let table: &Tablebase = self
+            .tables
+            .get(&exit)
+            .ok_or_else(|| format!("{} needs the {} table first", endgame.name(), exit.name()))?;
+        table
+            .result(exit.index(&squares, game.active_color))
+            .ok_or_else(|| {
+                format!(
+                    "{} leads to a position {} says can't happen",
+                    endgame.name(),
+                    exit.name()
+                )
+            })
+    }
+
+    // None unless a table covers the game. Positions with castling or en passant still possible aren't in any table.
+    pub fn probe(&self, game: &Game) -> Option<TablebaseResult> {
+        let pieces: usize = game
+            .list_of_pieces_ingame
+            .iter()
+            .filter(|p| !p.captured)
+            .count();
+        if pieces > MAX_TABLEBASE_PIECES || self.tables.is_empty() {
+            return None;
+        }
+        if !game.castling_rights().is_empty() || game.enpassant_hash() != 0 {
+            return None;
+        }
+        let (endgame, squares) = Endgame::from_game(game);
+        let table: &Tablebase = self.tables.get(&endgame)?;
+        table.result(endgame.index(&squares, game.active_color))
+    }
+}
+
+fn too_long(endgame: &Endgame) -> String {
+    format!(
+        "{} has mates longer than {} plies, which don't fit in a table",
+        endgame.name(),
+        LONGEST_MATE
+    )
+}
+
+// The index after a quiet move of one slot, or before it for a backward step: the side to move flips either way.
+fn moved(index: usize, step: u8, pieces: usize) -> usize {
+    let shift: usize = 6 * (pieces - 1 - (step >> 6) as usize);
+    let square: usize = (step & 63) as usize;
+    ((index & !(63 << shift)) | (square << shift)) ^ (1 << (6 * pieces))
+}
+
+// Moves the pieces of a game set up by Tablebases::build to other squares, without setting up a new Game each time.
+fn place(game: &mut Game, squares: &[u8], active_color: char, standard: &[Option<char>]) {
+    game.position.iter_mut().for_each(|square| *square = 0);
+    for (piece, square) in game.list_of_pieces_ingame.iter_mut().zip(squares) {
+        piece.position = *square;
+        // Castling-like moves never come into it.
+        piece.has_castled = true;
+        let unmoved: bool = standard.get(*square as usize) == Some(&Some(piece.symbol));
+        if unmoved != piece.list_of_moves.is_empty() {
+            piece.list_of_moves = if unmoved {
+                Vec::new()
+            } else {
+                vec![String::from(SETUP_MOVE)]
+            };
+        }
+        game.position[*square as usize] = piece.id;
+    }
+    game.active_color = active_color;
+    game.white_check = game.royal_in_check('w');
+    game.black_check = game.royal_in_check('b');
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::search::*;
+    use crate::tablebase::*;
+
+    #[test]
+    fn solves_queen_against_king() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let piece_symbol_map: HashMap<char, &PieceType> = piece_list.map_piecetypes_to_symbols();
+        let standard: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let directory: PathBuf = std::env::temp_dir().join("piecetestbed-tablebase");
+        let _ = std::fs::remove_dir_all(&directory);
+        let mut tablebases: Tablebases = Tablebases::open(&directory, &piece_symbol_map).unwrap();
+        let endgame: Endgame = Endgame::parse("QKvK", &piece_symbol_map).unwrap();
+        assert_eq!(endgame.name(), "KQvk");
+        let mut made: Vec<String> = Vec::new();
+        tablebases
+            .generate(&endgame, &piece_symbol_map, standard, &mut |table| {
+                made.push(table.endgame.name())
+            })
+            .unwrap();
+        assert_eq!(made, vec!["Kvk", "KQvk"]);
+        let table: &Tablebase = tablebases.get(&endgame).unwrap();
+        // The longest mate with a queen is ten moves, and White to move always wins.
+        let report: String = table.report();
+        assert!(report.contains("White to move: 100.0% won"), "{}", report);
+        assert!(report.contains("longest mate in 10"), "{}", report);
+        let probe = |fen: &str| -> Option<TablebaseResult> {
+            let game: Game = Game::from_fen(0, fen, standard, piece_symbol_map.clone()).unwrap();
+            tablebases.probe(&game)
+        };
+        assert_eq!(
+            probe("6k1/8/6K1/8/8/8/8/Q7 w - - 0 1"),
+            Some(TablebaseResult::Win(1))
+        );
+        assert_eq!(
+            probe("6k1/6Q1/8/8/8/8/8/K7 b - - 0 1"),
+            Some(TablebaseResult::Draw)
+        );
+        assert_eq!(
+            probe("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"),
+            Some(TablebaseResult::Draw)
+        );
+        assert_eq!(
+            probe("6Qk/8/6K1/8/8/8/8/8 b - - 0 1"),
+            Some(TablebaseResult::Draw)
+        );
+        assert_eq!(
+            probe("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1"),
+            Some(TablebaseResult::Loss(0))
+        );
+        assert_eq!(probe("6k1/8/8/8/8/8/8/R5K1 w - - 0 1"), None);
+        // Read back from disk, it says the same.
+        let reopened: Tablebases = Tablebases::open(&directory, &piece_symbol_map).unwrap();
+        assert_eq!(reopened.len(), 2);
+        assert_eq!(reopened.get(&endgame).unwrap().values, table.values);
+        // A one ply search sees the whole mate through the table.
+        let longest: &str = report
+            .split("from ")
+            .nth(1)
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap();
+        let mut game: Game =
+            Game::from_fen(0, longest, standard, piece_symbol_map.clone()).unwrap();
+        let mut searcher: Searcher = Searcher::new();
+        searcher.tablebases = Some(std::sync::Arc::new(reopened));
+        let limits: SearchLimits = SearchLimits {
+            depth: Some(1),
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            searcher.search(&mut game, limits, &mut |_| {}).score,
+            MATE - 19
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn names_endings() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let piece_symbol_map: HashMap<char, &PieceType> = piece_list.map_piecetypes_to_symbols();
+        let endgame: Endgame = Endgame::parse("KPvKr", &piece_symbol_map).unwrap();
+        assert_eq!(endgame.name(), "KPvkr");
+        let exits: Vec<String> = endgame
+            .exits(&piece_symbol_map)
+            .iter()
+            .map(|e| e.name())
+            .collect();
+        assert_eq!(
+            exits,
+            vec!["Kvkr", "KNvkr", "KBvkr", "KRvkr", "KQvkr", "KPvk"]
+        );
+        assert!(Endgame::parse("KQRvKR", &piece_symbol_map).is_err());
+        assert!(Endgame::parse("KQ", &piece_symbol_map).is_err());
+        assert!(Endgame::parse("KXvK", &piece_symbol_map).is_err());
+        let (squares, active_color) = endgame.decode(endgame.index(&[4, 12, 60, 0], 'b'));
+        assert_eq!((squares, active_color), (vec![4, 12, 60, 0], 'b'));
+        assert_eq!(
+            endgame.fen(&[60, 12, 4, 0], 'b'),
+            "r3k3/4P3/8/8/8/8/8/4K3 b - - 0 1"
+        );
+    }
+}
@@ -0,0 +1,614 @@
+use crate::clock::*;
+use crate::configuration::*;
+use crate::recorder::*;
+use crate::selfplay::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// An engine configuration with a name to show in the tables, written name=spec on the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entrant {
+    pub name: String,
+    pub spec: String,
+}
+
+impl Entrant {
+    // Without a name the spec is the name.
+    pub fn parse(text: &str) -> Entrant {
+        match text.split_once('=') {
+            // engine:depth=3 has an = in it but isn't named.
+            Some((name, spec)) if !name.contains(':') => Entrant {
+                name: String::from(name),
+                spec: String::from(spec),
+            },
+            _ => Entrant {
+                name: String::from(text),
+                spec: String::from(text),
+            },
+        }
+    }
+}
+
+// One game on the schedule, and how it went once it's been played.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TournamentGame {
+    pub id: u64,
+    pub round: u32,
+    pub opening: String,
+    pub white: String,
+    pub black: String,
+    pub result: String,
+    pub reason: String,
+    pub plies: usize,
+}
+
+// A sequential probability ratio test of whether the first entrant is elo1 stronger than the second (H1)
+// rather than elo0 (H0), wrong at most alpha and beta of the time. The likelihoods use the BayesElo
+// model with the draw rate taken from the games so far, like cutechess-cli.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    // elo0,elo1[,alpha,beta] with alpha and beta 0.05 if they're left out.
+    pub fn parse(text: &str) -> Result<Sprt, String> {
+        let numbers: Vec<f64> = text
+            .split(',')
+            .map(|n| n.trim().parse())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| format!("expected elo0,elo1[,alpha,beta], got {}", text))?;
+        let sprt: Sprt = match numbers[..] {
+            [elo0, elo1] => Sprt {
+                elo0,
+                elo1,
+                alpha: 0.05,
+                beta: 0.05,
+            },
+            [elo0, elo1, alpha, beta] => Sprt {
+                elo0,
+                elo1,
+                alpha,
+                beta,
+            },
+            _ => return Err(format!("expected elo0,elo1[,alpha,beta], got {}", text)),
+        };
+        if sprt.elo0 >= sprt.elo1 {
+            return Err(String::from("elo0 has to be less than elo1"));
+        }
+        if !(0.0..0.5).contains(&sprt.alpha)
+            || !(0.0..0.5).contains(&sprt.beta)
+            || sprt.alpha == 0.0
+            || sprt.beta == 0.0
+        {
+            return Err(String::from("alpha and beta have to be between 0 and 0.5"));
+        }
+        Ok(sprt)
+    }
+
+    // Log likelihood ratios at which H0 and H1 are accepted.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    // Log likelihood ratio of H1 to H0 given the first entrant's wins, draws and losses.
+    // It stays at 0 until there's been at least one of each, since the draw rate can't be estimated before.
+    pub fn llr(&self, wins: usize, draws: usize, losses: usize) -> f64 {
+        if wins == 0 || draws == 0 || losses == 0 {
+            return 0.0;
+        }
+        let games: f64 = (wins + draws + losses) as f64;
+        let (win, loss): (f64, f64) = (wins as f64 / games, losses as f64 / games);
+        let draw_elo: f64 = 200.0 * ((1.0 - loss) / loss * (1.0 - win) / win).log10();
+        // The bounds are in ordinary Elo, BayesElo stretches them by however drawish the games are.
+        let x: f64 = 10f64.powf(-draw_elo / 400.0);
+        let scale: f64 = 4.0 * x / ((1.0 + x) * (1.0 + x));
+        let probabilities = |elo: f64| -> (f64, f64, f64) {
+            let bayes_elo: f64 = elo / scale;
+            let win: f64 = 1.0 / (1.0 + 10f64.powf((draw_elo - bayes_elo) / 400.0));
+            let loss: f64 = 1.0 / (1.0 + 10f64.powf((draw_elo + bayes_elo) / 400.0));
+            (win, 1.0 - win - loss, loss)
+        };
+        let (win0, draw0, loss0) = probabilities(self.elo0);
+        let (win1, draw1, loss1) = probabilities(self.elo1);
+        wins as f64 * (win1 / win0).ln()
+            + draws as f64 * (draw1 / draw0).ln()
+            + losses as f64 * (loss1 / loss0).ln()
+    }
+
+    pub fn decision(&self, llr: f64) -> Option<&'static str> {
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some("H1 accepted")
+        } else if llr <= lower {
+            Some("H0 accepted")
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SprtResult {
+    #[serde(flatten)]
+    pub test: Sprt,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    pub llr: f64,
+    pub lower: f64,
+    pub upper: f64,
+    // None while it's still undecided.
+    pub decision: Option<String>,
+}
+
+// How an entrant did against the rest of the field. elo is the Elo difference that scores that well,
+// error is half the width of its 95% confidence interval.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Standing {
+    pub name: String,
+    pub spec: String,
+    pub games: usize,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    pub score: f64,
+    pub elo: f64,
+    pub error: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TournamentResults {
+    // Best score first.
+    pub standings: Vec<Standing>,
+    pub games: Vec<TournamentGame>,
+    pub sprt: Option<SprtResult>,
+    // False if it was stopped early, by SPRT or Ctrl-C.
+    pub complete: bool,
+}
+
+pub struct Tournament {
+    pub entrants: Vec<Entrant>,
+    // Names from startingPositions.json. Every pairing plays each one twice, once with each colour.
+    pub openings: Vec<String>,
+    pub rounds: u32,
+    // Everyone plays the first entrant instead of each other.
+    pub gauntlet: bool,
+    // Only for two entrants.
+    pub sprt: Option<Sprt>,
+    pub evaluator: String,
+    pub control: Option<TimeControl>,
+    // Random moves at the start of every game, the same for both games of an opening pair and different
+    // every round, so rounds between deterministic engines aren't replays.
+    pub random_plies: usize,
+    // See catch_interrupts.
+    pub stop: Arc<AtomicBool>,
+    pub abandon: Arc<AtomicBool>,
+}
+
+impl Tournament {
+    pub fn new(entrants: Vec<Entrant>, openings: Vec<String>) -> Result<Tournament, String> {
+        if entrants.len() < 2 {
+            return Err(String::from("a tournament needs at least two engines"));
+        }
+        for (index, entrant) in entrants.iter().enumerate() {
+            if entrant.spec.starts_with("human") {
+                return Err(format!(
+                    "{} is a person, tournaments are for engines",
+                    entrant.name
+                ));
+            }
+            if entrants[..index].iter().any(|e| e.name == entrant.name) {
+                return Err(format!("there are two engines called {}", entrant.name));
+            }
+        }
+        if openings.is_empty() {
+            return Err(String::from("there are no openings to play"));
+        }
+        Ok(Tournament {
+            entrants,
+            openings,
+            rounds: 1,
+            gauntlet: false,
+            sprt: None,
+            evaluator: String::from("material"),
+            control: None,
+            random_plies: RANDOM_PLIES,
+            stop: Arc::new(AtomicBool::new(false)),
+            abandon: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    // Every game in the order they're played. The two games of an opening pair are next to each other.
+    pub fn schedule(&self) -> Vec<TournamentGame> {
+        let mut pairings: Vec<(usize, usize)> = Vec::new();
+        for first in 0..self.entrants.len() {
+            for second in first + 1..self.entrants.len() {
+                if !self.gauntlet || first == 0 {
+                    pairings.push((first, second));
+                }
+            }
+        }
+        let mut games: Vec<TournamentGame> = Vec::new();
+        for round in 0..self.rounds {
+            for opening in &self.openings {
+                for &(first, second) in &pairings {
+                    for (white, black) in [(first, second), (second, first)] {
+                        games.push(TournamentGame {
+                            id: games.len() as u64,
+                            round: round + 1,
+                            opening: opening.clone(),
+                            white: self.entrants[white].name.clone(),
+                            black: self.entrants[black].name.clone(),
+                            result: String::from("*"),
+                            reason: String::new(),
+                            plies: 0,
+                        });
+                    }
+                }
+            }
+        }
+        games
+    }
+
+    // Plays the schedule on a pool of threads. on_game hears about each game in schedule order.
+    pub fn run<'a>(
+        &self,
+        threads: usize,
+        piece_symbol_map: &HashMap<char, &'a PieceType>,
+        position_name_map: &HashMap<String, String>,
+        on_game: &mut dyn FnMut(&TournamentGame),
+    ) -> Result<TournamentResults, String> {
+        if self.sprt.is_some() && self.entrants.len() != 2 {
+            return Err(String::from("SPRT compares two engines"));
+        }
+        let schedule: Vec<TournamentGame> = self.schedule();
+        let spec = |name: &str| -> String {
+            self.entrants
+                .iter()
+                .find(|e| e.name == name)
+                .map_or(String::new(), |e| e.spec.clone())
+        };
+        let mut played: Vec<TournamentGame> = Vec::new();
+        let mut sprt: Option<SprtResult> = None;
+        in_parallel(
+            0..schedule.len() as u64,
+            threads,
+            &self.stop,
+            &|id| {
+                let game: &TournamentGame = &schedule[id as usize];
+                let setup: GameSetup = GameSetup {
+                    white: spec(&game.white),
+                    black: spec(&game.black),
+                    position: game.opening.clone(),
+                    evaluator: self.evaluator.clone(),
+                    control: self.control.clone(),
+                    max_plies: None,
+                    random_plies: self.random_plies,
+                    // The two games of a pair are next to each other in the schedule.
+                    opening_seed: Some(id / 2),
+                };
+                play_game(
+                    id,
+                    &setup,
+                    &self.abandon,
+                    piece_symbol_map,
+                    position_name_map,
+                )
+            },
+            &mut |id, log: GameLog| {
+                // Games that were already under way when SPRT decided don't count.
+                if sprt.as_ref().is_some_and(|s| s.decision.is_some()) {
+                    return Ok(());
+                }
+                let mut game: TournamentGame = schedule[id as usize].clone();
+                game.result = log.result.unwrap_or_else(|| String::from("*"));
+                game.reason = log.reason.unwrap_or_default();
+                game.plies = log.plies.len();
+                on_game(&game);
+                played.push(game);
+                if let Some(test) = self.sprt {
+                    let result: SprtResult = sprt_result(test, &played, &self.entrants[0].name);
+                    if result.decision.is_some() {
+                        self.stop.store(true, Ordering::SeqCst);
+                        self.abandon.store(true, Ordering::SeqCst);
+                    }
+                    sprt = Some(result);
+                }
+                Ok(())
+            },
+        )?;
+        let complete: bool = played.len() == schedule.len();
+        Ok(TournamentResults {
+            standings: standings(&self.entrants, &played),
+            games: played,
+            sprt: self
+                .sprt
+                .map(|test| sprt.unwrap_or_else(|| sprt_result(test, &[], &self.entrants[0].name))),
+            complete,
+        })
+    }
+}
+
+impl TournamentResults {
+    // Standings with each entrant's points against every other, row against column.
+    pub fn cross_table(&self) -> String {
+        let width: usize = self
+            .standings
+            .iter()
+            .map(|s| s.name.len())
+            .max()
+            .unwrap_or(0)
+            .max(4);
+        let mut output: String = format!(
+            "{:>4} {:<width$} {:>7} {:>6} {:>6} {:>6}",
+            "Rank",
+            "Name",
+            "Elo",
+            "+/-",
+            "Games",
+            "Score",
+            width = width
+        );
+        for standing in &self.standings {
+            output.push_str(&format!(" {:>width$}", standing.name, width = width.max(9)));
+        }
+        output.push('\n');
+        for (rank, standing) in self.standings.iter().enumerate() {
+            output.push_str(&format!(
+                "{:>4} {:<width$} {:>7.1} {:>6.1} {:>6} {:>5.1}%",
+                rank + 1,
+                standing.name,
+                standing.elo,
+                standing.error,
+                standing.games,
+                standing.score * 100.0,
+                width = width
+            ));
+            for opponent in &self.standings {
+                let cell: String = if opponent.name == standing.name {
+                    String::from("-")
+                } else {
+                    let (points, games) = head_to_head(&self.games, &standing.name, &opponent.name);
+                    format!("{:.1}/{}", points, games)
+                };
+                output.push_str(&format!(" {:>width$}", cell, width = width.max(9)));
+            }
+            output.push('\n');
+        }
+        if let Some(sprt) = &self.sprt {
+            output.push_str(&format!(
+                "SPRT elo0={} elo1={} alpha={} beta={}: LLR {:.2} ({:.2}, {:.2}), {}\n",
+                sprt.test.elo0,
+                sprt.test.elo1,
+                sprt.test.alpha,
+                sprt.test.beta,
+                sprt.llr,
+                sprt.lower,
+                sprt.upper,
+                sprt.decision.as_deref().unwrap_or("undecided")
+            ));
+        }
+        output
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+// The Elo difference at which the stronger side expects to score this fraction of the points.
+pub fn elo_difference(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// What a result is worth to one side, or None if the game didn't finish.
+fn points(result: &str, white: bool) -> Option<f64> {
+    let white_points: f64 = match result {
+        "1-0" => 1.0,
+        "0-1" => 0.0,
+        "1/2-1/2" => 0.5,
+        _ => return None,
+    };
+    Some(if white {
+        white_points
+    } else {
+        1.0 - white_points
+    })
+}
+
+// Points and games for player against opponent.
+fn head_to_head(games: &[TournamentGame], player: &str, opponent: &str) -> (f64, usize) {
+    let mut total: f64 = 0.0;
+    let mut count: usize = 0;
+    for game in games {
+        let white: bool = game.white == player && game.black == opponent;
+        let black: bool = game.black == player && game.white == opponent;
+        if !white && !black {
+            continue;
+        }
+        if let Some(points) = points(&game.result, white) {
+            total += points;
+            count += 1;
+        }
+    }
+    (total, count)
+}
+
+fn standings(entrants: &[Entrant], games: &[TournamentGame]) -> Vec<Standing> {
+    let mut output: Vec<Standing> = entrants
+        .iter()
+        .map(|entrant| {
+            let scores: Vec<f64> = games
+                .iter()
+                .filter(|g| g.white == entrant.name || g.black == entrant.name)
+                .filter_map(|g| points(&g.result, g.white == entrant.name))
+                .collect();
+            let count: f64 = scores.len() as f64;
+            // Kept off 0 and 1 like Trial::score, where the Elo curve goes to infinity.
+            let clamp = |score: f64| score.clamp(0.5 / count, 1.0 - 0.5 / count);
+            let (score, elo, error): (f64, f64, f64) = if scores.is_empty() {
+                (0.5, 0.0, 0.0)
+            } else {
+                let score: f64 = scores.iter().sum::<f64>() / count;
+                let kept: f64 = clamp(score);
+                // 95% of the time the true score is within 1.96 standard errors of this one.
+                let variance: f64 =
+                    scores.iter().map(|s| (s - kept) * (s - kept)).sum::<f64>() / count;
+                let margin: f64 = 1.96 * (variance / count).sqrt();
+                let error: f64 = (elo_difference(clamp(kept + margin))
+                    - elo_difference(clamp(kept - margin)))
+                    / 2.0;
+                (score, elo_difference(kept), error)
+            };
+            Standing {
+                name: entrant.name.clone(),
+                spec: entrant.spec.clone(),
+                games: scores.len(),
+                wins: scores.iter().filter(|&&s| s == 1.0).count(),
+                draws: scores.iter().filter(|&&s| s == 0.5).count(),
+                losses: scores.iter().filter(|&&s| s == 0.0).count(),
+                score,
+                elo,
+                error,
+            }
+        })
+        .collect();
+    output.sort_by(|a, b| b.score.total_cmp(&a.score));
+    output
+}
+
+fn sprt_result(test: Sprt, games: &[TournamentGame], first: &str) -> SprtResult {
+    let scores: Vec<f64> = games
+        .iter()
+        .filter_map(|g| points(&g.result, g.white == first))
+        .collect();
+    let wins: usize = scores.iter().filter(|&&s| s == 1.0).count();
+    let draws: usize = scores.iter().filter(|&&s| s == 0.5).count();
+    let losses: usize = scores.iter().filter(|&&s| s == 0.0).count();
+    let llr: f64 = test.llr(wins, draws, losses);
+    let (lower, upper) = test.bounds();
+    SprtResult {
+        test,
+        wins,
+        draws,
+        losses,
+        llr,
+        lower,
+        upper,
+        decision: test.decision(llr).map(String::from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::tournament::*;
+    use std::path::Path;
+
+    #[test]
+    fn sprt_and_elo() {
+        assert!(elo_difference(0.5).abs() < 1e-9);
+        assert!((elo_difference(0.75) - 190.85).abs() < 0.01);
+        assert!(Sprt::parse("5,0").is_err());
+        let sprt: Sprt = Sprt::parse("0,10").unwrap();
+        let (lower, upper) = sprt.bounds();
+        assert!((upper - 2.944).abs() < 0.001 && (lower + 2.944).abs() < 0.001);
+        assert_eq!(sprt.llr(10, 0, 5), 0.0);
+        assert_eq!(sprt.decision(sprt.llr(600, 300, 100)), Some("H1 accepted"));
+        assert_eq!(
+            sprt.decision(sprt.llr(4000, 2000, 4000)),
+            Some("H0 accepted")
+        );
+        assert_eq!(sprt.decision(sprt.llr(5, 5, 4)), None);
+    }
+
+    #[test]
+    fn perfect_scores_have_finite_elo() {
+        let entrants: Vec<Entrant> = vec![Entrant::parse("a=random"), Entrant::parse("b=random")];
+        let games: Vec<TournamentGame> = (0..4)
+            .map(|id| TournamentGame {
+                id,
+                round: 1,
+                opening: String::from("standard"),
+                white: String::from(if id % 2 == 0 { "a" } else { "b" }),
+                black: String::from(if id % 2 == 0 { "b" } else { "a" }),
+                result: String::from(if id % 2 == 0 { "1-0" } else { "0-1" }),
+                reason: String::new(),
+                plies: 10,
+            })
+            .collect();
+        let table: Vec<Standing> = standings(&entrants, &games);
+        assert_eq!((table[0].name.as_str(), table[0].score), ("a", 1.0));
+        // Scored as 3.5 out of 4.
+        assert!((table[0].elo - elo_difference(0.875)).abs() < 1e-9);
+        assert!((table[1].elo + table[0].elo).abs() < 1e-9);
+        assert!(table.iter().all(|s| s.error.is_finite() && s.error > 0.0));
+    }
+
+    #[test]
+    fn plays_a_tournament() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let mut position_name_map: HashMap<String, String> = HashMap::new();
+        position_name_map.insert(
+            String::from("rook ending"),
+            String::from("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"),
+        );
+        position_name_map.insert(
+            String::from("back rank"),
+            String::from("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1"),
+        );
+        let entrants: Vec<Entrant> = ["strong=engine:depth=2", "random", "weak=random:seed=9"]
+            .iter()
+            .map(|e| Entrant::parse(e))
+            .collect();
+        assert_eq!(entrants[0].spec, "engine:depth=2");
+        assert_eq!(entrants[1].name, "random");
+        let openings: Vec<String> = vec![String::from("rook ending"), String::from("back rank")];
+        let mut tournament: Tournament = Tournament::new(entrants, openings).unwrap();
+        assert_eq!(tournament.schedule().len(), 12);
+        tournament.gauntlet = true;
+        let schedule: Vec<TournamentGame> = tournament.schedule();
+        assert_eq!(schedule.len(), 8);
+        assert_eq!(
+            (schedule[0].white.as_str(), schedule[1].white.as_str()),
+            ("strong", "random")
+        );
+        assert!(schedule
+            .iter()
+            .all(|g| g.white == "strong" || g.black == "strong"));
+        let mut seen: Vec<u64> = Vec::new();
+        let results: TournamentResults = tournament
+            .run(
+                2,
+                &piece_list.map_piecetypes_to_symbols(),
+                &position_name_map,
+                &mut |g| seen.push(g.id),
+            )
+            .unwrap();
+        assert_eq!(seen, (0..8).collect::<Vec<u64>>());
+        assert!(results.complete);
+        assert_eq!(results.standings[0].name, "strong");
+        assert_eq!(results.standings[0].games, 8);
+        assert!(results.standings[0].score > 0.5);
+        let table: String = results.cross_table();
+        assert!(table.lines().nth(1).unwrap().contains("strong"));
+        let json: serde_json::Value = serde_json::from_str(&results.to_json()).unwrap();
+        assert_eq!(json["games"].as_array().unwrap().len(), 8);
+        assert_eq!(json["standings"][0]["spec"], "engine:depth=2");
+        assert!(Tournament::new(
+            vec![Entrant::parse("human"), Entrant::parse("random")],
+            vec![String::from("x")]
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::search::*;
+
+pub const DEFAULT_HASH_MB: usize = 16;
+
+// What a stored score says about the real one. Scores outside the alpha-beta window only give a bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // The real score is at least this much (the search failed high).
+    Lower,
+    // The real score is at most this much (the search failed low).
+    Upper,
+}
+
+// What to do when a new position lands on a slot that already holds a different one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replacement {
+    // Keep whichever entry was searched deeper, unless the old one is left over from an earlier search.
+    DepthPreferred,
+    AlwaysReplace,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    hash: u64,
+    depth: u32,
+    score: i32,
+    bound: Bound,
+    best_move: Option<MoveKey>,
+    age: u8,
+}
+
+// A stored entry, with the score already corrected for the ply it was probed at.
+#[derive(Debug, Clone, Copy)]
+pub struct Probe {
+    pub depth: u32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<MoveKey>,
+}
+
+// Counts since the start of the current search.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TableStats {
+    pub probes: u64,
+    pub hits: u64,
+    // Probes that found a different position in the slot.
+    pub collisions: u64,
+    pub stores: u64,
+    // Stores that threw out a different position.
+    pub overwrites: u64,
+    // How full the table is, in permille, like UCI's hashfull.
+    pub hashfull: u32,
+}
+
+impl TableStats {
+    pub fn hit_rate(&self) -> f64 {
+        if self.probes == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.probes as f64
+        }
+    }
+}
+
+// Fixed size table of search results keyed by Game.hash, so positions reached again by another move order aren't searched twice.
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    replacement: Replacement,
+    age: u8,
+    stats: TableStats,
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize, replacement: Replacement) -> TranspositionTable {
+        let count: usize = (megabytes * 1024 * 1024 / std::mem::size_of::<Option<Entry>>()).max(1);
+        TranspositionTable {
+            entries: vec![None; count],
+            replacement,
+            age: 0,
+            stats: TableStats::default(),
+        }
+    }
+
+    // Forget everything, for when a new game starts.
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|e| *e = None);
+        self.age = 0;
+    }
+
+    // Called at the start of every search. Entries from earlier searches are still used but are the first to go.
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+        self.stats = TableStats::default();
+    }
+
+    pub fn probe(&mut self, hash: u64, ply: usize) -> Option<Probe> {
+        self.stats.probes += 1;
+        let entry: Entry = self.entries[self.index(hash)]?;
+        if entry.hash != hash {
+            self.stats.collisions += 1;
+            return None;
+        }
+        self.stats.hits += 1;
+        Some(Probe {
+            depth: entry.depth,
+            score: score_from_table(entry.score, ply),
+            bound: entry.bound,
+            best_move: entry.best_move,
+        })
+    }
+
+    pub fn store(
+        &mut self,
+        hash: u64,
+        depth: u32,
+        score: i32,
+        bound: Bound,
+        best_move: Option<MoveKey>,
+        ply: usize,
+    ) {
+        let index: usize = self.index(hash);
+        if let Some(old) = self.entries[index] {
+            let replace: bool = match self.replacement {
+                Replacement::AlwaysReplace => true,
+                Replacement::DepthPreferred => {
+                    old.hash == hash || old.age != self.age || depth >= old.depth
+                }
+            };
+            if !replace {
+                return;
+            }
+            if old.hash != hash {
+                self.stats.overwrites += 1;
+            }
+        }
+        self.stats.stores += 1;
+        self.entries[index] = Some(Entry {
+            hash,
+            depth,
+            score: score_to_table(score, ply),
+            bound,
+            best_move,
+            age: self.age,
+        });
+    }
+
+    pub fn stats(&self) -> TableStats {
+        let sample: usize = self.entries.len().min(1000);
+        let used: usize = self.entries[..sample]
+            .iter()
+            .filter(|e| e.is_some_and(|e| e.age == self.age))
+            .count();
+        TableStats {
+            hashfull: (used * 1000 / sample) as u32,
+            ..self.stats
+        }
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        (hash % self.entries.len() as u64) as usize
+    }
+}
+
+// The statistics as a UCI info string, for the console and for logs.
+pub fn format_table_stats(stats: &TableStats) -> String {
+    format!(
+        "info string table probes {} hits {} hitrate {:.3} collisions {} stores {} overwrites {} hashfull {}",
+        stats.probes,
+        stats.hits,
+        stats.hit_rate(),
+        stats.collisions,
+        stats.stores,
+        stats.overwrites,
+        stats.hashfull
+    )
+}
+
+// Mate scores count plies from the root, but the same position can turn up at any ply.
+// The table stores them counted from the position itself and converts back when probed.
+fn score_to_table(score: i32, ply: usize) -> i32 {
+    if score > MATE - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score < -MATE + MAX_PLY as i32 {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_table(score: i32, ply: usize) -> i32 {
+    if score > MATE - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score < -MATE + MAX_PLY as i32 {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::search::*;
+    use crate::transposition::*;
+
+    #[test]
+    fn mate_scores_are_stored_relative_to_the_position() {
+        let mut table: TranspositionTable = TranspositionTable::new(1, Replacement::AlwaysReplace);
+        table.new_search();
+        // Mate 3 plies after a position at ply 4 is mate at ply 9 when the position turns up again at ply 6.
+        table.store(99, 5, MATE - 7, Bound::Exact, None, 4);
+        assert_eq!(table.probe(99, 6).unwrap().score, MATE - 9);
+        table.store(99, 5, -MATE + 7, Bound::Exact, None, 4);
+        assert_eq!(table.probe(99, 2).unwrap().score, -MATE + 5);
+        table.store(99, 5, 123, Bound::Lower, None, 4);
+        let probe: Probe = table.probe(99, 9).unwrap();
+        assert_eq!((probe.score, probe.bound), (123, Bound::Lower));
+    }
+
+    #[test]
+    fn replacement_schemes() {
+        let mut depth_preferred: TranspositionTable =
+            TranspositionTable::new(1, Replacement::DepthPreferred);
+        let mut always: TranspositionTable = TranspositionTable::new(1, Replacement::AlwaysReplace);
+        let slots: u64 = depth_preferred.entries.len() as u64;
+        for table in [&mut depth_preferred, &mut always] {
+            table.new_search();
+            table.store(7, 6, 10, Bound::Exact, Some((1, 2, None)), 0);
+            // Same slot, different position, shallower search.
+            table.store(7 + slots, 2, 20, Bound::Exact, None, 0);
+        }
+        assert_eq!(
+            depth_preferred.probe(7, 0).unwrap().best_move,
+            Some((1, 2, None))
+        );
+        assert!(depth_preferred.probe(7 + slots, 0).is_none());
+        assert!(always.probe(7, 0).is_none());
+        assert_eq!(always.probe(7 + slots, 0).unwrap().score, 20);
+        let stats: TableStats = always.stats();
+        assert_eq!((stats.probes, stats.hits, stats.collisions), (2, 1, 1));
+        assert_eq!((stats.stores, stats.overwrites), (2, 1));
+        // Entries from an earlier search make way even for shallower ones.
+        depth_preferred.new_search();
+        depth_preferred.store(7 + slots, 1, 30, Bound::Upper, None, 0);
+        assert_eq!(depth_preferred.probe(7 + slots, 0).unwrap().score, 30);
+    }
+}
@@ -0,0 +1,683 @@
+use crate::configuration::*;
+use crate::evaluation::*;
+use crate::game::*;
+use crate::hints::*;
+use crate::notation::*;
+use crate::recorder::*;
+use crate::render::*;
+use crate::search::*;
+use crate::BOARDSIZE;
+use ratatui::crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, MouseButton,
+    MouseEventKind,
+};
+use ratatui::crossterm::execute;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How deep the analysis pane searches when asked.
+const ANALYSIS_DEPTH: u32 = 4;
+// Each square is three characters wide: a space either side of the piece, or brackets round it under the cursor.
+const CELL_WIDTH: u16 = 3;
+// Room for "Castling: White (O-O) (O-O-O), Black O-O O-O-O" without wrapping.
+const STATUS_WIDTH: u16 = 50;
+
+// The full-screen game: the board on the left with the status panel under it, clocks, moves and analysis on the right.
+// Pieces are picked up and put down with the arrow keys and enter, or by clicking.
+pub struct Tui<'a> {
+    pub game: Game<'a>,
+    pub renderer: Renderer,
+    cursor: u8,
+    // The square of the piece picked up, and where it can go.
+    selected: Option<u8>,
+    destinations: Vec<AvailableMove<'a>>,
+    // Promotions to pick from by piece letter, once a promoting piece has been put down.
+    promotions: Vec<AvailableMove<'a>>,
+    show_threats: bool,
+    // Every move played since the position was set up, with how long it took, for the move list and export.
+    pub recorder: Recorder,
+    // Time each side has used, White first, for untimed games. Timed ones show the game's clocks.
+    used: [Duration; 2],
+    // When the side to move started thinking.
+    turn_started: Instant,
+    searcher: Searcher,
+    analysis: Vec<String>,
+    message: String,
+    finished: bool,
+    quit: bool,
+    // Where the squares were last drawn, for working out which one was clicked.
+    board_area: Rect,
+}
+
+impl<'a> Tui<'a> {
+    pub fn new(game: Game<'a>, evaluator: Arc<dyn Evaluator>) -> Tui<'a> {
+        // Start the cursor on the side to move's royal piece, or the middle of the board without one.
+        let cursor: u8 = game
+            .list_of_pieces_ingame
+            .iter()
+            .find(|p| !p.captured && p.piece_type.royal && p.player == game.active_color)
+            .map_or(BOARDSIZE * BOARDSIZE / 2, |p| p.position);
+        let mut searcher: Searcher = Searcher::new();
+        searcher.evaluator = evaluator;
+        Tui {
+            recorder: Recorder::new("human", "human", &game),
+            game,
+            renderer: Renderer::default(),
+            cursor,
+            selected: None,
+            destinations: Vec::new(),
+            promotions: Vec::new(),
+            show_threats: false,
+            used: [Duration::ZERO; 2],
+            turn_started: Instant::now(),
+            searcher,
+            analysis: Vec::new(),
+            message: String::from("Arrow keys and enter or the mouse to move, h for help."),
+            finished: false,
+            quit: false,
+            board_area: Rect::default(),
+        }
+    }
+
+    // Takes over the terminal until q is pressed, and puts it back afterwards even if drawing fails.
+    pub fn run(&mut self) -> std::io::Result<()> {
+        let mut terminal: DefaultTerminal = ratatui::init();
+        let result: std::io::Result<()> = execute!(std::io::stdout(), EnableMouseCapture)
+            .and_then(|_| self.event_loop(&mut terminal));
+        let restored: std::io::Result<()> = execute!(std::io::stdout(), DisableMouseCapture);
+        ratatui::restore();
+        result.and(restored)
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+        while !self.quit {
+            self.check_flag();
+            terminal.draw(|frame| self.draw(frame))?;
+            // Poll rather than block so the clocks keep ticking while nobody touches anything.
+            if event::poll(Duration::from_millis(250))? {
+                self.handle_event(event::read()?);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn handle_event(&mut self, event: Event) {
+        // Waiting on a promotion, only the piece letter or Esc count.
+        if !self.promotions.is_empty() {
+            if let Event::Key(key) = event {
+                if key.kind == KeyEventKind::Press {
+                    self.choose_promotion(key.code);
+                }
+            }
+            return;
+        }
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Up => self.move_cursor(0, -1),
+                KeyCode::Down => self.move_cursor(0, 1),
+                KeyCode::Left => self.move_cursor(-1, 0),
+                KeyCode::Right => self.move_cursor(1, 0),
+                KeyCode::Enter | KeyCode::Char(' ') => self.activate(self.cursor),
+                KeyCode::Esc => self.deselect(),
+                KeyCode::Char('f') => self.renderer.flipped = !self.renderer.flipped,
+                KeyCode::Char('t') => self.show_threats = !self.show_threats,
+                KeyCode::Char('u') => self.undo(),
+                KeyCode::Char('a') => self.analyse(),
+                KeyCode::Char('h') => {
+                    self.message = String::from(
+                        "Enter/space/click: pick up or put down. Esc: drop. f: flip. t: threats. u: undo. a: analyse. q: quit.",
+                    )
+                }
+                KeyCode::Char('q') => self.quit = true,
+                _ => {}
+            },
+            Event::Mouse(mouse) if mouse.kind == MouseEventKind::Down(MouseButton::Left) => {
+                if let Some(square) = self.square_at(mouse.column, mouse.row) {
+                    self.cursor = square;
+                    self.activate(square);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Moves the cursor as it looks on screen, so up is always up whichever way round the board is.
+    fn move_cursor(&mut self, dx: i8, dy: i8) {
+        let (dx, dy) = if self.renderer.flipped {
+            (-dx, -dy)
+        } else {
+            (dx, dy)
+        };
+        let file: i8 = (self.cursor % BOARDSIZE) as i8 + dx;
+        let rank: i8 = (self.cursor / BOARDSIZE) as i8 + dy;
+        let size: i8 = BOARDSIZE as i8;
+        if (0..size).contains(&file) && (0..size).contains(&rank) {
+            self.cursor = (rank * size + file) as u8;
+        }
+    }
+
+    // Enter or a click: play the move if a piece is picked up and can go here, otherwise pick up what's here.
+    fn activate(&mut self, square: u8) {
+        if self.finished {
+            self.message = String::from("The game is over. u to take back a move, q to quit.");
+            return;
+        }
+        let landing: Vec<AvailableMove<'a>> = self
+            .destinations
+            .iter()
+            .filter(|m| m.to == square)
+            .copied()
+            .collect();
+        match landing.len() {
+            0 => {}
+            1 => {
+                self.play(landing[0]);
+                return;
+            }
+            // Promotions all share a destination, so ask which piece.
+            _ => {
+                let letters: Vec<String> = landing
+                    .iter()
+                    .filter_map(|m| m.promotion)
+                    .map(|p| p.to_ascii_lowercase().to_string())
+                    .collect();
+                self.message = format!("Promote to {}? Esc to cancel.", letters.join(", "));
+                self.promotions = landing;
+                return;
+            }
+        }
+        if self.selected == Some(square) {
+            self.deselect();
+            return;
+        }
+        match hint(&mut self.game, square) {
+            Ok((quiet, captures)) => {
+                self.message = if quiet.is_empty() && captures.is_empty() {
+                    format!(
+                        "The {} on {} has no legal moves.",
+                        self.game
+                            .piece(self.game.position[square as usize])
+                            .piece_type
+                            .name,
+                        square_name(square)
+                    )
+                } else {
+                    String::new()
+                };
+                self.selected = Some(square);
+                self.destinations = quiet;
+                self.destinations.extend(captures);
+            }
+            Err(why) => {
+                self.deselect();
+                self.message = why;
+            }
+        }
+    }
+
+    fn choose_promotion(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => {
+                self.promotions.clear();
+                self.message = String::new();
+            }
+            KeyCode::Char(letter) => {
+                if let Some(available_move) = self
+                    .promotions
+                    .iter()
+                    .find(|m| m.promotion.is_some_and(|p| p.eq_ignore_ascii_case(&letter)))
+                    .copied()
+                {
+                    self.play(available_move);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn deselect(&mut self) {
+        self.selected = None;
+        self.destinations.clear();
+        self.promotions.clear();
+    }
+
+    fn play(&mut self, available_move: AvailableMove<'a>) {
+        let mover: usize = if self.game.active_color == 'w' { 0 } else { 1 };
+        let elapsed: Duration = self.turn_started.elapsed();
+        self.used[mover] += elapsed;
+        self.turn_started = Instant::now();
+        if self.game.punch_clock(elapsed) {
+            self.recorder
+                .make_move(&mut self.game, available_move, elapsed, None);
+            self.cursor = available_move.to;
+        }
+        self.deselect();
+        self.analysis.clear();
+        self.game_over();
+    }
+
+    // The flag falls as soon as the time is up, not when the move finally comes.
+    fn check_flag(&mut self) {
+        let elapsed: Duration = self.turn_started.elapsed();
+        let out_of_time: bool =
+            self.game.clocks.as_ref().is_some_and(|c| {
+                c.flag().is_none() && elapsed > c.remaining(self.game.active_color)
+            });
+        if out_of_time && !self.finished {
+            self.game.punch_clock(elapsed);
+            self.deselect();
+            self.game_over();
+        }
+    }
+
+    fn game_over(&mut self) {
+        self.message = match self.game.outcome() {
+            Some(outcome) => {
+                self.finished = true;
+                self.recorder.finish(outcome.result(), outcome.reason());
+                format!("Game over: {} {{{}}}", outcome.result(), outcome.reason())
+            }
+            None => String::new(),
+        };
+    }
+
+    fn undo(&mut self) {
+        if self.game.list_of_moves.len() <= self.game.setup_moves {
+            self.message = String::from("Nothing to undo.");
+            return;
+        }
+        self.recorder.unmake_move(&mut self.game);
+        self.game.undo_clock();
+        self.deselect();
+        self.analysis.clear();
+        self.finished = false;
+        self.turn_started = Instant::now();
+        self.message = String::new();
+    }
+
+    // Searches to a fixed depth right away, one line per iteration. The screen waits for it.
+    fn analyse(&mut self) {
+        if self.finished {
+            return;
+        }
+        let limits: SearchLimits = SearchLimits {
+            depth: Some(ANALYSIS_DEPTH),
+            movetime: None,
+            nodes: None,
+        };
+        let mut iterations: Vec<SearchResult<'a>> = Vec::new();
+        self.searcher.search(&mut self.game, limits, &mut |result| {
+            iterations.push(result.clone())
+        });
+        self.analysis = iterations
+            .iter()
+            .map(|result| {
+                format!(
+                    "{:>2} {:>+6} {}",
+                    result.depth,
+                    result.score,
+                    line_san(&self.game, &result.principal_variation).join(" ")
+                )
+            })
+            .collect();
+    }
+
+    // Which square is drawn at a spot on the screen, if any.
+    fn square_at(&self, column: u16, row: u16) -> Option<u8> {
+        let area: Rect = self.board_area;
+        if column < area.x || row < area.y || column >= area.right() || row >= area.bottom() {
+            return None;
+        }
+        let index: u8 = ((row - area.y) as u8) * BOARDSIZE + ((column - area.x) / CELL_WIDTH) as u8;
+        Some(self.screen_to_square(index))
+    }
+
+    // Screen positions count from the top left like squares do, so flipping is just counting from the other end.
+    fn screen_to_square(&self, index: u8) -> u8 {
+        if self.renderer.flipped {
+            BOARDSIZE * BOARDSIZE - 1 - index
+        } else {
+            index
+        }
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        // Wide enough for the board, or the longest status line if that's wider.
+        let left_width: u16 = (3 + CELL_WIDTH * BOARDSIZE as u16 + 2).max(STATUS_WIDTH);
+        let board_height: u16 = BOARDSIZE as u16 + 3;
+        let [main, help] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Length(left_width), Constraint::Min(20)]).areas(main);
+        let [board, status] =
+            Layout::vertical([Constraint::Length(board_height), Constraint::Min(0)]).areas(left);
+        let [clocks, moves, analysis] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Min(3),
+            Constraint::Length(ANALYSIS_DEPTH as u16 + 2),
+        ])
+        .areas(right);
+        self.draw_board(frame, board);
+        let status_text: String = self.renderer.status(&mut self.game);
+        frame.render_widget(
+            Paragraph::new(status_text)
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title("Status")),
+            status,
+        );
+        frame.render_widget(
+            Paragraph::new(self.clock_lines()).block(Block::bordered().title("Clocks")),
+            clocks,
+        );
+        let move_lines: Vec<Line> = self.move_lines();
+        // Keep the latest moves in view once the list is longer than the pane.
+        let scroll: u16 = (move_lines.len() as u16).saturating_sub(moves.height.saturating_sub(2));
+        frame.render_widget(
+            Paragraph::new(move_lines)
+                .scroll((scroll, 0))
+                .block(Block::bordered().title("Moves")),
+            moves,
+        );
+        let analysis_lines: Vec<Line> = if self.analysis.is_empty() {
+            vec![Line::from("Press a to analyse.")]
+        } else {
+            self.analysis
+                .iter()
+                .map(|l| Line::from(l.as_str()))
+                .collect()
+        };
+        frame.render_widget(
+            Paragraph::new(analysis_lines).block(Block::bordered().title("Analysis")),
+            analysis,
+        );
+        frame.render_widget(Paragraph::new(self.message.as_str()), help);
+    }
+
+    fn draw_board(&mut self, frame: &mut Frame, area: Rect) {
+        let block: Block = Block::bordered().title("Board");
+        let inner: Rect = block.inner(area);
+        self.board_area = Rect {
+            x: inner.x + 3,
+            y: inner.y,
+            width: CELL_WIDTH * BOARDSIZE as u16,
+            height: BOARDSIZE as u16,
+        };
+        let last_move: Option<(u8, u8)> = self
+            .game
+            .list_of_moves
+            .last()
+            .map(|record| (record.played.from, record.played.to));
+        let attacked: Vec<u8> = if self.show_threats {
+            threats(&self.game)
+        } else {
+            Vec::new()
+        };
+        let mut lines: Vec<Line> = Vec::new();
+        for row in 0..BOARDSIZE {
+            let rank: u8 = self.screen_to_square(row * BOARDSIZE) / BOARDSIZE;
+            let mut spans: Vec<Span> = vec![Span::raw(format!("{:>2} ", BOARDSIZE - rank))];
+            for column in 0..BOARDSIZE {
+                let square: u8 = self.screen_to_square(row * BOARDSIZE + column);
+                let id: u8 = self.game.position[square as usize];
+                let light: bool = (square / BOARDSIZE + square % BOARDSIZE).is_multiple_of(2);
+                let destination: Option<&AvailableMove> =
+                    self.destinations.iter().find(|m| m.to == square);
+                let background: u8 = match destination {
+                    _ if self.selected == Some(square) => SELECTED,
+                    Some(m) if m.is_capture() => CAPTURE,
+                    Some(_) => QUIET,
+                    None if attacked.contains(&square) => ATTACKED,
+                    None if last_move.is_some_and(|(f, t)| square == f || square == t) => {
+                        if light {
+                            LIGHT_HIGHLIGHT
+                        } else {
+                            DARK_HIGHLIGHT
+                        }
+                    }
+                    None if light => LIGHT_SQUARE,
+                    None => DARK_SQUARE,
+                };
+                let (glyph, foreground) = if id == 0 {
+                    (' ', BLACK_PIECE)
+                } else {
+                    let piece: &Piece = self.game.piece(id);
+                    let foreground: u8 = if piece.player == 'w' {
+                        WHITE_PIECE
+                    } else {
+                        BLACK_PIECE
+                    };
+                    (self.renderer.glyph(piece), foreground)
+                };
+                let mut style: Style = Style::default()
+                    .bg(Color::Indexed(background))
+                    .fg(Color::Indexed(foreground));
+                let text: String = if square == self.cursor {
+                    style = style.add_modifier(Modifier::BOLD);
+                    format!("[{}]", glyph)
+                } else {
+                    format!(" {} ", glyph)
+                };
+                spans.push(Span::styled(text, style));
+            }
+            lines.push(Line::from(spans));
+        }
+        let mut files: String = String::from("   ");
+        for column in 0..BOARDSIZE {
+            let file: u8 = self.screen_to_square(column) % BOARDSIZE;
+            files.push_str(&format!(" {} ", (b'a' + file) as char));
+        }
+        lines.push(Line::from(files));
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    // Time left on a clock, or time used without one, ticking for the side to move.
+    fn clock_lines(&self) -> Vec<Line<'static>> {
+        let running: Duration = if self.finished {
+            Duration::ZERO
+        } else {
+            self.turn_started.elapsed()
+        };
+        [("White", 'w'), ("Black", 'b')]
+            .into_iter()
+            .map(|(name, player)| {
+                let ticking: Duration = if player == self.game.active_color {
+                    running
+                } else {
+                    Duration::ZERO
+                };
+                let time: Duration = match &self.game.clocks {
+                    Some(clocks) => clocks.remaining(player).saturating_sub(ticking),
+                    None => self.used[if player == 'w' { 0 } else { 1 }] + ticking,
+                };
+                let seconds: u64 = time.as_secs();
+                let text: String = format!("{} {:02}:{:02}", name, seconds / 60, seconds % 60);
+                if player == self.game.active_color && !self.finished {
+                    Line::styled(text, Style::default().add_modifier(Modifier::BOLD))
+                } else {
+                    Line::from(text)
+                }
+            })
+            .collect()
+    }
+
+    // One line per move number, like 1. e4 e5, starting 1... when Black moved first.
+    fn move_lines(&self) -> Vec<Line<'static>> {
+        let moves_played: usize = self.recorder.log.plies.len();
+        let black_first: bool = (self.game.active_color == 'b') == moves_played.is_multiple_of(2);
+        let first_number: u32 =
+            self.game.fullmove_number - (moves_played as u32 + black_first as u32) / 2;
+        let mut sans: Vec<&str> = self
+            .recorder
+            .log
+            .plies
+            .iter()
+            .map(|p| p.san.as_str())
+            .collect();
+        if black_first {
+            sans.insert(0, "...");
+        }
+        sans.chunks(2)
+            .enumerate()
+            .map(|(index, pair)| {
+                Line::from(format!(
+                    "{:>3}. {}",
+                    first_number as usize + index,
+                    pair.join(" ")
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::tui::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::crossterm::event::{KeyEvent, KeyModifiers, MouseEvent};
+    use ratatui::Terminal;
+    use std::path::Path;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn key(code: KeyCode) -> Event {
+        Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn sans<'a>(tui: &'a Tui) -> Vec<&'a str> {
+        tui.recorder
+            .log
+            .plies
+            .iter()
+            .map(|p| p.san.as_str())
+            .collect()
+    }
+
+    fn click(column: u16, row: u16) -> Event {
+        Event::Mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        })
+    }
+
+    // The screen as plain text, one string per row.
+    fn screen(terminal: &Terminal<TestBackend>) -> Vec<String> {
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn draws_the_panes() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let game: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let mut tui: Tui = Tui::new(game, Arc::new(Material));
+        let mut terminal: Terminal<TestBackend> = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| tui.draw(frame)).unwrap();
+        let rows: Vec<String> = screen(&terminal);
+        assert!(rows[0].starts_with("┌Board"));
+        assert!(rows[0].contains("┐┌Clocks"));
+        assert!(rows[1].starts_with("│ 8  r  n  b  q  k  b  n  r      "));
+        assert!(rows[8].starts_with("│ 1  R  N  B  Q [K] B  N  R "));
+        assert!(rows[9].starts_with("│    a  b  c  d  e  f  g  h "));
+        assert!(rows[1].contains("│White 00:00"));
+        assert!(rows[2].contains("│Black 00:00"));
+        assert!(rows.iter().any(|r| r.contains("Press a to analyse.")));
+        assert!(rows
+            .iter()
+            .any(|r| r.contains("│Castling: White (O-O) (O-O-O), Black O-O O-O-O")));
+        assert!(rows.iter().any(|r| r.contains("│Move 1, White to move.")));
+        // Last rank is light on h1, dark on g1.
+        let buffer = terminal.backend().buffer();
+        assert_eq!(buffer[(26, 8)].bg, Color::Indexed(LIGHT_SQUARE));
+        assert_eq!(buffer[(23, 8)].bg, Color::Indexed(DARK_SQUARE));
+    }
+
+    #[test]
+    fn moves_with_keys_and_mouse() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let game: Game =
+            Game::from_fen(0, START, START, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let mut tui: Tui = Tui::new(game, Arc::new(Material));
+        let mut terminal: Terminal<TestBackend> = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| tui.draw(frame)).unwrap();
+        // Up from the king to e2, pick up the pawn and look at where it can go.
+        tui.handle_event(key(KeyCode::Up));
+        tui.handle_event(key(KeyCode::Enter));
+        terminal.draw(|frame| tui.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        // e2 is column 17, e3 and e4 are rows 6 and 5.
+        assert_eq!(buffer[(17, 7)].bg, Color::Indexed(SELECTED));
+        assert_eq!(buffer[(17, 6)].bg, Color::Indexed(QUIET));
+        assert_eq!(buffer[(17, 5)].bg, Color::Indexed(QUIET));
+        tui.handle_event(key(KeyCode::Up));
+        tui.handle_event(key(KeyCode::Up));
+        tui.handle_event(key(KeyCode::Char(' ')));
+        assert_eq!(sans(&tui), vec!["e4"]);
+        // Clicking on a white piece with Black to move says so.
+        tui.handle_event(click(5, 8));
+        assert_eq!(tui.message, "The rook on a1 isn't yours to move.");
+        // Flip the board and click d7 then d5: d7 is now on the second row from the bottom, fifth from the left.
+        tui.handle_event(key(KeyCode::Char('f')));
+        tui.handle_event(click(17, 7));
+        tui.handle_event(click(17, 5));
+        assert_eq!(sans(&tui), vec!["e4", "d5"]);
+        terminal.draw(|frame| tui.draw(frame)).unwrap();
+        let rows: Vec<String> = screen(&terminal);
+        assert!(rows[1].starts_with("│ 1  R  N  B  K  Q  B  N  R "));
+        assert!(rows.iter().any(|r| r.contains("│  1. e4 d5")));
+        tui.handle_event(key(KeyCode::Char('a')));
+        assert_eq!(tui.analysis.len(), ANALYSIS_DEPTH as usize);
+        tui.handle_event(key(KeyCode::Char('u')));
+        assert_eq!(sans(&tui), vec!["e4"]);
+        assert_eq!(tui.game.active_color, 'b');
+        tui.handle_event(key(KeyCode::Char('q')));
+        assert!(tui.quit);
+    }
+
+    #[test]
+    fn asks_which_piece_to_promote_to() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let game: Game = Game::from_fen(
+            0,
+            "8/4P3/8/8/8/8/k7/4K3 w - - 0 1",
+            START,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        let mut tui: Tui = Tui::new(game, Arc::new(Material));
+        let mut terminal: Terminal<TestBackend> = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| tui.draw(frame)).unwrap();
+        // Pick up the pawn on e7 and put it down on e8.
+        tui.handle_event(click(17, 2));
+        tui.handle_event(click(17, 1));
+        assert!(sans(&tui).is_empty());
+        assert_eq!(tui.message, "Promote to n, b, r, q? Esc to cancel.");
+        // Letters go to the chooser rather than their usual commands.
+        tui.handle_event(key(KeyCode::Char('x')));
+        tui.handle_event(key(KeyCode::Char('q')));
+        assert!(!tui.quit);
+        assert_eq!(sans(&tui), vec!["e8=Q"]);
+        assert_eq!(
+            tui.game.piece(tui.game.position[4]).piece_type.name,
+            "queen"
+        );
+    }
+}
@@ -0,0 +1,473 @@
+use crate::configuration::*;
+use crate::game::*;
+use crate::search::*;
+use crate::transposition::*;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// Where the engine writes to. Shared with the search thread, which prints info lines and the best move.
+pub type SharedOutput = Arc<Mutex<dyn Write + Send>>;
+
+pub fn send(output: &SharedOutput, line: &str) {
+    let mut output = output.lock().unwrap();
+    // Nothing useful to do if the GUI has gone away.
+    let _ = writeln!(output, "{}", line);
+    let _ = output.flush();
+}
+
+// A search running on its own thread, which hands the Searcher back when it's done.
+struct RunningSearch {
+    handle: JoinHandle<Searcher>,
+    stop: Arc<AtomicBool>,
+    infinite: bool,
+}
+
+// The engine as seen over the Universal Chess Interface.
+// Rulesets loaded here live for the rest of the program, so games and search threads can borrow them freely.
+pub struct UciEngine {
+    output: SharedOutput,
+    pieces_path: String,
+    positions_path: String,
+    piece_list: &'static PieceList,
+    position_name_map: HashMap<String, String>,
+    variant: String,
+    game_counter: u64,
+    game: Game<'static>,
+    hash_megabytes: usize,
+    // None while a search has it.
+    searcher: Option<Searcher>,
+    running: Option<RunningSearch>,
+}
+
+impl UciEngine {
+    pub fn new(
+        pieces_path: &str,
+        positions_path: &str,
+        output: SharedOutput,
+    ) -> Result<UciEngine, String> {
+        let piece_list: &'static PieceList = load_ruleset(pieces_path)?;
+        let position_name_map: HashMap<String, String> = load_positions(positions_path)?;
+        let variant: String = default_variant(&position_name_map)?;
+        let game: Game<'static> = Game::from_fen(
+            0,
+            &position_name_map[&variant],
+            &position_name_map[&variant],
+            piece_list.map_piecetypes_to_symbols(),
+        )?;
+        Ok(UciEngine {
+            output,
+            pieces_path: String::from(pieces_path),
+            positions_path: String::from(positions_path),
+            piece_list,
+            position_name_map,
+            variant,
+            game_counter: 0,
+            game,
+            hash_megabytes: DEFAULT_HASH_MB,
+            searcher: Some(Searcher::new()),
+            running: None,
+        })
+    }
+
+    // Read commands until quit or the end of the input.
+    // At the end of the input a search still running is allowed to finish, so scripts can pipe commands in.
+    pub fn run(&mut self, input: impl BufRead) {
+        for line in input.lines() {
+            let line: String = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if !self.handle(&line) {
+                self.stop_search();
+                return;
+            }
+        }
+        self.wait_for_search();
+    }
+
+    // Returns false once asked to quit.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first().copied() {
+            Some("uci") => self.identify(),
+            Some("isready") => self.send("readyok"),
+            // A search still running when the GUI moves on is stopped, and still reports its best move.
+            Some("ucinewgame") => {
+                self.stop_search();
+                self.game_counter += 1;
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.table.clear();
+                }
+                self.start_position();
+            }
+            Some("setoption") => {
+                self.stop_search();
+                self.set_option(&words[1..]);
+            }
+            Some("position") => {
+                self.stop_search();
+                self.position(&words[1..]);
+            }
+            Some("go") => {
+                self.stop_search();
+                self.go(&words[1..]);
+            }
+            Some("stop") => self.stop_search(),
+            Some("quit") => return false,
+            Some(other) => self.send(&format!("info string unknown command {}", other)),
+            None => {}
+        }
+        true
+    }
+
+    fn new_searcher(&self) -> Searcher {
+        let mut searcher: Searcher = Searcher::new();
+        searcher.table = TranspositionTable::new(self.hash_megabytes, Replacement::DepthPreferred);
+        searcher
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.output, line);
+    }
+
+    fn identify(&self) {
+        self.send(&format!(
+            "id name piecetestbed {}",
+            env!("CARGO_PKG_VERSION")
+        ));
+        self.send("id author the piecetestbed authors");
+        self.send(&format!(
+            "option name Hash type spin default {} min 1 max 65536",
+            DEFAULT_HASH_MB
+        ));
+        self.send(&format!(
+            "option name PiecesFile type string default {}",
+            self.pieces_path
+        ));
+        self.send(&format!(
+            "option name PositionsFile type string default {}",
+            self.positions_path
+        ));
+        let mut variants: Vec<&String> = self.position_name_map.keys().collect();
+        variants.sort();
+        let choices: Vec<String> = variants.iter().map(|v| format!("var {}", v)).collect();
+        self.send(&format!(
+            "option name UCI_Variant type combo default {} {}",
+            self.variant,
+            choices.join(" ")
+        ));
+        self.send("uciok");
+    }
+
+    // setoption name <name> value <value>. Names may have spaces in them, values too.
+    fn set_option(&mut self, words: &[&str]) {
+        let value_at: usize = words
+            .iter()
+            .position(|w| *w == "value")
+            .unwrap_or(words.len());
+        let name: String = words.get(1..value_at).unwrap_or_default().join(" ");
+        let value: String = words.get(value_at + 1..).unwrap_or_default().join(" ");
+        match name.as_str() {
+            "Hash" => match value.parse::<usize>() {
+                Ok(megabytes) if megabytes > 0 => {
+                    self.hash_megabytes = megabytes;
+                    self.searcher = Some(self.new_searcher());
+                }
+                _ => self.send(&format!("info string bad Hash value {}", value)),
+            },
+            // Rulesets are never freed, so setting the same file again doesn't load another copy.
+            "PiecesFile" if value == self.pieces_path => self.start_position(),
+            "PiecesFile" => match load_ruleset(&value) {
+                Ok(piece_list) => {
+                    self.piece_list = piece_list;
+                    self.pieces_path = value;
+                    self.start_position();
+                }
+                Err(why) => self.send(&format!("info string {}", why)),
+            },
+            "PositionsFile" => match load_positions(&value) {
+                Ok(position_name_map) => {
+                    if !position_name_map.contains_key(&self.variant) {
+                        match default_variant(&position_name_map) {
+                            Ok(variant) => self.variant = variant,
+                            Err(why) => {
+                                self.send(&format!("info string {}", why));
+                                return;
+                            }
+                        }
+                    }
+                    self.position_name_map = position_name_map;
+                    self.positions_path = value;
+                    self.start_position();
+                }
+                Err(why) => self.send(&format!("info string {}", why)),
+            },
+            "UCI_Variant" => {
+                if self.position_name_map.contains_key(&value) {
+                    self.variant = value;
+                    self.start_position();
+                } else {
+                    self.send(&format!(
+                        "info string no position called {} in {}",
+                        value, self.positions_path
+                    ));
+                }
+            }
+            _ => self.send(&format!("info string unknown option {}", name)),
+        }
+    }
+
+    fn start_fen(&self) -> &str {
+        &self.position_name_map[&self.variant]
+    }
+
+    fn start_position(&mut self) {
+        self.position(&["startpos"]);
+    }
+
+    // position startpos|fen <fen> [moves <move> ...]
+    fn position(&mut self, words: &[&str]) {
+        let moves_at: usize = words
+            .iter()
+            .position(|w| *w == "moves")
+            .unwrap_or(words.len());
+        let fen: String = match words.first().copied() {
+            Some("startpos") => String::from(self.start_fen()),
+            Some("fen") => words[1..moves_at].join(" "),
+            _ => {
+                self.send("info string expected position startpos or position fen");
+                return;
+            }
+        };
+        let game: Result<Game<'static>, String> = Game::from_fen(
+            self.game_counter,
+            &fen,
+            self.start_fen(),
+            self.piece_list.map_piecetypes_to_symbols(),
+        );
+        self.game = match game {
+            Ok(game) => game,
+            Err(why) => {
+                self.send(&format!("info string bad fen {}: {}", fen, why));
+                return;
+            }
+        };
+        for coordinate in words.get(moves_at + 1..).unwrap_or_default() {
+            match self.game.find_move(coordinate) {
+                Some(available_move) => self.game.make_move(available_move),
+                None => {
+                    self.send(&format!("info string illegal move {}", coordinate));
+                    return;
+                }
+            }
+        }
+    }
+
+    // go [depth N] [nodes N] [movetime ms] [wtime ms] [btime ms] [winc ms] [binc ms] [movestogo N] [infinite]
+    fn go(&mut self, words: &[&str]) {
+        let mut limits: SearchLimits = SearchLimits::default();
+        let mut clock: (Option<u64>, Option<u64>, u64, u64) = (None, None, 0, 0);
+        let mut moves_to_go: Option<u32> = None;
+        let mut infinite: bool = false;
+        let mut index: usize = 0;
+        while index < words.len() {
+            let number: Option<u64> = words.get(index + 1).and_then(|w| w.parse().ok());
+            match words[index] {
+                "depth" => limits.depth = number.map(|n| n as u32),
+                "nodes" => limits.nodes = number,
+                "movetime" => limits.movetime = number.map(Duration::from_millis),
+                "wtime" => clock.0 = number,
+                "btime" => clock.1 = number,
+                "winc" => clock.2 = number.unwrap_or(0),
+                "binc" => clock.3 = number.unwrap_or(0),
+                "movestogo" => moves_to_go = number.map(|n| n as u32),
+                "infinite" => infinite = true,
+                _ => {}
+            }
+            index += 1;
+        }
+        let (remaining, increment) = if self.game.active_color == 'w' {
+            (clock.0, clock.2)
+        } else {
+            (clock.1, clock.3)
+        };
+        if let (Some(remaining), None) = (remaining, limits.movetime) {
+            limits.movetime = Some(time_for_move(
+                Duration::from_millis(remaining),
+                Duration::from_millis(increment),
+                moves_to_go,
+            ));
+        }
+        let mut searcher: Searcher = self.searcher.take().unwrap_or_else(|| self.new_searcher());
+        let stop: Arc<AtomicBool> = searcher.stop.clone();
+        stop.store(false, Ordering::Relaxed);
+        let mut game: Game<'static> = self.game.clone();
+        let output: SharedOutput = self.output.clone();
+        let thread_stop: Arc<AtomicBool> = stop.clone();
+        let handle: JoinHandle<Searcher> = std::thread::spawn(move || {
+            let result: SearchResult = searcher.search(&mut game, limits, &mut |info| {
+                send(&output, &format_info(info))
+            });
+            // UCI wants nothing more until stop when searching infinitely, even if the search ran out of things to do.
+            while infinite && !thread_stop.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            send(&output, &format_bestmove(&result));
+            searcher
+        });
+        self.running = Some(RunningSearch {
+            handle,
+            stop,
+            infinite,
+        });
+    }
+
+    fn stop_search(&mut self) {
+        if let Some(running) = &self.running {
+            running.stop.store(true, Ordering::Relaxed);
+        }
+        self.wait_for_search();
+    }
+
+    // Let the running search finish and take the Searcher back. Infinite searches never finish by themselves, so they're stopped.
+    fn wait_for_search(&mut self) {
+        if let Some(running) = self.running.take() {
+            if running.infinite {
+                running.stop.store(true, Ordering::Relaxed);
+            }
+            // A search that panicked takes its Searcher with it.
+            let searcher: Searcher = running
+                .handle
+                .join()
+                .unwrap_or_else(|_| self.new_searcher());
+            self.searcher = Some(searcher);
+        }
+    }
+}
+
+pub fn format_bestmove(result: &SearchResult) -> String {
+    match (result.best_move, result.principal_variation.get(1)) {
+        (Some(best_move), Some(ponder)) => format!(
+            "bestmove {} ponder {}",
+            best_move.coordinate_notation(),
+            ponder.coordinate_notation()
+        ),
+        (Some(best_move), None) => format!("bestmove {}", best_move.coordinate_notation()),
+        (None, _) => String::from("bestmove (none)"),
+    }
+}
+
+// Rulesets are leaked so they outlive every game and thread that borrows them. There are only ever a handful.
+pub fn load_ruleset(path: &str) -> Result<&'static PieceList, String> {
+    if !Path::new(path).is_file() {
+        return Err(format!("couldn't find {}", path));
+    }
+    match load_piece_list_from(Path::new(path)) {
+        Ok(piece_list) => Ok(Box::leak(Box::new(piece_list))),
+        Err(why) => Err(format!("couldn't read {}: {}", path, why)),
+    }
+}
+
+pub fn load_positions(path: &str) -> Result<HashMap<String, String>, String> {
+    if !Path::new(path).is_file() {
+        return Err(format!("couldn't find {}", path));
+    }
+    Ok(PositionListIntermediateRepresentation::from_file(Path::new(path)).map_positions_to_names())
+}
+
+// standard if there is one, otherwise whichever comes first by name.
+pub fn default_variant(position_name_map: &HashMap<String, String>) -> Result<String, String> {
+    if position_name_map.contains_key("standard") {
+        return Ok(String::from("standard"));
+    }
+    position_name_map
+        .keys()
+        .min()
+        .cloned()
+        .ok_or_else(|| String::from("no starting positions"))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::uci::*;
+    use std::io::Cursor;
+
+    // Feed the engine a script and return everything it printed.
+    fn run_script(script: &str) -> String {
+        let buffer: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut engine: UciEngine = UciEngine::new(
+            "testfiles/standardPieces.json",
+            "testfiles/standardStartingPositions.json",
+            buffer.clone(),
+        )
+        .unwrap();
+        engine.run(Cursor::new(script));
+        let output: Vec<u8> = buffer.lock().unwrap().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn handshake_lists_options_and_variants() {
+        let output: String = run_script("uci\nisready\n");
+        assert!(output.starts_with("id name piecetestbed"));
+        assert!(output.contains("option name Hash type spin"));
+        assert!(output.contains("option name PiecesFile type string"));
+        assert!(output.contains(
+            "option name UCI_Variant type combo default standard var ssehc var standard\n"
+        ));
+        assert!(output.ends_with("uciok\nreadyok\n"));
+    }
+
+    #[test]
+    fn plays_moves_and_searches() {
+        let output: String =
+            run_script("ucinewgame\nposition startpos moves e2e4 e7e5 g1f3\ngo depth 2\n");
+        assert!(output.contains("info depth 2 "));
+        let last: &str = output.lines().last().unwrap();
+        assert!(last.starts_with("bestmove "));
+        // It's black's move, so the best move starts on black's side of the board.
+        let from_rank: char = last.chars().nth(10).unwrap();
+        assert!(from_rank >= '5');
+    }
+
+    #[test]
+    fn fen_positions_and_clock() {
+        let output: String = run_script(
+            "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\ngo wtime 2000 btime 2000 winc 10\n",
+        );
+        assert!(output.contains("score mate 1"));
+        assert!(output.ends_with("bestmove a1a8\n"));
+    }
+
+    #[test]
+    fn infinite_search_waits_for_stop() {
+        let output: String = run_script("position startpos\ngo infinite\nstop\nisready\n");
+        let bestmove: usize = output.find("bestmove ").unwrap();
+        assert!(bestmove < output.find("readyok").unwrap());
+    }
+
+    #[test]
+    fn new_commands_stop_the_search() {
+        // Without limits go searches as deep as it can, so only the next command ends it.
+        let output: String =
+            run_script("position startpos\ngo\nposition startpos moves e2e4\ngo depth 1\n");
+        assert_eq!(output.matches("bestmove ").count(), 2);
+        assert!(output.contains("info depth 1 "));
+    }
+
+    #[test]
+    fn options_are_checked() {
+        let output: String = run_script(
+            "setoption name Hash value 1\nsetoption name PiecesFile value nowhere.json\nsetoption name UCI_Variant value ssehc\nposition startpos moves e2e4\nsetoption name PiecesFile value testfiles/standardPieces.json\nsetoption name UCI_Variant value standard\nposition startpos moves e2e4\n",
+        );
+        assert!(output.contains("info string couldn't find nowhere.json"));
+        // In ssehc the king starts on e2, so e2e4 is only a move in standard.
+        assert_eq!(output.matches("info string illegal move e2e4").count(), 1);
+    }
+}
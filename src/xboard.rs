@@ -0,0 +1,497 @@
+use crate::configuration::*;
+use crate::game::*;
+use crate::search::*;
+use crate::uci::*;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::time::Duration;
+
+// Thinking time when XBoard hasn't given a depth, a time per move or a clock.
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(5);
+
+// The engine as seen over the Chess Engine Communication Protocol used by XBoard and WinBoard.
+// Unlike UCI, the engine keeps track of the game itself and answers the opponent's moves with its own.
+// Searches run on the same thread as the input, so a search can't be interrupted.
+pub struct XBoardEngine {
+    output: SharedOutput,
+    piece_list: &'static PieceList,
+    position_name_map: HashMap<String, String>,
+    variant: String,
+    game_counter: u64,
+    game: Game<'static>,
+    searcher: Searcher,
+    // In force mode the engine only checks moves and plays none of its own.
+    force: bool,
+    engine_color: char,
+    post: bool,
+    depth: Option<u32>,
+    time_per_move: Option<Duration>,
+    clock: Option<Duration>,
+    increment: Duration,
+    moves_per_session: Option<u32>,
+}
+
+impl XBoardEngine {
+    pub fn new(
+        pieces_path: &str,
+        positions_path: &str,
+        output: SharedOutput,
+    ) -> Result<XBoardEngine, String> {
+        let piece_list: &'static PieceList = load_ruleset(pieces_path)?;
+        let position_name_map: HashMap<String, String> = load_positions(positions_path)?;
+        let variant: String = default_variant(&position_name_map)?;
+        let game: Game<'static> = Game::from_fen(
+            0,
+            &position_name_map[&variant],
+            &position_name_map[&variant],
+            piece_list.map_piecetypes_to_symbols(),
+        )?;
+        Ok(XBoardEngine {
+            output,
+            piece_list,
+            position_name_map,
+            variant,
+            game_counter: 0,
+            game,
+            searcher: Searcher::new(),
+            force: false,
+            engine_color: 'b',
+            post: false,
+            depth: None,
+            time_per_move: None,
+            clock: None,
+            increment: Duration::ZERO,
+            moves_per_session: None,
+        })
+    }
+
+    // Read commands until quit or the end of the input.
+    pub fn run(&mut self, input: impl BufRead) {
+        for line in input.lines() {
+            match line {
+                Ok(line) if self.handle(&line) => {}
+                _ => return,
+            }
+        }
+    }
+
+    // Returns false once asked to quit.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let argument: Option<&str> = words.get(1).copied();
+        let number: Option<u64> = argument.and_then(|a| a.parse().ok());
+        match words.first().copied() {
+            Some("protover") => self.features(),
+            Some("new") => {
+                self.game_counter += 1;
+                self.variant = default_variant(&self.position_name_map).unwrap_or_default();
+                self.force = false;
+                self.engine_color = 'b';
+                self.depth = None;
+                self.searcher.table.clear();
+                self.start_position();
+                self.describe_pieces();
+            }
+            Some("variant") => {
+                let name: &str = match argument {
+                    Some("normal") => "standard",
+                    Some(name) => name,
+                    None => "",
+                };
+                if self.position_name_map.contains_key(name) {
+                    self.variant = String::from(name);
+                    self.start_position();
+                    self.describe_pieces();
+                } else {
+                    self.send(&format!("Error (unknown variant): {}", name));
+                }
+            }
+            Some("setboard") => {
+                let fen: String = words[1..].join(" ");
+                match Game::from_fen(
+                    self.game_counter,
+                    &fen,
+                    &self.position_name_map[&self.variant],
+                    self.piece_list.map_piecetypes_to_symbols(),
+                ) {
+                    Ok(game) => self.game = game,
+                    Err(why) => self.send(&format!("tellusererror Illegal position: {}", why)),
+                }
+            }
+            Some("usermove") => {
+                if let Some(coordinate) = argument {
+                    self.user_move(coordinate);
+                }
+            }
+            Some("go") => {
+                self.force = false;
+                self.engine_color = self.game.active_color;
+                self.think();
+            }
+            Some("playother") => {
+                self.force = false;
+                self.engine_color = opponent(self.game.active_color);
+            }
+            Some("force") | Some("result") => self.force = true,
+            Some("undo") => self.game.unmake_move(),
+            Some("remove") => {
+                self.game.unmake_move();
+                self.game.unmake_move();
+            }
+            Some("ping") => self.send(&format!("pong {}", argument.unwrap_or(""))),
+            Some("sd") => self.depth = number.map(|n| n as u32),
+            Some("st") => self.time_per_move = number.map(Duration::from_secs),
+            // Centiseconds left on the engine's clock.
+            Some("time") => self.clock = number.map(|n| Duration::from_millis(n * 10)),
+            // level <moves per session> <base time> <increment in seconds>. Only the increment and moves per session matter, the clock comes from time.
+            Some("level") => {
+                self.moves_per_session = argument
+                    .and_then(|a| a.parse().ok())
+                    .filter(|&moves: &u32| moves > 0);
+                self.increment = words
+                    .get(3)
+                    .and_then(|i| i.parse::<f64>().ok())
+                    .map_or(Duration::ZERO, Duration::from_secs_f64);
+                self.time_per_move = None;
+            }
+            Some("post") => self.post = true,
+            Some("nopost") => self.post = false,
+            Some("quit") => return false,
+            // Things XBoard tells every engine that make no difference here.
+            Some(
+                "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer"
+                | "name" | "rating" | "ics" | "otim" | "?" | ".",
+            ) => {}
+            Some(other) => {
+                // Without usermove=1 moves arrive on their own.
+                if self.game.find_move(other).is_some() {
+                    self.user_move(other);
+                } else {
+                    self.send(&format!("Error (unknown command): {}", other));
+                }
+            }
+            None => {}
+        }
+        true
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.output, line);
+    }
+
+    fn features(&self) {
+        let mut variants: Vec<&str> = self
+            .position_name_map
+            .keys()
+            .map(|name| xboard_variant_name(name))
+            .collect();
+        variants.sort();
+        self.send(&format!(
+            "feature myname=\"piecetestbed {}\" variants=\"{}\" setboard=1 usermove=1 ping=1 playother=1 colors=0 sigint=0 sigterm=0 analyze=0 done=1",
+            env!("CARGO_PKG_VERSION"),
+            variants.join(",")
+        ));
+    }
+
+    // Tell XBoard the variant's pieces and start position, then how every piece moves, so it can show
+    // the board and legal moves for variants and pieces it has never heard of. XBoard wants setup first.
+    fn describe_pieces(&self) {
+        let letters: String = self.piece_list.pieces.iter().map(|p| p.white_id).collect();
+        self.send(&format!("setup ({}) {}", letters, self.game.to_fen()));
+        for piece_type in &self.piece_list.pieces {
+            self.send(&format!(
+                "piece {}& {}",
+                piece_type.white_id,
+                betza(piece_type)
+            ));
+        }
+    }
+
+    fn start_position(&mut self) {
+        let fen: &String = &self.position_name_map[&self.variant];
+        if let Ok(game) = Game::from_fen(
+            self.game_counter,
+            fen,
+            fen,
+            self.piece_list.map_piecetypes_to_symbols(),
+        ) {
+            self.game = game;
+        }
+    }
+
+    fn user_move(&mut self, coordinate: &str) {
+        match self.game.find_move(coordinate) {
+            Some(available_move) => self.game.make_move(available_move),
+            None => {
+                self.send(&format!("Illegal move: {}", coordinate));
+                return;
+            }
+        }
+        if let Some(outcome) = self.game.outcome() {
+            self.send_result(outcome);
+        } else if !self.force && self.game.active_color == self.engine_color {
+            self.think();
+        }
+    }
+
+    fn think(&mut self) {
+        if let Some(outcome) = self.game.outcome() {
+            self.send_result(outcome);
+            return;
+        }
+        let mut limits: SearchLimits = SearchLimits {
+            depth: self.depth,
+            movetime: self.time_per_move,
+            ..SearchLimits::default()
+        };
+        if limits.movetime.is_none() {
+            if let Some(clock) = self.clock {
+                let moves_to_go: Option<u32> = self
+                    .moves_per_session
+                    .map(|session| session - (self.game.fullmove_number - 1) % session);
+                limits.movetime = Some(time_for_move(clock, self.increment, moves_to_go));
+            } else if limits.depth.is_none() {
+                limits.movetime = Some(DEFAULT_MOVE_TIME);
+            }
+        }
+        let post: bool = self.post;
+        let output: SharedOutput = self.output.clone();
+        let result: SearchResult = self.searcher.search(&mut self.game, limits, &mut |info| {
+            if post {
+                send(&output, &format_thinking(info));
+            }
+        });
+        if let Some(best_move) = result.best_move {
+            self.game.make_move(best_move);
+            self.send(&format!("move {}", best_move.coordinate_notation()));
+            if let Some(outcome) = self.game.outcome() {
+                self.send_result(outcome);
+            }
+        }
+    }
+
+    fn send_result(&self, outcome: Outcome) {
+        self.send(&format!("{} {{{}}}", outcome.result(), outcome.reason()));
+    }
+}
+
+// XBoard calls standard chess "normal". Every other position goes by its own name.
+fn xboard_variant_name(name: &str) -> &str {
+    if name == "standard" {
+        "normal"
+    } else {
+        name
+    }
+}
+
+// ply score time nodes pv, with the time in centiseconds.
+pub fn format_thinking(result: &SearchResult) -> String {
+    let principal_variation: Vec<String> = result
+        .principal_variation
+        .iter()
+        .map(|m| m.coordinate_notation())
+        .collect();
+    format!(
+        "{} {} {} {} {}",
+        result.depth,
+        result.score,
+        result.elapsed.as_millis() / 10,
+        result.nodes,
+        principal_variation.join(" ")
+    )
+}
+
+// The moves of a PieceType in Betza notation, as XBoard's piece command wants them.
+// Moves are grouped by leap and by what they're allowed to do, then given directions.
+// Leaps XBoard has no letter for are left out, and oblique moves are only described down to the quadrant.
+pub fn betza(piece_type: &PieceType) -> String {
+    // Everything about a group except its directions, in the order the moves came in.
+    let mut groups: Vec<(String, Vec<(i32, i32)>)> = Vec::new();
+    for m in &piece_type.moveset {
+        let (h, v) = (
+            m.translation.0.min(m.translation.1),
+            m.translation.0.max(m.translation.1),
+        );
+        let atom: &str = if m.castles {
+            "O"
+        } else {
+            match (h, v) {
+                (0, 1) => "W",
+                (1, 1) => "F",
+                (0, 2) => "D",
+                (1, 2) => "N",
+                (2, 2) => "A",
+                (0, 3) => "H",
+                (1, 3) => "C",
+                (2, 3) => "Z",
+                (3, 3) => "G",
+                _ => continue,
+            }
+        };
+        let mut key: String = String::new();
+        if m.only_first_move {
+            key.push('i');
+        }
+        let mut suffix: String = String::new();
+        if m.castles {
+            // Castling says everything about itself.
+        } else if m.enpassant {
+            suffix.push('e');
+        } else if m.moves && !m.captures {
+            suffix.push('m');
+        } else if m.captures && !m.moves {
+            suffix.push('c');
+        }
+        // Leapers jump unless told otherwise, sliders are always blocked.
+        if !m.jump && !m.any_multiple && !m.castles && v > 1 {
+            suffix.push('n');
+        }
+        let letters: String = if m.castles {
+            format!("O{}", v)
+        } else if m.any_multiple {
+            match atom {
+                "W" => String::from("R"),
+                "F" => String::from("B"),
+                _ => format!("{}{}", atom, atom),
+            }
+        } else {
+            String::from(atom)
+        };
+        key.push('|');
+        key.push_str(&suffix);
+        key.push('|');
+        key.push_str(&letters);
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, vectors)) => vectors.extend(move_vectors(m)),
+            None => groups.push((key, move_vectors(m))),
+        }
+    }
+    let mut output: String = String::new();
+    for (key, vectors) in groups {
+        let parts: Vec<&str> = key.split('|').collect();
+        output.push_str(parts[0]);
+        let directions: String = directions(&vectors);
+        // O already means castling to either side.
+        if !(parts[2].starts_with('O') && directions == "lr") {
+            output.push_str(&directions);
+        }
+        output.push_str(parts[1]);
+        output.push_str(parts[2]);
+    }
+    output
+}
+
+// Betza direction letters for a set of move vectors (up is forward). Nothing when the move goes every way its leap can.
+fn directions(vectors: &[(i32, i32)]) -> String {
+    let has = |test: &dyn Fn(i32, i32) -> bool| vectors.iter().any(|&(x, y)| test(x, y));
+    let (x, y) = vectors[0];
+    let orthogonal: bool = x == 0 || y == 0;
+    if orthogonal {
+        let mut output: String = String::new();
+        for (letter, present) in [
+            ("f", has(&|x, y| x == 0 && y > 0)),
+            ("b", has(&|x, y| x == 0 && y < 0)),
+            ("l", has(&|x, y| y == 0 && x < 0)),
+            ("r", has(&|x, y| y == 0 && x > 0)),
+        ] {
+            if present {
+                output.push_str(letter);
+            }
+        }
+        return if output.len() == 4 {
+            String::new()
+        } else {
+            output
+        };
+    }
+    let quadrants: [bool; 4] = [
+        has(&|x, y| x < 0 && y > 0),
+        has(&|x, y| x > 0 && y > 0),
+        has(&|x, y| x < 0 && y < 0),
+        has(&|x, y| x > 0 && y < 0),
+    ];
+    match quadrants {
+        [true, true, true, true] => String::new(),
+        [true, true, false, false] => String::from("f"),
+        [false, false, true, true] => String::from("b"),
+        [true, false, true, false] => String::from("l"),
+        [false, true, false, true] => String::from("r"),
+        _ => ["fl", "fr", "bl", "br"]
+            .iter()
+            .zip(quadrants)
+            .filter(|(_, present)| *present)
+            .map(|(name, _)| *name)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::xboard::*;
+    use std::io::Cursor;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    fn run_script(script: &str) -> String {
+        let buffer: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut engine: XBoardEngine = XBoardEngine::new(
+            "testfiles/standardPieces.json",
+            "testfiles/standardStartingPositions.json",
+            buffer.clone(),
+        )
+        .unwrap();
+        engine.run(Cursor::new(script));
+        let output: Vec<u8> = buffer.lock().unwrap().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn standard_pieces_in_betza() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let betzas: Vec<String> = piece_list.pieces.iter().map(betza).collect();
+        assert_eq!(betzas, vec!["fmWifmnDfcFfeF", "R", "N", "B", "RB", "WFO2"]);
+    }
+
+    #[test]
+    fn announces_variants_and_pieces() {
+        let output: String = run_script("xboard\nprotover 2\nnew\nping 3\n");
+        assert!(output.starts_with("feature myname=\"piecetestbed"));
+        assert!(output.contains("variants=\"normal,ssehc\""));
+        assert!(output.contains("piece N& N\n"));
+        assert!(output.ends_with("pong 3\n"));
+    }
+
+    #[test]
+    fn sets_up_custom_variants() {
+        let output: String = run_script("new\nvariant ssehc\n");
+        let setup: &str = "setup (PRNBQK) pppppppp/rnbqkbnr/8/8/8/8/RNBQKBNR/PPPPPPPP w ";
+        assert_eq!(output.matches(setup).count(), 1);
+        // The pieces come after it.
+        let after: &str = output.split(setup).nth(1).unwrap();
+        assert!(after.contains("piece N& N\n"));
+    }
+
+    #[test]
+    fn answers_moves_and_reports_mate() {
+        let output: String = run_script("new\nsd 1\nusermove e2e4\nusermove e2e5\n");
+        assert!(output.lines().any(|l| l.starts_with("move ")));
+        assert!(output.ends_with("Illegal move: e2e5\n"));
+        let output: String =
+            run_script("new\nforce\nsetboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\nsd 2\npost\ngo\n");
+        assert!(output.contains(&format!("2 {} ", MATE - 1)));
+        assert!(output.ends_with("move a1a8\n1-0 {White mates}\n"));
+    }
+
+    #[test]
+    fn force_and_undo() {
+        let output: String = run_script(
+            "new\nforce\nusermove e2e4\nusermove e7e5\nundo\nusermove e7e5\nremove\nusermove e2e4\nvariant ssehc\nusermove e2e4\n",
+        );
+        // No engine moves in force mode, and in ssehc e2 holds the king.
+        assert!(!output.lines().any(|l| l.starts_with("move ")));
+        assert_eq!(output.matches("Illegal move").count(), 1);
+    }
+}
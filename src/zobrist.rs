@@ -0,0 +1,200 @@
+use crate::configuration::*;
+use crate::game::*;
+
+// Zobrist keys are made on the fly from a fixed seed rather than stored in a table,
+// so any symbol that turns up in pieces.json gets keys without anything to set up.
+const ZOBRIST_SEED: u64 = 0x5EED_0FC0_FFEE_C4E5;
+
+pub const SIDE_TO_MOVE_KEY: u64 = splitmix64(ZOBRIST_SEED ^ (1 << 40));
+
+// A good 64 bit mix of its input. Different inputs give unrelated outputs, which is all Zobrist hashing needs.
+pub const fn splitmix64(x: u64) -> u64 {
+    let mut z: u64 = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+pub fn piece_square_key(symbol: char, square: u8) -> u64 {
+    splitmix64(ZOBRIST_SEED ^ ((symbol as u64) << 16) ^ square as u64)
+}
+
+// One key per castling-like move still available, named by where the royal piece and its target stand.
+pub fn castling_key(royal_square: u8, target_square: u8) -> u64 {
+    splitmix64(ZOBRIST_SEED ^ (2 << 40) ^ ((royal_square as u64) << 8) ^ target_square as u64)
+}
+
+pub fn enpassant_key(square: u8) -> u64 {
+    splitmix64(ZOBRIST_SEED ^ (3 << 40) ^ square as u64)
+}
+
+impl<'a> Game<'a> {
+    // The hash worked out from scratch. make_move keeps Game.hash up to date without this, and checks against it in debug builds.
+    pub fn compute_hash(&self) -> u64 {
+        let mut output: u64 = 0;
+        for piece in self.list_of_pieces_ingame.iter().filter(|p| !p.captured) {
+            output ^= piece_square_key(piece.symbol, piece.position);
+        }
+        if self.active_color == 'b' {
+            output ^= SIDE_TO_MOVE_KEY;
+        }
+        output ^ self.castling_hash() ^ self.enpassant_hash()
+    }
+
+    pub fn castling_hash(&self) -> u64 {
+        let mut output: u64 = 0;
+        for (royal_id, m) in self.castling_rights() {
+            let royal: &Piece = self.piece(royal_id);
+            if let Some(target_square) = self.relative_square(
+                royal.position,
+                m.castle_target_piece_relative_location,
+                royal.player,
+            ) {
+                output ^= castling_key(royal.position, target_square);
+            }
+        }
+        output
+    }
+
+    // Only moves that some en passant-like move is waiting for open up an en passant opportunity. The FEN names the square for those.
+    pub fn opens_enpassant(&self) -> bool {
+        let last: &AvailableMove = match self.list_of_moves.last() {
+            Some(record) => &record.played,
+            None => return false,
+        };
+        self.piece_symbol_map.values().any(|piece_type| {
+            piece_type.moveset.iter().any(|m| {
+                m.enpassant_target_piece_previous_move.as_ref() == Some(&last.move_type.id)
+            })
+        })
+    }
+
+    // Keyed only when something can really take en passant, like Polyglot does, so a double push nothing can take
+    // hashes the same as getting there in two steps.
+    pub fn enpassant_hash(&self) -> u64 {
+        match self.list_of_moves.last() {
+            Some(record) if self.opens_enpassant() && self.can_capture_enpassant() => {
+                enpassant_key(record.played.to)
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::configuration::*;
+    use crate::game::*;
+    use std::path::Path;
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for coordinate in moves {
+            let available_move: AvailableMove = game
+                .scan_available_moves()
+                .into_iter()
+                .find(|m| m.coordinate_notation() == *coordinate)
+                .unwrap();
+            game.make_move(available_move);
+        }
+    }
+
+    #[test]
+    fn hash_survives_make_and_unmake() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let fen: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut game: Game =
+            Game::from_fen(0, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let start: u64 = game.hash;
+        for available_move in game.scan_available_moves() {
+            game.make_move(available_move);
+            assert_ne!(game.hash, start);
+            assert_eq!(game.hash, game.compute_hash());
+            game.unmake_move();
+            assert_eq!(game.hash, start);
+        }
+    }
+
+    #[test]
+    fn transpositions_share_a_hash() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let fen: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let mut first: Game =
+            Game::from_fen(0, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        let mut second: Game =
+            Game::from_fen(1, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        play(&mut first, &["g1f3", "g8f6", "b1c3"]);
+        play(&mut second, &["b1c3", "g8f6", "g1f3"]);
+        assert_eq!(first.hash, second.hash);
+        // Same pieces, other side to move.
+        play(&mut first, &["b8c6"]);
+        play(&mut second, &["b8c6", "f3g1"]);
+        assert_ne!(first.hash, second.hash);
+    }
+
+    #[test]
+    fn hash_includes_castling_and_enpassant() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let fen: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let mut game: Game =
+            Game::from_fen(0, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        play(&mut game, &["e2e4"]);
+        let from_fen: Game = Game::from_fen(
+            1,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            fen,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        assert_eq!(game.hash, from_fen.hash);
+        let no_enpassant: Game = Game::from_fen(
+            2,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            fen,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        // No black pawn can take on e3, so that's the same position.
+        assert_eq!(game.hash, no_enpassant.hash);
+        let mut two_steps: Game = Game::from_fen(
+            4,
+            "rnbqkbnr/pppppppp/8/8/8/4P3/PPPP1PPP/RNBQKBNR w KQkq - 0 1",
+            fen,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        play(&mut two_steps, &["e3e4"]);
+        assert_eq!(game.hash, two_steps.hash);
+        let no_castling: Game = Game::from_fen(
+            3,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b Qkq e3 0 1",
+            fen,
+            piece_list.map_piecetypes_to_symbols(),
+        )
+        .unwrap();
+        assert_ne!(game.hash, no_castling.hash);
+        // With a pawn on e4 to take it, d7d5 gives Black's position an en passant key.
+        play(&mut game, &["g8f6", "e4e5", "d7d5"]);
+        let after_d5: &str = "rnbqkb1r/ppp1pppp/5n2/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3";
+        let no_d6: Game =
+            Game::from_fen(5, after_d5, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        assert_ne!(game.hash, no_d6.hash);
+        assert_eq!(game.to_fen(), after_d5.replace(" - ", " d6 "));
+    }
+
+    #[test]
+    fn repetitions_are_counted() {
+        let piece_list: PieceList =
+            load_piece_list_from(Path::new("testfiles/standardPieces.json")).unwrap();
+        let fen: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let mut game: Game =
+            Game::from_fen(0, fen, fen, piece_list.map_piecetypes_to_symbols()).unwrap();
+        play(&mut game, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(game.repetitions(), 1);
+        play(&mut game, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(game.repetitions(), 2);
+    }
+}